@@ -3,12 +3,26 @@
 import { AIManager } from './dist/ai/manager.js';
 import fs from 'fs';
 import path from 'path';
+import readline from 'readline';
+
+const FORMAT_HINTS = {
+    markdown: 'Format your answer as Markdown.',
+    plain: 'Answer in plain text, without Markdown or code fences.',
+    json: 'Answer with a single valid JSON value and nothing else.',
+};
 
 async function main() {
     const args = process.argv.slice(2);
     
-    if (args.includes('--setup-sessions')) {
-        await setupSessions();
+    if (args.includes('--selftest')) {
+        // Reaching here means Node ran the script and its imports resolved
+        console.log('ok');
+    } else if (args.includes('--list-models')) {
+        await listModels(args);
+    } else if (args.includes('--setup-sessions')) {
+        await setupSessions(args);
+    } else if (args.includes('--serve')) {
+        await serve();
     } else if (args.includes('--prompt')) {
         await handlePrompt(args);
     } else {
@@ -17,11 +31,15 @@ async function main() {
     }
 }
 
-async function setupSessions() {
+async function setupSessions(args) {
     try {
+        // --only claude,gemini redoes just those sessions, even if they exist
+        const onlyIndex = args.indexOf('--only');
+        const only = onlyIndex === -1 ? undefined : args[onlyIndex + 1].split(',');
         const manager = new AIManager();
         await manager.initialize();
-        await manager.setupSessions();
+        // One JSON line per progress step, read by the app as it happens
+        await manager.setupSessions(progress => console.log(JSON.stringify(progress)), only);
         await manager.close();
         console.log('Sessions setup completed');
     } catch (error) {
@@ -30,24 +48,108 @@ async function setupSessions() {
     }
 }
 
+async function listModels(args) {
+    try {
+        const id = args[args.indexOf('--list-models') + 1];
+        const models = await new AIManager().listModels(id);
+        // One JSON array of model names; empty when the bot can't enumerate them
+        console.log(JSON.stringify(models));
+    } catch (error) {
+        console.error('List models error:', error.message);
+        process.exit(1);
+    }
+}
+
+// Stays up answering newline-delimited JSON requests on stdin, each
+// { id, prompt, chatbots, context, options, attachments, format } with the
+// same meaning as the --prompt flags. Each answer is one line, { id, response }
+// or { id, error }, written as soon as it's ready, so answers may come back
+// out of order. A { id, cancel: true } line means the app gave up on that
+// request, so no answer is written for it.
+async function serve() {
+    const manager = new AIManager();
+    await manager.initialize();
+    const lines = readline.createInterface({ input: process.stdin });
+    const running = [];
+    const cancelled = new Set();
+    for await (const line of lines) {
+        if (!line.trim()) continue;
+        running.push(answer(manager, line, cancelled));
+    }
+    await Promise.allSettled(running);
+    await manager.close();
+}
+
+async function answer(manager, line, cancelled) {
+    let id = null;
+    try {
+        const request = JSON.parse(line);
+        id = request.id;
+        if (request.cancel) {
+            cancelled.add(id);
+            return;
+        }
+        const prompt = request.format && FORMAT_HINTS[request.format]
+            ? `${request.prompt}\n\n${FORMAT_HINTS[request.format]}`
+            : request.prompt;
+        const response = await manager.sendPromptToAll({
+            prompt,
+            chatbots: request.chatbots,
+            context: request.context || [],
+            options: request.options || {},
+            attachments: request.attachments || [],
+        });
+        flattenErrors(response);
+        if (!cancelled.delete(id)) console.log(JSON.stringify({ id, response }));
+    } catch (error) {
+        if (!cancelled.delete(id)) console.log(JSON.stringify({ id, error: error.message }));
+    }
+}
+
+// The app expects `error` as text; the type goes first so it can spot
+// codes like SESSION_INVALID
+function flattenErrors(response) {
+    for (const result of response.results) {
+        if (result.error && typeof result.error === 'object') {
+            result.error = `${result.error.type}: ${result.error.message}`;
+        }
+    }
+}
+
 async function handlePrompt(args) {
     try {
         const promptIndex = args.indexOf('--prompt');
         const chatbotsIndex = args.indexOf('--chatbots');
+        const contextIndex = args.indexOf('--context');
+        const optionsIndex = args.indexOf('--options');
+        const attachmentsIndex = args.indexOf('--attachments');
+        const formatIndex = args.indexOf('--format');
         
         if (promptIndex === -1 || chatbotsIndex === -1) {
             throw new Error('Missing required arguments');
         }
         
-        const prompt = args[promptIndex + 1];
+        // Web chat UIs have no format switch, so the hint rides along with the prompt
+        const format = formatIndex === -1 ? undefined : args[formatIndex + 1];
+        const prompt = format && FORMAT_HINTS[format]
+            ? `${args[promptIndex + 1]}\n\n${FORMAT_HINTS[format]}`
+            : args[promptIndex + 1];
         const chatbots = args[chatbotsIndex + 1].split(',');
+        // Earlier turns as [{ role: 'system' | 'user' | 'assistant', content }], oldest first;
+        // a system prompt, if any, comes first
+        const context = contextIndex === -1 ? [] : JSON.parse(args[contextIndex + 1]);
+        // Per-bot { model, params } overrides; omitted fields use the provider defaults
+        const options = optionsIndex === -1 ? {} : JSON.parse(args[optionsIndex + 1]);
+        // [{ filename, mime, path }], already validated by the app
+        const attachments = attachmentsIndex === -1 ? [] : JSON.parse(args[attachmentsIndex + 1]);
         
         const manager = new AIManager();
         await manager.initialize();
         
-        const response = await manager.sendPromptToAll({ prompt, chatbots });
+        const response = await manager.sendPromptToAll({ prompt, chatbots, context, options, attachments });
         await manager.close();
         
+        flattenErrors(response);
         console.log(JSON.stringify(response));
     } catch (error) {
         console.error('Prompt error:', error.message);
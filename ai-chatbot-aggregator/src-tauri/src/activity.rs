@@ -0,0 +1,94 @@
+use crate::ChatBotResponse;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+/// Finished requests kept for `list_requests`, oldest dropped first.
+const RECENT_LIMIT: usize = 50;
+/// Characters of the prompt shown in `RequestInfo::prompt_preview`.
+const PREVIEW_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestState {
+    Running,
+    /// At least one bot answered.
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestInfo {
+    id: String,
+    prompt_preview: String,
+    bots: Vec<String>,
+    state: RequestState,
+    /// Milliseconds since the Unix epoch.
+    started_ms: u64,
+}
+
+#[derive(Default)]
+struct Requests {
+    running: HashMap<String, RequestInfo>,
+    recent: VecDeque<RequestInfo>,
+}
+
+/// Requests in flight plus the last `RECENT_LIMIT` that finished, for an
+/// activity feed. Kept in memory only.
+#[derive(Default)]
+pub struct Activity {
+    requests: Mutex<Requests>,
+}
+
+impl Activity {
+    pub fn start(&self, id: &str, prompt: &str, bots: &[String], started_ms: u64) {
+        let mut prompt_preview: String = prompt.chars().take(PREVIEW_CHARS).collect();
+        if prompt_preview.len() < prompt.len() {
+            prompt_preview.push('…');
+        }
+        self.lock().running.insert(
+            id.to_string(),
+            RequestInfo {
+                id: id.to_string(),
+                prompt_preview,
+                bots: bots.to_vec(),
+                state: RequestState::Running,
+                started_ms,
+            },
+        );
+    }
+
+    /// Moves a running request to the recent ones, settling its state from
+    /// the answers it got.
+    pub fn finish(&self, id: &str, cancelled: bool, results: &[ChatBotResponse]) {
+        let mut requests = self.lock();
+        let Some(mut info) = requests.running.remove(id) else {
+            return;
+        };
+        info.state = if cancelled {
+            RequestState::Cancelled
+        } else if results.iter().any(|r| r.status == "success") {
+            RequestState::Completed
+        } else {
+            RequestState::Failed
+        };
+        if requests.recent.len() == RECENT_LIMIT {
+            requests.recent.pop_front();
+        }
+        requests.recent.push_back(info);
+    }
+
+    /// Running requests, then finished ones; each group newest first.
+    pub fn list(&self) -> Vec<RequestInfo> {
+        let requests = self.lock();
+        let mut running: Vec<RequestInfo> = requests.running.values().cloned().collect();
+        running.sort_by_key(|r| std::cmp::Reverse(r.started_ms));
+        running.extend(requests.recent.iter().rev().cloned());
+        running
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Requests> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,213 @@
+use super::node::{run_process, run_raw};
+use super::{redact_env, redact_env_values, BackendCall, BackendError, ChatBackend, DryRunCommand};
+use crate::ChatBotResponse;
+use async_trait::async_trait;
+use tokio::process::Command;
+
+/// Placeholders a command template may use in its arguments.
+const PLACEHOLDERS: &[&str] = &["prompt", "id", "model", "system_prompt", "context"];
+
+/// A chatbot's own invocation, e.g. `my-bot --model {model} --ask {prompt}`.
+/// The template is split into words once, like a shell would (single and
+/// double quotes group, backslash escapes), and placeholders are substituted
+/// inside each word afterwards, so a prompt never turns into extra
+/// arguments and nothing is ever passed through a shell.
+#[derive(Debug, Clone)]
+pub struct CommandTemplate {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandTemplate {
+    /// Fails on unbalanced quotes or braces, unknown placeholders, or a
+    /// placeholder in the program name.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut words = split_words(template)?.into_iter();
+        let program = words
+            .next()
+            .ok_or_else(|| "Command template must not be empty".to_string())?;
+        if !placeholders(&program)?.is_empty() {
+            return Err(
+                "The program in a command template must not contain placeholders".to_string(),
+            );
+        }
+        let args: Vec<String> = words.collect();
+        for arg in &args {
+            if let Some(unknown) = placeholders(arg)?
+                .into_iter()
+                .find(|p| !PLACEHOLDERS.contains(p))
+            {
+                return Err(format!(
+                    "Unknown placeholder '{{{}}}' in command template; expected one of {}",
+                    unknown,
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{}}}", p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        Ok(Self { program, args })
+    }
+
+    fn uses(&self, placeholder: &str) -> bool {
+        self.args.iter().any(|arg| {
+            placeholders(arg)
+                .map(|found| found.contains(&placeholder))
+                .unwrap_or(false)
+        })
+    }
+
+    fn render(&self, call: &BackendCall<'_>) -> Result<Vec<String>, BackendError> {
+        let context = serde_json::to_string(call.context)
+            .map_err(|e| BackendError::Failed(format!("Failed to encode context: {}", e)))?;
+        let id = call.chatbots.join(",");
+        Ok(self
+            .args
+            .iter()
+            .map(|arg| {
+                substitute(arg, |name| match name {
+                    "prompt" => call.prompt,
+                    "id" => &id,
+                    "model" => call.options.model.unwrap_or_default(),
+                    "system_prompt" => call.system_prompt.unwrap_or_default(),
+                    "context" => &context,
+                    _ => "",
+                })
+            })
+            .collect())
+    }
+}
+
+/// Runs a bot's `command_template` instead of the Node backend. The program
+/// may print answers in the `ai-backend.js` format or just print its answer
+/// as plain text.
+pub struct CommandBackend {
+    template: CommandTemplate,
+    env: Vec<(String, String)>,
+}
+
+impl CommandBackend {
+    /// `env` is set on top of the inherited environment, as for the Node backend.
+    pub fn new(template: CommandTemplate, env: Vec<(String, String)>) -> Self {
+        Self { template, env }
+    }
+}
+
+#[async_trait]
+impl ChatBackend for CommandBackend {
+    /// Only when the template has somewhere to put it; otherwise the system
+    /// prompt would be lost, so it goes into `{context}` like for other bots.
+    fn supports_system_prompt(&self, _chatbot_id: &str) -> bool {
+        self.template.uses("system_prompt")
+    }
+
+    fn describe(&self, call: &BackendCall<'_>) -> Result<Vec<DryRunCommand>, BackendError> {
+        Ok(vec![DryRunCommand {
+            chatbots: call.chatbots.to_vec(),
+            program: self.template.program.clone(),
+            args: self.template.render(call)?,
+            env: redact_env(&self.env),
+            working_dir: None,
+        }])
+    }
+
+    async fn dispatch(&self, call: &BackendCall<'_>) -> Result<Vec<ChatBotResponse>, BackendError> {
+        let mut command = Command::new(&self.template.program);
+        command.args(self.template.render(call)?);
+        command.envs(self.env.iter().cloned());
+        let id = call.chatbots.first().map(String::as_str);
+        run_process(command, call, &self.template.program, id).await
+    }
+
+    async fn dispatch_raw(&self, call: &BackendCall<'_>) -> Result<String, BackendError> {
+        let mut command = Command::new(&self.template.program);
+        command.args(self.template.render(call)?);
+        command.envs(self.env.iter().cloned());
+        let stdout = run_raw(command, call).await?;
+        Ok(redact_env_values(&stdout, &self.env))
+    }
+}
+
+/// Shell-style word splitting without expansion of any kind.
+fn split_words(template: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated ' in command template".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => return Err("Unterminated \" in command template".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated \" in command template".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("Command template ends with a lone \\".to_string()),
+            },
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Names of the `{placeholders}` in `word`, in order.
+fn placeholders(word: &str) -> Result<Vec<&str>, String> {
+    let mut found = Vec::new();
+    let mut rest = word;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(format!(
+                "Unmatched '}}' in command template argument '{}'",
+                word
+            ));
+        }
+        let after = &rest[open + 1..];
+        let close = after
+            .find('}')
+            .ok_or_else(|| format!("Unmatched '{{' in command template argument '{}'", word))?;
+        found.push(&after[..close]);
+        rest = &after[close + 1..];
+    }
+    Ok(found)
+}
+
+/// Replaces each `{name}` in an already validated `word` with `value(name)`.
+fn substitute<'a>(word: &str, value: impl Fn(&str) -> &'a str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut rest = word;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('}') else {
+            out.push_str(&rest[open..]);
+            return out;
+        };
+        out.push_str(value(&after[..close]));
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
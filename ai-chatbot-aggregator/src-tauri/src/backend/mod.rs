@@ -0,0 +1,305 @@
+mod command;
+mod node;
+mod openai;
+mod persistent;
+
+pub use command::{CommandBackend, CommandTemplate};
+pub use node::{spawn_env, BackendConfig, NodeBackend};
+pub use openai::OpenAiBackend;
+pub use persistent::PersistentNodeBackend;
+
+use crate::children::ChildRegistry;
+use crate::conversations::Message;
+use crate::error::AppError;
+use crate::secrets::Secrets;
+use crate::settings::NetworkSettings;
+use crate::{Attachment, ChatBotResponse};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+/// Builds the backend registered under `kind` ("node", "node-persistent" or
+/// "openai"), routing its traffic through the proxies in `network`.
+pub fn create(
+    kind: &str,
+    config: Arc<BackendConfig>,
+    secrets: Arc<Secrets>,
+    network: &NetworkSettings,
+) -> Result<Arc<dyn ChatBackend>, AppError> {
+    let node: Arc<dyn ChatBackend> = Arc::new(NodeBackend::new(config.clone(), network));
+    match kind {
+        "node" => Ok(node),
+        "node-persistent" => Ok(Arc::new(PersistentNodeBackend::new(config, network))),
+        "openai" => Ok(Arc::new(OpenAiBackend::new(secrets, node, network)?)),
+        other => Err(AppError::Validation(format!(
+            "Unknown backend '{}'; expected node, node-persistent or openai",
+            other
+        ))),
+    }
+}
+
+/// The backend prompts are currently sent through, switchable at runtime.
+pub struct ActiveBackend {
+    inner: RwLock<(String, Arc<dyn ChatBackend>)>,
+}
+
+impl ActiveBackend {
+    pub fn new(kind: &str, backend: Arc<dyn ChatBackend>) -> Self {
+        Self {
+            inner: RwLock::new((kind.to_string(), backend)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<dyn ChatBackend> {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .1
+            .clone()
+    }
+
+    pub fn kind(&self) -> String {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+            .clone()
+    }
+
+    pub fn set(&self, kind: &str, backend: Arc<dyn ChatBackend>) {
+        *self.inner.write().unwrap_or_else(|e| e.into_inner()) = (kind.to_string(), backend);
+    }
+}
+
+/// One invocation of a backend on behalf of a prompt request.
+#[derive(Clone, Copy)]
+pub struct BackendCall<'a> {
+    pub request_id: &'a str,
+    pub prompt: &'a str,
+    /// Only set for bots the backend reports as supporting system prompts;
+    /// for the rest it arrives as the first `context` message instead.
+    pub system_prompt: Option<&'a str>,
+    /// Earlier turns of the conversation, oldest first; empty for one-off prompts.
+    pub context: &'a [Message],
+    pub chatbots: &'a [String],
+    pub timeout_ms: Option<u64>,
+    /// Process-based backends stop a child whose stdout or stderr grows past this.
+    pub max_output_bytes: usize,
+    pub options: BotOptions<'a>,
+    /// Only set for bots the backend reports as supporting seeds.
+    pub seed: Option<u64>,
+    /// "markdown", "plain" or "json", already validated; the bot's own habit when `None`.
+    pub response_format: Option<&'a str>,
+    /// Only non-empty for bots the backend reports as supporting attachments.
+    pub attachments: &'a [Attachment],
+    /// Called with `(chatbot_id, text)` for each partial chunk a streaming backend produces.
+    pub on_delta: &'a (dyn Fn(&str, &str) + Send + Sync),
+    /// Receives anything the backend wrote to stderr, successful run or not.
+    pub on_diagnostics: &'a (dyn Fn(&str) + Send + Sync),
+    /// Where process-based backends register their children so cancellation can reach them.
+    pub children: &'a ChildRegistry,
+}
+
+/// Per-bot overrides from its `ChatBotConfig`. Anything left out means the
+/// backend's own default.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct BotOptions<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<&'a serde_json::Value>,
+    /// Only used by backends that make HTTP requests themselves, and never
+    /// passed on to the Node script.
+    #[serde(skip)]
+    pub headers: Option<&'a HashMap<String, String>>,
+}
+
+impl BotOptions<'_> {
+    /// Whether there is anything to pass to the Node script; `headers` don't count.
+    pub fn is_empty(&self) -> bool {
+        self.model.is_none() && self.params.is_none()
+    }
+}
+
+/// The instruction that asks a bot for `format`, for backends with no
+/// native switch for it.
+pub fn format_instruction(format: &str) -> Option<&'static str> {
+    match format {
+        "markdown" => Some("Format your answer as Markdown."),
+        "plain" => Some("Answer in plain text, without Markdown or code fences."),
+        "json" => Some("Answer with a single valid JSON value and nothing else."),
+        _ => None,
+    }
+}
+
+/// What a backend would run for a call, as reported by dry runs. Secret
+/// values are already redacted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunCommand {
+    pub chatbots: Vec<String>,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Variables set on the process on top of the inherited environment.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Where the process starts; unset means the app's working directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["key", "token", "secret", "password", "authorization"]
+        .iter()
+        .any(|s| key.contains(s))
+}
+
+/// Replaces the value of every key that looks like a credential, at any depth.
+pub fn redact_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Environment variables as safe to display: credential-looking names have
+/// their values masked, as do passwords embedded in URLs such as proxies.
+pub fn redact_env(env: &[(String, String)]) -> BTreeMap<String, String> {
+    env.iter()
+        .map(|(key, value)| {
+            let value = if is_secret_key(key) {
+                REDACTED.to_string()
+            } else {
+                match reqwest::Url::parse(value) {
+                    Ok(mut url) if url.password().is_some() => {
+                        let _ = url.set_password(Some(REDACTED));
+                        url.to_string()
+                    }
+                    _ => value.clone(),
+                }
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// `text` with the values of credential-looking variables in `env` masked,
+/// for output a process might have echoed them into.
+pub fn redact_env_values(text: &str, env: &[(String, String)]) -> String {
+    env.iter()
+        .filter(|(key, value)| is_secret_key(key) && !value.is_empty())
+        .fold(text.to_string(), |text, (_, value)| {
+            text.replace(value.as_str(), REDACTED)
+        })
+}
+
+/// Request headers as safe to log or display, sorted by name.
+pub fn redact_headers(headers: &HashMap<String, String>) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_secret_key(name) || name.eq_ignore_ascii_case("cookie") {
+                REDACTED.to_string()
+            } else {
+                value.clone()
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+/// Why a backend call produced no answer.
+#[derive(Debug)]
+pub enum BackendError {
+    Timeout(u64),
+    Cancelled,
+    /// The backend could not be started or crashed; worth retrying.
+    Transient(String),
+    Failed(String),
+}
+
+impl BackendError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, BackendError::Transient(_))
+    }
+
+    pub fn status(&self) -> &'static str {
+        match self {
+            BackendError::Timeout(_) => "timeout",
+            BackendError::Cancelled => "cancelled",
+            BackendError::Transient(_) | BackendError::Failed(_) => "error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            BackendError::Timeout(ms) => format!("No response within {} ms", ms),
+            BackendError::Cancelled => "Cancelled by user".to_string(),
+            BackendError::Transient(message) | BackendError::Failed(message) => message.clone(),
+        }
+    }
+}
+
+/// Something that can put a prompt to a set of chatbots. Retries, concurrency
+/// limits and progress events are handled by the dispatcher on top of this.
+#[async_trait]
+pub trait ChatBackend: Send + Sync {
+    /// Checks that the backend can run at all, so a misconfiguration is
+    /// reported once instead of once per bot.
+    fn ready(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Whether this backend takes `BackendCall::system_prompt` for `chatbot_id`.
+    fn supports_system_prompt(&self, _chatbot_id: &str) -> bool {
+        false
+    }
+
+    /// Whether this backend can make `chatbot_id` sample deterministically from `BackendCall::seed`.
+    fn supports_seed(&self, _chatbot_id: &str) -> bool {
+        false
+    }
+
+    /// Whether this backend can pass files to `chatbot_id`.
+    fn supports_attachments(&self, _chatbot_id: &str) -> bool {
+        false
+    }
+
+    /// Whether this backend reports `chatbot_id`'s answer through
+    /// `BackendCall::on_delta` while it is being written.
+    fn supports_streaming(&self, _chatbot_id: &str) -> bool {
+        false
+    }
+
+    /// Describes what `dispatch` would run for `call`, without running it.
+    fn describe(&self, call: &BackendCall<'_>) -> Result<Vec<DryRunCommand>, BackendError>;
+
+    /// Returns one response per chatbot in `call.chatbots` that answered.
+    async fn dispatch(&self, call: &BackendCall<'_>) -> Result<Vec<ChatBotResponse>, BackendError>;
+
+    /// Stops any long-lived process the backend keeps, so the next call
+    /// starts a fresh one. Returns whether one was running.
+    async fn restart(&self) -> bool {
+        false
+    }
+
+    /// Runs `call` like `dispatch` but returns the backend's stdout unparsed.
+    /// Only process-based backends have one.
+    async fn dispatch_raw(&self, _call: &BackendCall<'_>) -> Result<String, BackendError> {
+        Err(BackendError::Failed(
+            "This backend has no raw output".to_string(),
+        ))
+    }
+}
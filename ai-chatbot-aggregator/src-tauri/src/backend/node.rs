@@ -0,0 +1,1021 @@
+use super::{
+    redact_env, redact_env_values, redact_secrets, BackendCall, BackendError, ChatBackend,
+    DryRunCommand,
+};
+use crate::capture::Capture;
+use crate::dispatch::add_note;
+use crate::settings::NetworkSettings;
+use crate::{now_millis, ChatBotResponse};
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::io::BufReader;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+const NODE_ENV: &str = "AIMULTICHAT_NODE";
+const SCRIPT_ENV: &str = "AIMULTICHAT_SCRIPT";
+const WORKDIR_ENV: &str = "AIMULTICHAT_WORKDIR";
+const SCRIPT_NAME: &str = "ai-backend.js";
+/// Stdout lines read ahead of the dispatch loop. Once this many are waiting
+/// the reader stops, the pipe fills, and the backend blocks on its writes.
+const LINE_BUFFER: usize = 64;
+
+/// How to launch the Node backend, resolved once at startup.
+pub struct BackendConfig {
+    pub node_path: String,
+    pub script_path: String,
+    /// Where the script runs, so it finds files next to it wherever the app
+    /// was started from; `None` inherits the app's working directory.
+    pub working_dir: Option<PathBuf>,
+    /// Extra variables for every spawn; see [`BackendConfig::set_env`].
+    env: RwLock<HashMap<String, String>>,
+}
+
+impl BackendConfig {
+    /// Environment overrides win; otherwise `node` is looked up on `PATH` and the
+    /// script is taken from the bundled resources, falling back to the working
+    /// directory as in development. The script runs in its own directory.
+    pub fn resolve(app: &AppHandle, env: HashMap<String, String>) -> Self {
+        let node_path = env_override(NODE_ENV).unwrap_or_else(|| {
+            find_on_path("node")
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "node".to_string())
+        });
+
+        let script_path = env_override(SCRIPT_ENV).unwrap_or_else(|| {
+            app.path()
+                .resource_dir()
+                .map(|dir| dir.join(SCRIPT_NAME))
+                .ok()
+                .filter(|p| p.is_file())
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_else(|| SCRIPT_NAME.to_string())
+        });
+        // Relative paths mean the app's working directory, not the script's.
+        let script_path = std::path::absolute(&script_path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or(script_path);
+        let node_path = if Path::new(&node_path).components().count() > 1 {
+            std::path::absolute(&node_path)
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or(node_path)
+        } else {
+            node_path
+        };
+
+        let working_dir = env_override(WORKDIR_ENV).map(PathBuf::from).or_else(|| {
+            Path::new(&script_path)
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(Path::to_path_buf)
+        });
+
+        Self::new(node_path, script_path, working_dir, env)
+    }
+
+    /// Runs `script_path` with `node_path` as given, without resolving either.
+    pub fn new(
+        node_path: String,
+        script_path: String,
+        working_dir: Option<PathBuf>,
+        env: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            node_path,
+            script_path,
+            working_dir,
+            env: RwLock::new(env),
+        }
+    }
+
+    pub fn env(&self) -> HashMap<String, String> {
+        self.env.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replaces the configured variables. Spawned processes inherit the app's
+    /// environment, then get the proxy variables, then these; later ones win,
+    /// so a configured `HTTPS_PROXY` overrides the proxy setting.
+    pub fn set_env(&self, env: HashMap<String, String>) {
+        *self.env.write().unwrap_or_else(|e| e.into_inner()) = env;
+    }
+
+    /// A command for the Node binary, started in `working_dir`.
+    pub fn node_command(&self) -> Result<Command, String> {
+        let mut command = Command::new(self.node_program()?);
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+        Ok(command)
+    }
+
+    /// Returns the Node binary to spawn, or a descriptive error if it doesn't exist.
+    pub fn node_program(&self) -> Result<PathBuf, String> {
+        let path = Path::new(&self.node_path);
+        let found = if path.components().count() > 1 {
+            path.is_file().then(|| path.to_path_buf())
+        } else {
+            find_on_path(&self.node_path)
+        };
+
+        found.ok_or_else(|| {
+            format!(
+                "Node.js binary not found at '{}'; install Node.js or set {} to its full path",
+                self.node_path, NODE_ENV
+            )
+        })
+    }
+}
+
+/// Runs `ai-backend.js` as a child process per call and reads its JSON from stdout.
+pub struct NodeBackend {
+    config: Arc<BackendConfig>,
+    network: NetworkSettings,
+}
+
+impl NodeBackend {
+    pub fn new(config: Arc<BackendConfig>, network: &NetworkSettings) -> Self {
+        Self {
+            config,
+            network: network.clone(),
+        }
+    }
+
+    /// Variables set on top of the inherited environment, in increasing precedence.
+    fn env(&self) -> Vec<(String, String)> {
+        spawn_env(&self.config, &self.network)
+    }
+}
+
+/// The proxy variables followed by the configured ones, for anything that spawns the script.
+pub fn spawn_env(config: &BackendConfig, network: &NetworkSettings) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = network
+        .env_vars()
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+    let mut configured: Vec<_> = config.env().into_iter().collect();
+    configured.sort();
+    env.extend(configured);
+    env
+}
+
+impl NodeBackend {
+    /// Arguments for the script, starting with its path. With `redact`,
+    /// credential-looking option values are masked for display.
+    fn args(&self, call: &BackendCall<'_>, redact: bool) -> Result<Vec<String>, BackendError> {
+        let mut args = vec![
+            self.config.script_path.clone(),
+            "--prompt".to_string(),
+            call.prompt.to_string(),
+            "--chatbots".to_string(),
+            call.chatbots.join(","),
+        ];
+        // Prior turns go in as one JSON array of `{"role", "content"}` objects.
+        if !call.context.is_empty() {
+            args.push("--context".to_string());
+            args.push(encode(call.context, "context")?);
+        }
+        // Model and params as one JSON object, e.g. `{"model":"gpt-4o","params":{"temperature":0.2}}`.
+        if !call.options.is_empty() {
+            let mut options = serde_json::to_value(call.options)
+                .map_err(|e| BackendError::Failed(format!("Failed to encode options: {}", e)))?;
+            if redact {
+                redact_secrets(&mut options);
+            }
+            args.push("--options".to_string());
+            args.push(options.to_string());
+        }
+        if let Some(format) = call.response_format {
+            args.push("--format".to_string());
+            args.push(format.to_string());
+        }
+        // A JSON array of `{"filename", "mime", "path"}` objects.
+        if !call.attachments.is_empty() {
+            args.push("--attachments".to_string());
+            args.push(encode(call.attachments, "attachments")?);
+        }
+        Ok(args)
+    }
+}
+
+fn encode<T: serde::Serialize + ?Sized>(value: &T, what: &str) -> Result<String, BackendError> {
+    serde_json::to_string(value)
+        .map_err(|e| BackendError::Failed(format!("Failed to encode {}: {}", what, e)))
+}
+
+#[async_trait]
+impl ChatBackend for NodeBackend {
+    fn ready(&self) -> Result<(), String> {
+        self.config.node_program().map(|_| ())
+    }
+
+    /// Attachment paths are handed to the script, which decides per bot how to upload them.
+    fn supports_attachments(&self, _chatbot_id: &str) -> bool {
+        true
+    }
+
+    fn supports_streaming(&self, _chatbot_id: &str) -> bool {
+        true
+    }
+
+    fn describe(&self, call: &BackendCall<'_>) -> Result<Vec<DryRunCommand>, BackendError> {
+        // A missing binary is exactly what a dry run should help diagnose, so
+        // report the configured path instead of failing.
+        let program = self
+            .config
+            .node_program()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| self.config.node_path.clone());
+        Ok(vec![DryRunCommand {
+            chatbots: call.chatbots.to_vec(),
+            program,
+            args: self.args(call, true)?,
+            env: redact_env(&self.env()),
+            working_dir: self
+                .config
+                .working_dir
+                .as_ref()
+                .map(|dir| dir.to_string_lossy().into_owned()),
+        }])
+    }
+
+    async fn dispatch(&self, call: &BackendCall<'_>) -> Result<Vec<ChatBotResponse>, BackendError> {
+        // Execute the Node.js script to handle AI interactions
+        let mut command = self.config.node_command().map_err(BackendError::Failed)?;
+        command.args(self.args(call, false)?);
+        command.envs(self.env());
+        run_process(command, call, &self.config.script_path, None).await
+    }
+
+    async fn dispatch_raw(&self, call: &BackendCall<'_>) -> Result<String, BackendError> {
+        let mut command = self.config.node_command().map_err(BackendError::Failed)?;
+        command.args(self.args(call, false)?);
+        let env = self.env();
+        command.envs(env.iter().cloned());
+        let stdout = run_raw(command, call).await?;
+        Ok(redact_env_values(&stdout, &env))
+    }
+}
+
+/// Runs `command` for `call` and collects the answers it prints, honouring the
+/// call's timeout and cancellation. Output follows the `ai-backend.js` format;
+/// with `plain_text_id`, any line that isn't part of it is taken as streamed
+/// text for that bot instead of being ignored.
+pub(super) async fn run_process(
+    mut command: Command,
+    call: &BackendCall<'_>,
+    program: &str,
+    plain_text_id: Option<&str>,
+) -> Result<Vec<ChatBotResponse>, BackendError> {
+    // Children are tracked per request under the ids they answer for.
+    let key = call.chatbots.join(",");
+    // The prompt and context are user content, so only their sizes are logged.
+    tracing::info!(
+        request_id = call.request_id,
+        chatbots = %key,
+        program,
+        prompt_chars = call.prompt.chars().count(),
+        context_turns = call.context.len(),
+        attachments = call.attachments.len(),
+        has_options = !call.options.is_empty(),
+        "spawning AI backend"
+    );
+    let started = Instant::now();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| BackendError::Transient(format!("Failed to execute AI backend: {}", e)))?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| BackendError::Failed("Failed to capture AI backend stdout".to_string()))?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| BackendError::Failed("Failed to capture AI backend stderr".to_string()))?;
+    // Stdout lines, or why reading them stopped; stderr can stop them too.
+    let (tx, mut lines) = mpsc::channel(LINE_BUFFER);
+    // Drain stderr concurrently so a chatty backend can't fill the pipe and
+    // stall. It only holds a weak sender, so the channel still closes with stdout.
+    let overflow = tx.downgrade();
+    let limit = call.max_output_bytes;
+    let stderr_task = tauri::async_runtime::spawn(async move {
+        let captured = Capture::new("AI backend stderr", limit)
+            .rest(&mut stderr)
+            .await;
+        if let (Err(message), Some(tx)) = (&captured, overflow.upgrade()) {
+            let _ = tx.send(Err(message.clone())).await;
+        }
+        captured
+    });
+
+    if let Some(mut child) = call.children.insert(call.request_id, &key, child) {
+        let _ = child.kill().await;
+        return Err(BackendError::Cancelled);
+    }
+
+    let deadline = call
+        .timeout_ms
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+    tauri::async_runtime::spawn(async move {
+        let mut reader = BufReader::new(stdout);
+        let mut capture = Capture::new("AI backend stdout", limit);
+        loop {
+            let line = match capture.next_line(&mut reader).await {
+                Ok(None) => break,
+                Ok(Some(buf)) => Ok(decode_line(buf)),
+                Err(message) => Err(message),
+            };
+            let failed = line.is_err();
+            // A closed channel means the dispatch loop gave up on this run.
+            if tx.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    let mut stream = StreamAssembler::default();
+    let mut parse_error = None;
+
+    loop {
+        let line = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, lines.recv()).await {
+                Ok(line) => line,
+                Err(_) => {
+                    tracing::warn!(request_id = call.request_id, chatbots = %key, "AI backend timed out");
+                    call.children.kill(call.request_id, &key).await;
+                    return Err(BackendError::Timeout(call.timeout_ms.unwrap_or_default()));
+                }
+            },
+            None => lines.recv().await,
+        };
+
+        let (line, lossy) = match line {
+            Some(Ok(line)) => line,
+            None => break,
+            Some(Err(message)) => {
+                call.children.kill(call.request_id, &key).await;
+                return Err(BackendError::Failed(message));
+            }
+        };
+
+        match parse_backend_line(&line) {
+            Ok(BackendLine::Delta(delta)) if call.chatbots.contains(&delta.id) => {
+                (call.on_delta)(&delta.id, &delta.delta);
+                stream.push_delta(delta, lossy);
+            }
+            Ok(BackendLine::Responses(parsed)) => parsed
+                .into_iter()
+                .filter(|r| call.chatbots.contains(&r.id))
+                .for_each(|r| stream.push_response(r, lossy)),
+            Ok(BackendLine::Delta(_)) => {}
+            other => match (plain_text_id, other) {
+                (Some(id), _) => {
+                    let text = if stream.has_text(id) {
+                        format!("\n{}", line)
+                    } else {
+                        line
+                    };
+                    (call.on_delta)(id, &text);
+                    let delta = Delta {
+                        id: id.to_string(),
+                        delta: text,
+                    };
+                    stream.push_delta(delta, lossy);
+                }
+                (None, Err(e)) => {
+                    let recovered = recover_results(&line);
+                    if recovered.is_empty() {
+                        parse_error = Some(e);
+                    }
+                    for mut response in recovered {
+                        if call.chatbots.contains(&response.id) {
+                            add_note(
+                                &mut response,
+                                "Recovered from cut-off backend output".to_string(),
+                            );
+                            stream.push_response(response, lossy);
+                        }
+                    }
+                }
+                (None, _) => {}
+            },
+        }
+    }
+    // cancel_prompt kills the child and takes it out of the registry. Text
+    // that streamed in before then is kept, marked as cut short.
+    let Some(mut child) = call.children.take(call.request_id, &key) else {
+        let responses = stream.cancel();
+        if responses.is_empty() {
+            return Err(BackendError::Cancelled);
+        }
+        return Ok(responses);
+    };
+    let responses = stream.finish();
+    // Stderr can still overflow once stdout has closed.
+    let waited = match select(Box::pin(child.wait()), stderr_task).await {
+        Either::Left((status, stderr_task)) => Ok((status, stderr_task.await)),
+        Either::Right((Ok(Err(message)), _)) => Err(message),
+        Either::Right((stderr, wait)) => Ok((wait.await, stderr)),
+    };
+    let (status, stderr) = match waited {
+        Ok(waited) => waited,
+        Err(message) => {
+            let _ = child.kill().await;
+            return Err(BackendError::Failed(message));
+        }
+    };
+    let status =
+        status.map_err(|e| BackendError::Failed(format!("Failed to execute AI backend: {}", e)))?;
+    tracing::info!(
+        request_id = call.request_id,
+        chatbots = %key,
+        %status,
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        responses = responses.len(),
+        "AI backend exited"
+    );
+
+    let stderr = stderr
+        .unwrap_or_else(|_| Ok(Vec::new()))
+        .map_err(BackendError::Failed)?;
+    let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+    if !stderr.is_empty() {
+        (call.on_diagnostics)(&stderr);
+    }
+
+    // Answers printed before a crash are still answers; only a run that
+    // produced nothing counts as a failure.
+    if !status.success() && responses.is_empty() {
+        let code = status
+            .code()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "none (killed by signal)".to_string());
+        return Err(BackendError::Transient(format!(
+            "AI backend exited with code {}: {}",
+            code, stderr
+        )));
+    }
+
+    match parse_error {
+        Some(e) if responses.is_empty() => Err(BackendError::Failed(format!(
+            "Failed to parse response: {}",
+            e
+        ))),
+        _ => Ok(responses),
+    }
+}
+
+/// Runs `command` for `call` and returns its stdout as printed, failing like
+/// `run_process` on a timeout, cancellation or unsuccessful exit.
+pub(super) async fn run_raw(
+    mut command: Command,
+    call: &BackendCall<'_>,
+) -> Result<String, BackendError> {
+    let key = call.chatbots.join(",");
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| BackendError::Transient(format!("Failed to execute AI backend: {}", e)))?;
+    let (Some(mut stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err(BackendError::Failed(
+            "Failed to capture AI backend output".to_string(),
+        ));
+    };
+    if let Some(mut child) = call.children.insert(call.request_id, &key, child) {
+        let _ = child.kill().await;
+        return Err(BackendError::Cancelled);
+    }
+
+    let limit = call.max_output_bytes;
+    let mut stdout_capture = Capture::new("AI backend stdout", limit);
+    let mut stderr_capture = Capture::new("AI backend stderr", limit);
+    let output = futures::future::join(
+        stdout_capture.rest(&mut stdout),
+        stderr_capture.rest(&mut stderr),
+    );
+    let (stdout, stderr) = match call.timeout_ms {
+        Some(ms) => match tokio::time::timeout(Duration::from_millis(ms), output).await {
+            Ok(output) => output,
+            Err(_) => {
+                call.children.kill(call.request_id, &key).await;
+                return Err(BackendError::Timeout(ms));
+            }
+        },
+        None => output.await,
+    };
+    let (stdout, stderr) = match (stdout, stderr) {
+        (Ok(stdout), Ok(stderr)) => (stdout, stderr),
+        (Err(message), _) | (_, Err(message)) => {
+            call.children.kill(call.request_id, &key).await;
+            return Err(BackendError::Failed(message));
+        }
+    };
+
+    let Some(mut child) = call.children.take(call.request_id, &key) else {
+        return Err(BackendError::Cancelled);
+    };
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| BackendError::Failed(format!("Failed to execute AI backend: {}", e)))?;
+    let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+    if !stderr.is_empty() {
+        (call.on_diagnostics)(&stderr);
+    }
+    if !status.success() {
+        let code = status
+            .code()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "none (killed by signal)".to_string());
+        return Err(BackendError::Transient(format!(
+            "AI backend exited with code {}: {}",
+            code, stderr
+        )));
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Decodes one stdout line without its line ending. Invalid UTF-8 is
+/// replaced rather than failing the run, and reported as the `bool`.
+fn decode_line(mut bytes: Vec<u8>) -> (String, bool) {
+    while matches!(bytes.last(), Some(b'\n' | b'\r')) {
+        bytes.pop();
+    }
+    match String::from_utf8(bytes) {
+        Ok(line) => (line, false),
+        Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), true),
+    }
+}
+
+/// A partial chunk of a bot's answer: `{"id":"claude","delta":"partial text"}`.
+#[derive(Deserialize)]
+struct Delta {
+    id: String,
+    delta: String,
+}
+
+/// The answers in an aggregated `PromptResponse` line that was cut off, e.g.
+/// because the backend was killed mid-write: every `results` element that
+/// was written out in full, up to the first incomplete one.
+fn recover_results(line: &str) -> Vec<ChatBotResponse> {
+    let mut recovered = Vec::new();
+    // An unescaped `"results"` can only be a key; quotes inside strings are escaped.
+    let Some(at) = line.find("\"results\"") else {
+        return recovered;
+    };
+    let rest = line[at + "\"results\"".len()..].trim_start();
+    let Some(mut rest) = rest
+        .strip_prefix(':')
+        .and_then(|rest| rest.trim_start().strip_prefix('['))
+    else {
+        return recovered;
+    };
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let mut values = serde_json::Deserializer::from_str(rest).into_iter::<ChatBotResponse>();
+        match values.next() {
+            Some(Ok(response)) => {
+                recovered.push(response);
+                rest = &rest[values.byte_offset()..];
+            }
+            _ => return recovered,
+        }
+    }
+}
+
+enum BackendLine {
+    Delta(Delta),
+    Responses(Vec<ChatBotResponse>),
+    Ignored,
+}
+
+/// Which kind of line an object is, found by skipping over its values
+/// without building any of them.
+#[derive(Deserialize)]
+struct Probe {
+    delta: Option<IgnoredAny>,
+    results: Option<IgnoredAny>,
+}
+
+/// The part of an aggregated `PromptResponse` that is kept. Everything else
+/// in it is skipped rather than allocated, which matters for the
+/// multi-megabyte answers some bots produce.
+#[derive(Deserialize)]
+struct Aggregated {
+    results: Vec<ChatBotResponse>,
+}
+
+/// Parses one line of backend stdout. The backend may stream deltas, print
+/// one `ChatBotResponse` per line, or print a single aggregated
+/// `PromptResponse`; anything that isn't a JSON object (e.g. log output) is
+/// ignored. Each line is fully parsed at most once, after a probe that
+/// decides which of the three it is.
+fn parse_backend_line(line: &str) -> Result<BackendLine, serde_json::Error> {
+    let line = line.trim();
+    if !line.starts_with('{') {
+        return Ok(BackendLine::Ignored);
+    }
+
+    let probe: Probe = serde_json::from_str(line)?;
+    if probe.delta.is_some() {
+        serde_json::from_str(line).map(BackendLine::Delta)
+    } else if probe.results.is_some() {
+        serde_json::from_str::<Aggregated>(line).map(|a| BackendLine::Responses(a.results))
+    } else {
+        serde_json::from_str(line).map(|r| BackendLine::Responses(vec![r]))
+    }
+}
+
+/// Reassembles streamed output per bot. A complete response always wins over
+/// the deltas seen for that bot, so the result matches non-streaming mode; a
+/// bot that only ever streamed is finished from its accumulated text, or
+/// reported as cancelled with it if the run was stopped.
+#[derive(Default)]
+struct StreamAssembler {
+    /// Bot id, text so far, and whether any of it had to be decoded lossily.
+    partial: Vec<(String, String, bool)>,
+    complete: Vec<ChatBotResponse>,
+}
+
+impl StreamAssembler {
+    fn push_delta(&mut self, delta: Delta, lossy: bool) {
+        match self.partial.iter_mut().find(|(id, _, _)| *id == delta.id) {
+            Some((_, text, warning)) => {
+                text.push_str(&delta.delta);
+                *warning |= lossy;
+            }
+            None => self.partial.push((delta.id, delta.delta, lossy)),
+        }
+    }
+
+    fn has_text(&self, id: &str) -> bool {
+        self.partial.iter().any(|(partial, _, _)| partial == id)
+    }
+
+    fn push_response(&mut self, mut response: ChatBotResponse, lossy: bool) {
+        self.partial.retain(|(id, _, _)| *id != response.id);
+        response.encoding_warning |= lossy;
+        self.complete.push(response);
+    }
+
+    fn finish(self) -> Vec<ChatBotResponse> {
+        self.finish_as("success", None)
+    }
+
+    fn cancel(self) -> Vec<ChatBotResponse> {
+        self.finish_as("cancelled", Some(BackendError::Cancelled.message()))
+    }
+
+    fn finish_as(self, status: &str, error: Option<String>) -> Vec<ChatBotResponse> {
+        let mut responses = self.complete;
+        responses.extend(
+            self.partial
+                .into_iter()
+                .map(|(id, text, lossy)| ChatBotResponse {
+                    // The dispatcher fills in the display name.
+                    id,
+                    response: text,
+                    status: status.to_string(),
+                    error: error.clone(),
+                    timestamp: now_millis(),
+                    encoding_warning: lossy,
+                    ..Default::default()
+                }),
+        );
+        responses
+    }
+}
+
+fn env_override(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn find_on_path(program: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).find_map(|dir| {
+        let candidate = dir.join(program);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let exe = candidate.with_extension("exe");
+        exe.is_file().then_some(exe)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config whose "Node binary" is this test executable, which exists on
+    /// every platform.
+    fn config(working_dir: Option<PathBuf>) -> BackendConfig {
+        let node_path = std::env::current_exe().unwrap();
+        BackendConfig::new(
+            node_path.to_string_lossy().into_owned(),
+            "ai-backend.js".to_string(),
+            working_dir,
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn node_command_starts_in_the_working_dir() {
+        let dir = std::env::temp_dir();
+        let command = config(Some(dir.clone())).node_command().unwrap();
+        assert_eq!(command.as_std().get_current_dir(), Some(dir.as_path()));
+
+        let command = config(None).node_command().unwrap();
+        assert_eq!(command.as_std().get_current_dir(), None);
+    }
+
+    #[test]
+    fn node_command_reports_a_missing_binary() {
+        let config = BackendConfig::new(
+            "/no/such/node".to_string(),
+            "ai-backend.js".to_string(),
+            None,
+            HashMap::new(),
+        );
+        let error = config.node_command().unwrap_err();
+        assert!(error.contains("'/no/such/node'"), "{}", error);
+    }
+
+    #[test]
+    fn decode_line_strips_the_line_ending() {
+        assert_eq!(
+            decode_line(b"hello\r\n".to_vec()),
+            ("hello".to_string(), false)
+        );
+        assert_eq!(decode_line(b"hello".to_vec()), ("hello".to_string(), false));
+    }
+
+    #[test]
+    fn decode_line_replaces_invalid_utf8() {
+        let (line, lossy) = decode_line(b"caf\xe9 \xff\xfe ok\n".to_vec());
+        assert!(lossy);
+        assert_eq!(line, "caf\u{fffd} \u{fffd}\u{fffd} ok");
+        // A multi-byte character cut short.
+        assert_eq!(
+            decode_line(vec![b'a', 0xE4, 0xBD]),
+            ("a\u{fffd}".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn parses_each_kind_of_line() {
+        assert!(matches!(
+            parse_backend_line("starting up"),
+            Ok(BackendLine::Ignored)
+        ));
+        assert!(matches!(
+            parse_backend_line(r#"{"id":"a","delta":"Hi"}"#),
+            Ok(BackendLine::Delta(d)) if d.id == "a" && d.delta == "Hi"
+        ));
+        let single = r#"{"id":"a","name":"A","response":"Hi","status":"success","error":null,"timestamp":1}"#;
+        assert!(matches!(
+            parse_backend_line(single),
+            Ok(BackendLine::Responses(r)) if r.len() == 1 && r[0].response == "Hi"
+        ));
+        assert!(parse_backend_line("{not json").is_err());
+    }
+
+    #[test]
+    fn parses_a_large_aggregated_answer() {
+        // Two 8 MB answers, plus a field that is only skipped over.
+        let body = "lorem ipsum ".repeat(700_000);
+        let result = |id: &str| {
+            serde_json::json!({
+                "id": id, "name": id, "response": body, "status": "success",
+                "error": null, "timestamp": 1,
+            })
+        };
+        let line = serde_json::json!({
+            "prompt": "Hi",
+            "results": [result("a"), result("b")],
+            "timestamp": 1,
+            "debug": body,
+        })
+        .to_string();
+
+        let started = std::time::Instant::now();
+        let Ok(BackendLine::Responses(responses)) = parse_backend_line(&line) else {
+            panic!("not parsed as an aggregated answer");
+        };
+        let elapsed = started.elapsed();
+        assert_eq!(responses.len(), 2);
+        assert!(responses.iter().all(|r| r.response.len() == body.len()));
+        // Generous, so only a quadratic slowdown trips it.
+        assert!(elapsed < Duration::from_secs(10), "took {:?}", elapsed);
+    }
+
+    #[test]
+    fn recovers_complete_results_from_a_cut_off_line() {
+        let line = concat!(
+            r#"{"prompt":"Hi","results":["#,
+            r#"{"id":"a","name":"A","response":"First, \"quoted\" ]}","status":"success","error":null,"timestamp":1},"#,
+            r#" {"id":"b","name":"B","response":"Second","status":"success","error":null,"timestamp":2},"#,
+            r#"{"id":"c","name":"C","response":"Thi"#,
+        );
+        assert!(parse_backend_line(line).is_err());
+        let recovered = recover_results(line);
+        let ids: Vec<&str> = recovered.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(recovered[0].response, "First, \"quoted\" ]}");
+    }
+
+    #[test]
+    fn recovers_nothing_without_a_results_array() {
+        assert!(recover_results(r#"{"prompt":"Hi","resul"#).is_empty());
+        assert!(recover_results(r#"{"results": {"id":"a"}"#).is_empty());
+        assert!(recover_results(r#"{"results":[{"id":"a","name":"#).is_empty());
+    }
+
+    /// Runs fake backends, which are shell scripts.
+    #[cfg(unix)]
+    mod process {
+        use super::super::*;
+        use crate::backend::BotOptions;
+        use crate::children::ChildRegistry;
+        use std::sync::Mutex;
+
+        const REQUEST: &str = "request";
+
+        /// A call to `chatbots` with nothing but the output limit set.
+        fn call<'a>(
+            children: &'a ChildRegistry,
+            chatbots: &'a [String],
+            on_delta: &'a (dyn Fn(&str, &str) + Send + Sync),
+        ) -> BackendCall<'a> {
+            BackendCall {
+                request_id: REQUEST,
+                prompt: "Hello",
+                system_prompt: None,
+                context: &[],
+                chatbots,
+                timeout_ms: None,
+                max_output_bytes: 16 * 1024 * 1024,
+                options: BotOptions::default(),
+                seed: None,
+                response_format: None,
+                attachments: &[],
+                on_delta,
+                on_diagnostics: &|_| {},
+                children,
+            }
+        }
+
+        fn sh(script: &str) -> Command {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            command
+        }
+
+        fn ids(ids: &[&str]) -> Vec<String> {
+            ids.iter().map(|id| id.to_string()).collect()
+        }
+
+        #[test]
+        fn keeps_every_delta_from_a_fast_backend() {
+            let children = ChildRegistry::default();
+            let chatbots = ids(&["a"]);
+            let seen = Mutex::new(0usize);
+            let on_delta = |_: &str, delta: &str| {
+                *seen.lock().unwrap() += delta.len();
+                // A slow consumer, so the reader has to hold the backend back.
+                std::thread::sleep(Duration::from_micros(20));
+            };
+            let backend = sh(r#"seq 1 20000 | sed 's/.*/{"id":"a","delta":"xy"}/'"#);
+
+            let responses = tauri::async_runtime::block_on(run_process(
+                backend,
+                &call(&children, &chatbots, &on_delta),
+                "sh",
+                None,
+            ))
+            .unwrap();
+            assert_eq!(*seen.lock().unwrap(), 40_000);
+            assert_eq!(responses.len(), 1);
+            assert_eq!(responses[0].status, "success");
+            assert_eq!(responses[0].response, "xy".repeat(20_000));
+        }
+
+        #[test]
+        fn cancelling_keeps_the_streamed_text() {
+            let children = ChildRegistry::default();
+            children.begin(REQUEST, None);
+            let chatbots = ids(&["a"]);
+            let seen = Mutex::new(0usize);
+            let on_delta = |_: &str, _: &str| *seen.lock().unwrap() += 1;
+            let backend = sh(r#"
+                for chunk in Par tial " text"; do
+                    printf '{"id":"a","delta":"%s"}\n' "$chunk"
+                done
+                exec sleep 30
+            "#);
+
+            let call = call(&children, &chatbots, &on_delta);
+            let cancel = async {
+                while *seen.lock().unwrap() < 3 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                assert!(children.cancel(REQUEST).await);
+            };
+            let started = Instant::now();
+            let (responses, _) = tauri::async_runtime::block_on(futures::future::join(
+                run_process(backend, &call, "sh", None),
+                cancel,
+            ));
+            let responses = responses.unwrap();
+            assert!(started.elapsed() < Duration::from_secs(10));
+            assert_eq!(responses.len(), 1);
+            assert_eq!(responses[0].status, "cancelled");
+            assert_eq!(responses[0].response, "Partial text");
+        }
+
+        #[test]
+        fn stops_a_backend_that_prints_too_much() {
+            let children = ChildRegistry::default();
+            let chatbots = ids(&["a"]);
+            let mut call = call(&children, &chatbots, &|_, _| {});
+            call.max_output_bytes = 10_000;
+
+            let started = Instant::now();
+            let result = tauri::async_runtime::block_on(run_process(
+                sh("while :; do echo spam; done"),
+                &call,
+                "sh",
+                None,
+            ));
+            let Err(BackendError::Failed(message)) = result else {
+                panic!("expected the run to fail");
+            };
+            assert!(message.starts_with("AI backend stdout exceeded the limit of 10000 bytes"));
+            assert!(started.elapsed() < Duration::from_secs(10));
+            // The child was killed and is no longer tracked.
+            assert!(children.take(REQUEST, "a").is_none());
+        }
+
+        #[test]
+        fn keeps_answers_printed_before_a_crash() {
+            let children = ChildRegistry::default();
+            let chatbots = ids(&["a", "b"]);
+            let backend = sh(r#"
+                printf '{"results":[{"id":"a","name":"A","response":"Done","status":"success","error":null,"timestamp":1},{"id":"b","na'
+                kill -9 $$
+            "#);
+
+            let responses = tauri::async_runtime::block_on(run_process(
+                backend,
+                &call(&children, &chatbots, &|_, _| {}),
+                "sh",
+                None,
+            ))
+            .unwrap();
+            assert_eq!(responses.len(), 1);
+            assert_eq!(responses[0].id, "a");
+            assert_eq!(responses[0].response, "Done");
+            assert_eq!(
+                responses[0].error.as_deref(),
+                Some("Recovered from cut-off backend output")
+            );
+        }
+
+        #[test]
+        fn flags_answers_with_invalid_utf8() {
+            let children = ChildRegistry::default();
+            let chatbots = ids(&["a", "b"]);
+            let backend = sh(r#"
+                printf '{"id":"a","delta":"caf\351"}\n'
+                printf '{"id":"b","delta":"fine"}\n'
+            "#);
+
+            let responses = tauri::async_runtime::block_on(run_process(
+                backend,
+                &call(&children, &chatbots, &|_, _| {}),
+                "sh",
+                None,
+            ))
+            .unwrap();
+            assert_eq!(responses.len(), 2);
+            assert_eq!(responses[0].response, "caf\u{fffd}");
+            assert!(responses[0].encoding_warning);
+            assert_eq!(responses[1].response, "fine");
+            assert!(!responses[1].encoding_warning);
+        }
+    }
+}
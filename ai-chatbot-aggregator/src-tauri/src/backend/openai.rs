@@ -0,0 +1,334 @@
+use super::{
+    format_instruction, redact_headers, redact_secrets, BackendCall, BackendError, ChatBackend,
+    DryRunCommand, REDACTED,
+};
+use crate::error::AppError;
+use crate::pricing;
+use crate::secrets::Secrets;
+use crate::settings::NetworkSettings;
+use crate::{now_millis, ChatBotResponse};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const API_URL: &str = "https://api.openai.com/v1/chat/completions";
+const API_KEY_ENV: &str = "OPENAI_API_KEY";
+const PROVIDER: &str = "openai";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+const CHATBOT_ID: &str = "chatgpt";
+
+/// Answers for `chatgpt` by calling the OpenAI chat completions API directly;
+/// every other bot is handed to `fallback`.
+pub struct OpenAiBackend {
+    client: reqwest::Client,
+    secrets: Arc<Secrets>,
+    fallback: Arc<dyn ChatBackend>,
+}
+
+#[derive(Deserialize)]
+struct Completion {
+    #[serde(default)]
+    model: String,
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Option<String>,
+}
+
+impl OpenAiBackend {
+    pub fn new(
+        secrets: Arc<Secrets>,
+        fallback: Arc<dyn ChatBackend>,
+        network: &NetworkSettings,
+    ) -> Result<Self, AppError> {
+        let identity = &network.client_identity;
+        let mut builder = reqwest::Client::builder().user_agent(&identity.user_agent);
+        if let Some(id) = &identity.client_id {
+            let value = reqwest::header::HeaderValue::from_str(id)
+                .map_err(|_| AppError::Validation(format!("Invalid client id '{}'", id)))?;
+            builder = builder.default_headers(reqwest::header::HeaderMap::from_iter([(
+                reqwest::header::HeaderName::from_static("x-client-id"),
+                value,
+            )]));
+        }
+        if let Some(url) = &network.http_proxy {
+            builder = builder.proxy(proxy(reqwest::Proxy::http(url), url)?);
+        }
+        if let Some(url) = &network.https_proxy {
+            builder = builder.proxy(proxy(reqwest::Proxy::https(url), url)?);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| AppError::BackendSpawn(format!("Failed to build HTTP client: {}", e)))?;
+        Ok(Self {
+            client,
+            secrets,
+            fallback,
+        })
+    }
+
+    /// The stored key wins; the environment variable is a fallback for development.
+    fn api_key(&self) -> Result<String, BackendError> {
+        if let Some(key) = self.secrets.get(PROVIDER).map_err(BackendError::Failed)? {
+            return Ok(key);
+        }
+        std::env::var(API_KEY_ENV).map_err(|_| {
+            BackendError::Failed(format!(
+                "No OpenAI API key stored and {} is not set",
+                API_KEY_ENV
+            ))
+        })
+    }
+
+    fn request_body(call: &BackendCall<'_>) -> Result<Value, BackendError> {
+        let mut messages = serde_json::to_value(call.context)
+            .map_err(|e| BackendError::Failed(format!("Failed to encode context: {}", e)))?;
+        if let Some(messages) = messages.as_array_mut() {
+            if let Some(system) = call.system_prompt {
+                messages.insert(0, json!({ "role": "system", "content": system }));
+            }
+            // JSON mode also requires the word "JSON" somewhere in the messages.
+            if let Some(instruction) = call.response_format.and_then(format_instruction) {
+                messages.push(json!({ "role": "system", "content": instruction }));
+            }
+            messages.push(json!({ "role": "user", "content": call.prompt }));
+        }
+        let mut body = json!({
+            "model": call.options.model.unwrap_or(DEFAULT_MODEL),
+            "messages": messages,
+        });
+        if let (Some(body), Some(seed)) = (body.as_object_mut(), call.seed) {
+            body.insert("seed".to_string(), json!(seed));
+        }
+        if let (Some(body), Some("json")) = (body.as_object_mut(), call.response_format) {
+            body.insert(
+                "response_format".to_string(),
+                json!({ "type": "json_object" }),
+            );
+        }
+        // Params are merged into the request body; model and messages are ours.
+        if let (Some(body), Some(Value::Object(params))) =
+            (body.as_object_mut(), call.options.params)
+        {
+            for (key, value) in params {
+                if key != "model" && key != "messages" {
+                    body.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(body)
+    }
+
+    async fn complete(&self, call: &BackendCall<'_>) -> Result<ChatBotResponse, BackendError> {
+        let timeout_ms = call.timeout_ms;
+        let body = Self::request_body(call)?;
+
+        let mut request = self.client.post(API_URL).json(&body);
+        if !call.options.headers.is_some_and(has_authorization) {
+            request = request.bearer_auth(self.api_key()?);
+        }
+        if let Some(headers) = call.options.headers {
+            tracing::debug!(
+                request_id = call.request_id,
+                headers = ?redact_headers(headers),
+                "adding custom headers"
+            );
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+        }
+        if let Some(ms) = timeout_ms {
+            request = request.timeout(Duration::from_millis(ms));
+        }
+
+        let started = Instant::now();
+        let response = request
+            .send()
+            .await
+            .map_err(|e| request_error(e, timeout_ms))?;
+        let status = response.status();
+        tracing::info!(
+            request_id = call.request_id,
+            model = call.options.model.unwrap_or(DEFAULT_MODEL),
+            %status,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "OpenAI request finished"
+        );
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let message = format!("OpenAI API returned {}: {}", status, body);
+            // Rate limits and server errors are worth another attempt; anything
+            // else is the API rejecting the request and is reported as-is.
+            if status.as_u16() == 429 || status.is_server_error() {
+                return Err(BackendError::Transient(message));
+            }
+            return Ok(self.response(String::new(), "error", Some(message), started));
+        }
+
+        let completion: Completion = response
+            .json()
+            .await
+            .map_err(|e| BackendError::Failed(format!("Failed to parse OpenAI response: {}", e)))?;
+        let content = completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .unwrap_or_default();
+
+        let mut result = self.response(content, "success", None, started);
+        if let Some(usage) = completion.usage {
+            // Price by the model that actually answered, which may be a dated variant.
+            let model = if completion.model.is_empty() {
+                call.options.model.unwrap_or(DEFAULT_MODEL)
+            } else {
+                &completion.model
+            };
+            result.prompt_tokens = Some(usage.prompt_tokens);
+            result.completion_tokens = Some(usage.completion_tokens);
+            result.estimated_cost_usd =
+                pricing::estimate_cost(model, usage.prompt_tokens, usage.completion_tokens);
+        }
+        Ok(result)
+    }
+
+    fn response(
+        &self,
+        response: String,
+        status: &str,
+        error: Option<String>,
+        started: Instant,
+    ) -> ChatBotResponse {
+        ChatBotResponse {
+            id: CHATBOT_ID.to_string(),
+            name: "ChatGPT".to_string(),
+            response,
+            status: status.to_string(),
+            error,
+            timestamp: now_millis(),
+            latency_ms: started.elapsed().as_millis() as u64,
+            ..Default::default()
+        }
+    }
+}
+
+/// Splits the call's bots into those served directly and those handed to the fallback.
+fn split(call: &BackendCall<'_>) -> (Vec<String>, Vec<String>) {
+    call.chatbots
+        .iter()
+        .cloned()
+        .partition(|id| id == CHATBOT_ID)
+}
+
+fn has_authorization(headers: &HashMap<String, String>) -> bool {
+    headers
+        .keys()
+        .any(|name| name.eq_ignore_ascii_case("authorization"))
+}
+
+fn proxy(result: reqwest::Result<reqwest::Proxy>, url: &str) -> Result<reqwest::Proxy, AppError> {
+    result.map_err(|e| AppError::Validation(format!("Invalid proxy URL '{}': {}", url, e)))
+}
+
+fn request_error(e: reqwest::Error, timeout_ms: Option<u64>) -> BackendError {
+    if e.is_timeout() {
+        BackendError::Timeout(timeout_ms.unwrap_or_default())
+    } else {
+        BackendError::Transient(format!("OpenAI request failed: {}", e))
+    }
+}
+
+#[async_trait]
+impl ChatBackend for OpenAiBackend {
+    fn supports_system_prompt(&self, chatbot_id: &str) -> bool {
+        chatbot_id == CHATBOT_ID || self.fallback.supports_system_prompt(chatbot_id)
+    }
+
+    fn supports_seed(&self, chatbot_id: &str) -> bool {
+        chatbot_id == CHATBOT_ID || self.fallback.supports_seed(chatbot_id)
+    }
+
+    fn supports_attachments(&self, chatbot_id: &str) -> bool {
+        chatbot_id != CHATBOT_ID && self.fallback.supports_attachments(chatbot_id)
+    }
+
+    fn supports_streaming(&self, chatbot_id: &str) -> bool {
+        chatbot_id != CHATBOT_ID && self.fallback.supports_streaming(chatbot_id)
+    }
+
+    fn describe(&self, call: &BackendCall<'_>) -> Result<Vec<DryRunCommand>, BackendError> {
+        let (direct, rest) = split(call);
+        let mut commands = Vec::new();
+        if !direct.is_empty() {
+            let mut body = Self::request_body(call)?;
+            redact_secrets(&mut body);
+            let mut args = Vec::new();
+            if !call.options.headers.is_some_and(has_authorization) {
+                args.push(format!("Authorization: Bearer {}", REDACTED));
+            }
+            if let Some(headers) = call.options.headers {
+                args.extend(
+                    redact_headers(headers)
+                        .into_iter()
+                        .map(|(name, value)| format!("{}: {}", name, value)),
+                );
+            }
+            args.push(body.to_string());
+            commands.push(DryRunCommand {
+                chatbots: direct,
+                program: format!("POST {}", API_URL),
+                args,
+                env: Default::default(),
+                working_dir: None,
+            });
+        }
+        if !rest.is_empty() {
+            let fallback_call = BackendCall {
+                chatbots: &rest,
+                ..*call
+            };
+            commands.extend(self.fallback.describe(&fallback_call)?);
+        }
+        Ok(commands)
+    }
+
+    async fn dispatch(&self, call: &BackendCall<'_>) -> Result<Vec<ChatBotResponse>, BackendError> {
+        let (direct, rest) = split(call);
+
+        let mut responses = Vec::new();
+        if !direct.is_empty() {
+            responses.push(self.complete(call).await?);
+        }
+        if !rest.is_empty() {
+            let fallback_call = BackendCall {
+                chatbots: &rest,
+                ..*call
+            };
+            responses.extend(self.fallback.dispatch(&fallback_call).await?);
+        }
+
+        Ok(responses)
+    }
+
+    async fn restart(&self) -> bool {
+        self.fallback.restart().await
+    }
+}
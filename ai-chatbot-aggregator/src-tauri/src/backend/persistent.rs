@@ -0,0 +1,502 @@
+use super::node::NodeBackend;
+use super::{spawn_env, BackendCall, BackendConfig, BackendError, ChatBackend, DryRunCommand};
+use crate::capture::Capture;
+use crate::conversations::Message;
+use crate::settings::NetworkSettings;
+use crate::{Attachment, ChatBotResponse};
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+type Answer = Result<Vec<ChatBotResponse>, String>;
+
+/// Requests written to the process that haven't been answered yet.
+#[derive(Default)]
+struct Routes {
+    /// Set once stdout closes; nothing written after that will be answered.
+    exited: bool,
+    waiting: HashMap<String, oneshot::Sender<Answer>>,
+}
+
+struct Process {
+    stdin: ChildStdin,
+    routes: Arc<Mutex<Routes>>,
+    /// Only held so the process is killed when this is dropped.
+    _child: Child,
+}
+
+/// One request line for `ai-backend.js --serve`; the fields mean what the
+/// matching `--prompt` flags do.
+#[derive(Serialize)]
+struct ServeRequest<'a> {
+    id: &'a str,
+    prompt: &'a str,
+    chatbots: &'a [String],
+    context: &'a [Message],
+    options: super::BotOptions<'a>,
+    attachments: &'a [Attachment],
+    format: Option<&'a str>,
+}
+
+/// Tells `ai-backend.js --serve` to drop the call `id`; no answer follows.
+#[derive(Serialize)]
+struct ServeCancel<'a> {
+    id: &'a str,
+    cancel: bool,
+}
+
+#[derive(Deserialize)]
+struct ServeReply {
+    id: Option<String>,
+    response: Option<Aggregated>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Aggregated {
+    results: Vec<ChatBotResponse>,
+}
+
+/// Runs `ai-backend.js --serve` once and sends it every call as a line of
+/// JSON, so the script and its browser sessions only start up once. Answers
+/// are matched to calls by id. Whenever the process can't be started or dies
+/// with a call in flight, that call is run by spawning a process of its own
+/// like `NodeBackend`, and the next call starts a fresh persistent one.
+pub struct PersistentNodeBackend {
+    config: Arc<BackendConfig>,
+    network: NetworkSettings,
+    fallback: NodeBackend,
+    process: tokio::sync::Mutex<Option<Process>>,
+}
+
+impl PersistentNodeBackend {
+    pub fn new(config: Arc<BackendConfig>, network: &NetworkSettings) -> Self {
+        Self {
+            fallback: NodeBackend::new(config.clone(), network),
+            config,
+            network: network.clone(),
+            process: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Writes `line` to the process, starting one if there is none running,
+    /// and returns where its answer will arrive.
+    async fn send(
+        &self,
+        id: &str,
+        line: &str,
+        limit: usize,
+    ) -> Result<(Arc<Mutex<Routes>>, oneshot::Receiver<Answer>), String> {
+        let mut process = self.process.lock().await;
+        if process.as_ref().is_none_or(|p| lock(&p.routes).exited) {
+            *process = Some(self.spawn(limit)?);
+        }
+        let Some(running) = process.as_mut() else {
+            unreachable!("a process was just started");
+        };
+
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut routes = lock(&running.routes);
+            if routes.exited {
+                return Err("AI backend exited".to_string());
+            }
+            routes.waiting.insert(id.to_string(), tx);
+        }
+        // Writes happen under the process lock, so lines never interleave.
+        if let Err(e) = running.stdin.write_all(line.as_bytes()).await {
+            *process = None;
+            return Err(format!("Failed to write to AI backend: {}", e));
+        }
+        Ok((running.routes.clone(), rx))
+    }
+
+    /// Stops waiting for the call `id` and, if the process that took it is
+    /// still running, tells it to stop working on it.
+    async fn abandon(&self, routes: &Arc<Mutex<Routes>>, id: &str) {
+        lock(routes).waiting.remove(id);
+        let mut process = self.process.lock().await;
+        let Some(running) = process
+            .as_mut()
+            .filter(|p| Arc::ptr_eq(&p.routes, routes) && !lock(&p.routes).exited)
+        else {
+            return;
+        };
+        let mut line = serde_json::to_string(&ServeCancel { id, cancel: true })
+            .expect("a cancel line always encodes");
+        line.push('\n');
+        if let Err(e) = running.stdin.write_all(line.as_bytes()).await {
+            tracing::warn!("Failed to cancel an AI backend call: {}", e);
+            *process = None;
+        }
+    }
+
+    fn spawn(&self, limit: usize) -> Result<Process, String> {
+        let mut child = self
+            .config
+            .node_command()?
+            .envs(spawn_env(&self.config, &self.network))
+            .arg(&self.config.script_path)
+            .arg("--serve")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to execute AI backend: {}", e))?;
+        let (Some(stdin), Some(stdout), Some(mut stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err("Failed to capture AI backend pipes".to_string());
+        };
+
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let reader_routes = routes.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                // The process outlives any one call, so the limit is per answer.
+                let line = Capture::new("AI backend stdout", limit)
+                    .next_line(&mut reader)
+                    .await;
+                match line {
+                    Ok(Some(line)) => route(&reader_routes, &line),
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        break;
+                    }
+                }
+            }
+            // Dropping the senders tells every waiting call its answer is lost.
+            let mut routes = lock(&reader_routes);
+            routes.exited = true;
+            routes.waiting.clear();
+        });
+        // Nothing ties stderr to a single call, so it only goes to the log.
+        tauri::async_runtime::spawn(async move {
+            let mut reader = BufReader::new(&mut stderr);
+            loop {
+                match Capture::new("AI backend stderr", limit)
+                    .next_line(&mut reader)
+                    .await
+                {
+                    Ok(Some(line)) => {
+                        tracing::warn!("AI backend: {}", String::from_utf8_lossy(&line).trim_end())
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::warn!("{}; discarding the rest", e);
+                        let _ = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await;
+                        break;
+                    }
+                }
+            }
+        });
+
+        tracing::info!(program = %self.config.script_path, "started persistent AI backend");
+        Ok(Process {
+            stdin,
+            routes,
+            _child: child,
+        })
+    }
+}
+
+#[async_trait]
+impl ChatBackend for PersistentNodeBackend {
+    fn ready(&self) -> Result<(), String> {
+        self.fallback.ready()
+    }
+
+    fn supports_attachments(&self, chatbot_id: &str) -> bool {
+        self.fallback.supports_attachments(chatbot_id)
+    }
+
+    /// What a fallback spawn would run; the persistent process gets the same
+    /// request as JSON on stdin instead.
+    fn describe(&self, call: &BackendCall<'_>) -> Result<Vec<DryRunCommand>, BackendError> {
+        self.fallback.describe(call)
+    }
+
+    async fn dispatch(&self, call: &BackendCall<'_>) -> Result<Vec<ChatBotResponse>, BackendError> {
+        let id = Uuid::new_v4().to_string();
+        let request = ServeRequest {
+            id: &id,
+            prompt: call.prompt,
+            chatbots: call.chatbots,
+            context: call.context,
+            options: call.options,
+            attachments: call.attachments,
+            format: call.response_format,
+        };
+        let mut line = serde_json::to_string(&request)
+            .map_err(|e| BackendError::Failed(format!("Failed to encode request: {}", e)))?;
+        line.push('\n');
+
+        let (routes, answer) = match self.send(&id, &line, call.max_output_bytes).await {
+            Ok(sent) => sent,
+            Err(e) => {
+                tracing::warn!(
+                    "Persistent AI backend unavailable, spawning one for this call: {}",
+                    e
+                );
+                return self.fallback.dispatch(call).await;
+            }
+        };
+        // There is no child to kill, so a timeout or cancel is sent as a line.
+        let timeout = async {
+            match call.timeout_ms {
+                Some(ms) => {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    BackendError::Timeout(ms)
+                }
+                None => std::future::pending().await,
+            }
+        };
+        let cancelled = async {
+            call.children.cancelled(call.request_id).await;
+            BackendError::Cancelled
+        };
+        let stopped = select(Box::pin(timeout), Box::pin(cancelled));
+        let answer = match select(answer, stopped).await {
+            Either::Left((answer, _)) => answer,
+            Either::Right((stopped, _)) => {
+                self.abandon(&routes, &id).await;
+                return Err(stopped.factor_first().0);
+            }
+        };
+        match answer {
+            Ok(Ok(results)) => Ok(results
+                .into_iter()
+                .filter(|r| call.chatbots.contains(&r.id))
+                .collect()),
+            Ok(Err(message)) => Err(BackendError::Failed(message)),
+            Err(_) => {
+                tracing::warn!("Persistent AI backend exited mid-call, spawning one for this call");
+                self.fallback.dispatch(call).await
+            }
+        }
+    }
+
+    /// Kills the persistent process; calls still waiting on it fall back to
+    /// spawning a process each.
+    async fn restart(&self) -> bool {
+        self.process
+            .lock()
+            .await
+            .take()
+            .is_some_and(|p| !lock(&p.routes).exited)
+    }
+}
+
+/// Hands one stdout line to the call it answers. Lines that aren't replies,
+/// such as log output, are skipped.
+fn route(routes: &Mutex<Routes>, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if !line.starts_with('{') {
+        return;
+    }
+    let reply: ServeReply = match serde_json::from_str(line) {
+        Ok(reply) => reply,
+        Err(e) => {
+            tracing::warn!("Ignoring unparsable AI backend reply: {}", e);
+            return;
+        }
+    };
+    let Some(tx) = reply.id.and_then(|id| lock(routes).waiting.remove(&id)) else {
+        return;
+    };
+    let answer = match (reply.response, reply.error) {
+        (Some(response), _) => Ok(response.results),
+        (None, error) => {
+            Err(error.unwrap_or_else(|| "AI backend returned no response".to_string()))
+        }
+    };
+    let _ = tx.send(answer);
+}
+
+fn lock(routes: &Mutex<Routes>) -> MutexGuard<'_, Routes> {
+    routes.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::children::ChildRegistry;
+    use std::path::PathBuf;
+
+    const REQUEST: &str = "request";
+
+    /// Answers each request with its own pid, except prompts "hang" (no
+    /// answer) and "die" (exits). Without `--serve` it answers "spawned".
+    /// Every line it reads is appended to `requests.log` next to it.
+    const ECHO_BACKEND: &str = r#"
+log="$(dirname "$0")/requests.log"
+answer() {
+    printf '{%s"response":{"results":[{"id":"a","name":"A","response":"%s","status":"success","error":null,"timestamp":1}]}}\n' "$1" "$2"
+}
+if [ "$1" != "--serve" ]; then
+    answer "" spawned
+    exit 0
+fi
+while IFS= read -r line; do
+    echo "$line" >> "$log"
+    id=$(printf '%s' "$line" | sed 's/^{"id":"\([^"]*\)".*/\1/')
+    case "$line" in
+        *'"cancel":true'* | *'"prompt":"hang"'*) ;;
+        *'"prompt":"die"'*) exit 1 ;;
+        *) answer "\"id\":\"$id\"," $$ ;;
+    esac
+done
+"#;
+
+    struct Fixture {
+        dir: PathBuf,
+        backend: PersistentNodeBackend,
+        children: ChildRegistry,
+        chatbots: Vec<String>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("persistent-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let script = dir.join("backend.sh");
+            std::fs::write(&script, ECHO_BACKEND).unwrap();
+            let config = BackendConfig::new(
+                "/bin/sh".to_string(),
+                script.to_string_lossy().into_owned(),
+                None,
+                HashMap::new(),
+            );
+            let children = ChildRegistry::default();
+            children.begin(REQUEST, None);
+            Self {
+                dir,
+                backend: PersistentNodeBackend::new(Arc::new(config), &NetworkSettings::default()),
+                children,
+                chatbots: vec!["a".to_string()],
+            }
+        }
+
+        fn call<'a>(&'a self, prompt: &'a str, timeout_ms: Option<u64>) -> BackendCall<'a> {
+            BackendCall {
+                request_id: REQUEST,
+                prompt,
+                system_prompt: None,
+                context: &[],
+                chatbots: &self.chatbots,
+                timeout_ms,
+                max_output_bytes: 1024 * 1024,
+                options: Default::default(),
+                seed: None,
+                response_format: None,
+                attachments: &[],
+                on_delta: &|_, _| {},
+                on_diagnostics: &|_| {},
+                children: &self.children,
+            }
+        }
+
+        async fn ask(&self, prompt: &str) -> Result<String, BackendError> {
+            let responses = self.backend.dispatch(&self.call(prompt, None)).await?;
+            Ok(responses[0].response.clone())
+        }
+
+        /// Waits up to a second for the backend to have read a cancel line.
+        async fn saw_cancel(&self) -> bool {
+            for _ in 0..100 {
+                let log =
+                    std::fs::read_to_string(self.dir.join("requests.log")).unwrap_or_default();
+                if log.contains(r#""cancel":true"#) {
+                    return true;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            false
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tauri::async_runtime::block_on(future)
+    }
+
+    #[test]
+    fn calls_share_one_process() {
+        let fixture = Fixture::new();
+        block_on(async {
+            let first = fixture.ask("one").await.unwrap();
+            let second = fixture.ask("two").await.unwrap();
+            assert_ne!(first, "spawned");
+            assert_eq!(first, second);
+        });
+    }
+
+    #[test]
+    fn restart_starts_a_fresh_process() {
+        let fixture = Fixture::new();
+        block_on(async {
+            assert!(!fixture.backend.restart().await);
+            let first = fixture.ask("one").await.unwrap();
+            assert!(fixture.backend.restart().await);
+            assert!(!fixture.backend.restart().await);
+            let second = fixture.ask("two").await.unwrap();
+            assert_ne!(first, second);
+        });
+    }
+
+    #[test]
+    fn cancel_tells_the_process_to_drop_the_call() {
+        let fixture = Fixture::new();
+        block_on(async {
+            let call = fixture.call("hang", None);
+            let cancel = async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                fixture.children.cancel(REQUEST).await;
+            };
+            let (result, _) = futures::future::join(fixture.backend.dispatch(&call), cancel).await;
+            assert!(matches!(result, Err(BackendError::Cancelled)));
+            assert!(fixture.saw_cancel().await);
+        });
+    }
+
+    #[test]
+    fn timeout_tells_the_process_to_drop_the_call() {
+        let fixture = Fixture::new();
+        block_on(async {
+            let result = fixture
+                .backend
+                .dispatch(&fixture.call("hang", Some(100)))
+                .await;
+            assert!(matches!(result, Err(BackendError::Timeout(100))));
+            assert!(fixture.saw_cancel().await);
+            // The process is still usable afterwards.
+            assert_ne!(fixture.ask("one").await.unwrap(), "spawned");
+        });
+    }
+
+    #[test]
+    fn falls_back_to_a_spawn_when_the_process_dies() {
+        let fixture = Fixture::new();
+        block_on(async {
+            assert_eq!(fixture.ask("die").await.unwrap(), "spawned");
+            assert_ne!(fixture.ask("one").await.unwrap(), "spawned");
+        });
+    }
+}
@@ -0,0 +1,73 @@
+use crate::error::AppError;
+use crate::state::AppState;
+use crate::{PromptRequest, PromptResponse};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+/// Emitted as `batch-progress` after each prompt of a batch finishes.
+#[derive(Debug, Clone, Serialize)]
+struct BatchProgress<'a> {
+    batch_id: &'a str,
+    /// Zero-based position of the prompt that just finished.
+    index: usize,
+    total: usize,
+    request_id: Option<&'a str>,
+    /// Set when the prompt failed outright or no bot answered it.
+    error: Option<&'a str>,
+}
+
+/// Runs `prompts` one after another against the same bots, so rate limits
+/// see a steady trickle instead of a burst. With `stop_on_error` the first
+/// failing prompt ends the run; the results gathered so far are still returned.
+pub async fn run_batch(
+    app: &AppHandle,
+    prompts: Vec<String>,
+    chatbots: Vec<String>,
+    stop_on_error: bool,
+) -> Result<Vec<PromptResponse>, AppError> {
+    if prompts.is_empty() {
+        return Err(AppError::Validation(
+            "A batch needs at least one prompt".to_string(),
+        ));
+    }
+
+    let batch_id = Uuid::new_v4().to_string();
+    let total = prompts.len();
+    let mut results = Vec::with_capacity(total);
+
+    for (index, prompt) in prompts.into_iter().enumerate() {
+        let request = PromptRequest {
+            prompt,
+            chatbots: chatbots.clone(),
+            ..Default::default()
+        };
+
+        // Through the queue, so a batch takes turns with prompts sent meanwhile.
+        let queue = &app.state::<AppState>().queue;
+        let (response, error) = match queue.submit(request).await {
+            Ok(response) if response.results.iter().any(|r| r.status == "success") => {
+                (Some(response), None)
+            }
+            Ok(response) => (Some(response), Some("No chatbot answered".to_string())),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let progress = BatchProgress {
+            batch_id: &batch_id,
+            index,
+            total,
+            request_id: response.as_ref().map(|r| r.request_id.as_str()),
+            error: error.as_deref(),
+        };
+        let _ = app.emit("batch-progress", &progress);
+
+        results.extend(response);
+        if error.is_some() && stop_on_error {
+            tracing::info!(batch_id = %batch_id, index, "batch stopped on error");
+            break;
+        }
+    }
+
+    Ok(results)
+}
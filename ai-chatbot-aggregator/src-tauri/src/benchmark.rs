@@ -0,0 +1,66 @@
+use crate::dispatch::{self, DispatchContext};
+use crate::error::AppError;
+use crate::PromptRequest;
+use serde::Serialize;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// Most runs one benchmark may make; each one is a real call to the bot.
+pub const MAX_ITERATIONS: u32 = 100;
+
+/// Spawn-to-answer latency over a benchmark's runs, in milliseconds. Failed
+/// runs are counted but left out of the distribution.
+#[derive(Debug, Default, Serialize)]
+pub struct BenchmarkResult {
+    chatbot_id: String,
+    iterations: u32,
+    failures: u32,
+    /// The first run, which pays for a cold start: nothing cached by the OS
+    /// yet. Unset if that run failed.
+    first_ms: Option<u64>,
+    min_ms: Option<u64>,
+    max_ms: Option<u64>,
+    mean_ms: Option<f64>,
+    p95_ms: Option<u64>,
+}
+
+/// Sends `request` to `chatbot_id` `iterations` times, one after another so
+/// runs don't compete for the machine.
+pub async fn run(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    chatbot_id: &str,
+    iterations: u32,
+) -> Result<BenchmarkResult, AppError> {
+    let mut latencies = Vec::with_capacity(iterations as usize);
+    let mut result = BenchmarkResult {
+        chatbot_id: chatbot_id.to_string(),
+        iterations,
+        ..BenchmarkResult::default()
+    };
+    for i in 0..iterations {
+        let request_id = Uuid::new_v4().to_string();
+        let started = Instant::now();
+        let response = dispatch::dispatch_one(ctx, request, &request_id, chatbot_id).await?;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        if response.status == "success" {
+            if i == 0 {
+                result.first_ms = Some(elapsed_ms);
+            }
+            latencies.push(elapsed_ms);
+        } else {
+            result.failures += 1;
+        }
+    }
+
+    latencies.sort_unstable();
+    result.min_ms = latencies.first().copied();
+    result.max_ms = latencies.last().copied();
+    if !latencies.is_empty() {
+        result.mean_ms = Some(latencies.iter().sum::<u64>() as f64 / latencies.len() as f64);
+        // Nearest rank: the smallest latency at least 95% of runs were within.
+        let rank = (latencies.len() * 95).div_ceil(100);
+        result.p95_ms = Some(latencies[rank - 1]);
+    }
+    Ok(result)
+}
@@ -0,0 +1,241 @@
+use crate::ChatBotResponse;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// Calls are answered with "circuit_open" until the cooldown is over.
+    Open,
+    /// The cooldown is over; the next call is let through as a probe.
+    HalfOpen,
+}
+
+#[derive(Default)]
+struct Circuit {
+    /// Consecutive failures; reset by any answer.
+    failures: u32,
+    opened: Option<Instant>,
+    /// When the call probing a half-open circuit was let through.
+    probe: Option<Instant>,
+}
+
+/// Where the breaker reads the time from; tests use one they move by hand.
+type Clock = Box<dyn Fn() -> Instant + Send + Sync>;
+
+struct Config {
+    threshold: u32,
+    cooldown: Duration,
+}
+
+/// Per-chatbot circuit breakers: after `threshold` consecutive failures a bot
+/// is skipped for `cooldown`, then one call decides whether it is back. A
+/// threshold of 0 turns breaking off.
+pub struct CircuitBreaker {
+    config: Mutex<Config>,
+    circuits: Mutex<HashMap<String, Circuit>>,
+    clock: Clock,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self::with_clock(threshold, cooldown, Box::new(Instant::now))
+    }
+
+    fn with_clock(threshold: u32, cooldown: Duration, clock: Clock) -> Self {
+        Self {
+            config: Mutex::new(Config {
+                threshold,
+                cooldown,
+            }),
+            circuits: Mutex::new(HashMap::new()),
+            clock,
+        }
+    }
+
+    /// Applies to failures from now on; open circuits keep their cooldown.
+    pub fn configure(&self, threshold: u32, cooldown: Duration) {
+        *self.config.lock().unwrap_or_else(|e| e.into_inner()) = Config {
+            threshold,
+            cooldown,
+        };
+    }
+
+    /// Whether `chatbot_id` may be called now. Refused calls get how long
+    /// until the next probe; letting a call through a half-open circuit makes
+    /// it the probe, and other calls wait for its outcome.
+    pub fn allow(&self, chatbot_id: &str) -> Result<(), Duration> {
+        let (threshold, cooldown) = self.config();
+        if threshold == 0 {
+            return Ok(());
+        }
+        let now = (self.clock)();
+        let mut circuits = self.lock();
+        let Some(circuit) = circuits.get_mut(chatbot_id) else {
+            return Ok(());
+        };
+        let Some(opened) = circuit.opened else {
+            return Ok(());
+        };
+        let retry_at = opened + cooldown;
+        if now < retry_at {
+            return Err(retry_at - now);
+        }
+        // A probe that never reported back, e.g. because its race was lost,
+        // doesn't hold the circuit forever.
+        match circuit.probe {
+            Some(probe) if now < probe + cooldown => Err(probe + cooldown - now),
+            _ => {
+                circuit.probe = Some(now);
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts the outcome of a call to a backend. Any answer, even one that
+    /// fails validation, closes the circuit; a cancelled call tells nothing.
+    pub fn record(&self, response: &ChatBotResponse) {
+        let (threshold, _) = self.config();
+        let mut circuits = self.lock();
+        match response.status.as_str() {
+            "success" | "invalid_json" | "schema_violation" => {
+                circuits.remove(&response.id);
+            }
+            "cancelled" => {
+                if let Some(circuit) = circuits.get_mut(&response.id) {
+                    circuit.probe = None;
+                }
+            }
+            _ => {
+                let circuit = circuits.entry(response.id.clone()).or_default();
+                circuit.failures += 1;
+                let probe_failed = circuit.probe.take().is_some();
+                if probe_failed || (threshold > 0 && circuit.failures >= threshold) {
+                    circuit.opened = Some((self.clock)());
+                }
+            }
+        }
+    }
+
+    pub fn state(&self, chatbot_id: &str) -> CircuitState {
+        let (threshold, cooldown) = self.config();
+        match self.lock().get(chatbot_id).and_then(|c| c.opened) {
+            _ if threshold == 0 => CircuitState::Closed,
+            None => CircuitState::Closed,
+            Some(opened) if (self.clock)() < opened + cooldown => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Closes `chatbot_id`'s circuit and forgets its failures.
+    pub fn reset(&self, chatbot_id: &str) {
+        self.lock().remove(chatbot_id);
+    }
+
+    fn config(&self) -> (u32, Duration) {
+        let config = self.config.lock().unwrap_or_else(|e| e.into_inner());
+        (config.threshold, config.cooldown)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Circuit>> {
+        self.circuits.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    const COOLDOWN: Duration = Duration::from_secs(30);
+
+    /// A breaker whose clock only moves when `wait_out_cooldown` says so.
+    fn breaker(threshold: u32) -> (CircuitBreaker, Arc<Mutex<Instant>>) {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let clock = Arc::clone(&now);
+        let clock = Box::new(move || *clock.lock().unwrap());
+        (CircuitBreaker::with_clock(threshold, COOLDOWN, clock), now)
+    }
+
+    fn outcome(status: &str) -> ChatBotResponse {
+        ChatBotResponse {
+            id: "a".to_string(),
+            status: status.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn wait_out_cooldown(now: &Mutex<Instant>) {
+        *now.lock().unwrap() += COOLDOWN;
+    }
+
+    #[test]
+    fn opens_after_the_threshold_and_closes_after_a_good_probe() {
+        let (breaker, now) = breaker(2);
+        breaker.record(&outcome("error"));
+        assert_eq!(breaker.state("a"), CircuitState::Closed);
+        assert!(breaker.allow("a").is_ok());
+
+        breaker.record(&outcome("timeout"));
+        assert_eq!(breaker.state("a"), CircuitState::Open);
+        assert_eq!(breaker.allow("a"), Err(COOLDOWN));
+
+        wait_out_cooldown(&now);
+        assert_eq!(breaker.state("a"), CircuitState::HalfOpen);
+        assert!(breaker.allow("a").is_ok());
+        // Only the probe goes through until it reports back.
+        assert!(breaker.allow("a").is_err());
+
+        breaker.record(&outcome("success"));
+        assert_eq!(breaker.state("a"), CircuitState::Closed);
+        assert!(breaker.allow("a").is_ok());
+        assert_eq!(breaker.state("b"), CircuitState::Closed);
+    }
+
+    #[test]
+    fn a_failed_probe_reopens_at_once() {
+        let (breaker, now) = breaker(3);
+        for _ in 0..3 {
+            breaker.record(&outcome("error"));
+        }
+        wait_out_cooldown(&now);
+        assert!(breaker.allow("a").is_ok());
+        breaker.record(&outcome("error"));
+        assert_eq!(breaker.state("a"), CircuitState::Open);
+        assert!(breaker.allow("a").is_err());
+    }
+
+    #[test]
+    fn a_cancelled_probe_lets_the_next_call_probe() {
+        let (breaker, now) = breaker(1);
+        breaker.record(&outcome("error"));
+        wait_out_cooldown(&now);
+        assert!(breaker.allow("a").is_ok());
+        breaker.record(&outcome("cancelled"));
+        assert_eq!(breaker.state("a"), CircuitState::HalfOpen);
+        assert!(breaker.allow("a").is_ok());
+    }
+
+    #[test]
+    fn answers_close_and_zero_disables() {
+        let (breaker, _) = breaker(1);
+        breaker.record(&outcome("error"));
+        breaker.record(&outcome("invalid_json"));
+        assert_eq!(breaker.state("a"), CircuitState::Closed);
+
+        breaker.record(&outcome("error"));
+        breaker.configure(0, COOLDOWN);
+        assert_eq!(breaker.state("a"), CircuitState::Closed);
+        assert!(breaker.allow("a").is_ok());
+
+        breaker.configure(1, COOLDOWN);
+        breaker.reset("a");
+        assert_eq!(breaker.state("a"), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::backend::is_secret_key;
+use crate::error::AppError;
+use crate::settings::Settings;
+use crate::templates::Template;
+use crate::ChatBotConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Bumped whenever a bundle written by this version couldn't be read by an
+/// older one.
+pub const VERSION: u32 = 1;
+
+/// Everything the user configured, in one file for backups and support
+/// tickets. API keys live in the secret store and are never part of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    pub version: u32,
+    pub chatbots: Vec<ChatBotConfig>,
+    pub selection: Vec<String>,
+    pub templates: Vec<Template>,
+    pub settings: Settings,
+}
+
+impl ConfigBundle {
+    /// A bundle of the given state with credential-looking backend variables
+    /// and bot headers left out.
+    pub fn new(
+        mut chatbots: Vec<ChatBotConfig>,
+        selection: Vec<String>,
+        templates: Vec<Template>,
+        mut settings: Settings,
+    ) -> Self {
+        strip_secrets(&mut settings.backend_env);
+        for config in &mut chatbots {
+            if let Some(headers) = &mut config.headers {
+                strip_secrets(headers);
+            }
+        }
+        Self {
+            version: VERSION,
+            chatbots,
+            selection,
+            templates,
+            settings,
+        }
+    }
+
+    /// Reads a bundle, refusing one written in another format version before
+    /// looking at the rest of it.
+    pub fn read(path: &Path) -> Result<Self, AppError> {
+        let value: serde_json::Value = crate::persist::read_json(path)
+            .map_err(AppError::Validation)?
+            .ok_or_else(|| AppError::NotFound(format!("No file at {}", path.display())))?;
+        match value.get("version").and_then(serde_json::Value::as_u64) {
+            Some(version) if version == u64::from(VERSION) => {}
+            Some(version) => {
+                return Err(AppError::Validation(format!(
+                    "Unsupported config bundle version {} (expected {})",
+                    version, VERSION
+                )))
+            }
+            None => {
+                return Err(AppError::Validation(
+                    "Not a config bundle: missing 'version'".to_string(),
+                ))
+            }
+        }
+        serde_json::from_value(value)
+            .map_err(|e| AppError::Validation(format!("Invalid config bundle: {}", e)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), AppError> {
+        crate::persist::write_json(path, self).map_err(AppError::Storage)
+    }
+
+    /// Puts back the secrets `new` left out, taking them from the current
+    /// configuration, so importing a bundle doesn't log the user out.
+    pub fn keep_secrets(&mut self, settings: &Settings, chatbots: &[ChatBotConfig]) {
+        restore_secrets(&mut self.settings.backend_env, &settings.backend_env);
+        for config in &mut self.chatbots {
+            let Some(current) = chatbots
+                .iter()
+                .find(|c| c.id == config.id)
+                .and_then(|c| c.headers.as_ref())
+                .filter(|headers| headers.keys().any(|name| is_secret_key(name)))
+            else {
+                continue;
+            };
+            restore_secrets(config.headers.get_or_insert_with(HashMap::new), current);
+        }
+    }
+}
+
+fn strip_secrets(vars: &mut HashMap<String, String>) {
+    vars.retain(|name, _| !is_secret_key(name));
+}
+
+fn restore_secrets(vars: &mut HashMap<String, String>, current: &HashMap<String, String>) {
+    for (name, value) in current {
+        if is_secret_key(name) && !vars.contains_key(name) {
+            vars.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bot(id: &str, headers: &[(&str, &str)]) -> ChatBotConfig {
+        ChatBotConfig {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            url: "https://example.com".to_string(),
+            is_enabled: true,
+            model: Some("model-1".to_string()),
+            params: Some(serde_json::json!({ "temperature": 0.2 })),
+            order: 0,
+            capabilities: Vec::new(),
+            command_template: None,
+            headers: (!headers.is_empty()).then(|| vars(headers)),
+            disabled_until: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Neither side implements `PartialEq`, so state is compared as JSON.
+    fn json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    fn bundle_path() -> std::path::PathBuf {
+        std::env::temp_dir()
+            .join(format!("bundle-{}", uuid::Uuid::new_v4()))
+            .join("bundle.json")
+    }
+
+    #[test]
+    fn export_then_import_restores_the_same_state() {
+        let settings = Settings {
+            system_prompt: Some("Be brief.".to_string()),
+            backend_env: vars(&[("API_TOKEN", "t0ken"), ("REGION", "eu")]),
+            ..Default::default()
+        };
+        let chatbots = vec![
+            bot("a", &[("Authorization", "Bearer x"), ("X-Team", "docs")]),
+            bot("b", &[]),
+        ];
+        let templates = vec![Template {
+            name: "review".to_string(),
+            body: "Review {code}".to_string(),
+        }];
+        let selection = vec!["b".to_string()];
+
+        let path = bundle_path();
+        ConfigBundle::new(
+            chatbots.clone(),
+            selection.clone(),
+            templates.clone(),
+            settings.clone(),
+        )
+        .write(&path)
+        .unwrap();
+        let mut imported = ConfigBundle::read(&path).unwrap();
+        imported.keep_secrets(&settings, &chatbots);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(json(&imported.chatbots), json(&chatbots));
+        assert_eq!(json(&imported.selection), json(&selection));
+        assert_eq!(json(&imported.templates), json(&templates));
+        assert_eq!(json(&imported.settings), json(&settings));
+    }
+
+    #[test]
+    fn secrets_stay_out_of_the_file() {
+        let settings = Settings {
+            backend_env: vars(&[("OPENAI_API_KEY", "sk-123"), ("REGION", "eu")]),
+            ..Default::default()
+        };
+        let chatbots = vec![bot(
+            "a",
+            &[("Authorization", "Bearer x"), ("X-Team", "docs")],
+        )];
+
+        let path = bundle_path();
+        ConfigBundle::new(chatbots, Vec::new(), Vec::new(), settings)
+            .write(&path)
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert!(!written.contains("sk-123") && !written.contains("Bearer x"));
+        assert!(written.contains("REGION") && written.contains("X-Team"));
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let path = bundle_path();
+        let mut bundle = json(&ConfigBundle::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Settings::default(),
+        ));
+        bundle["version"] = serde_json::json!(VERSION + 1);
+        crate::persist::write_json(&path, &bundle).unwrap();
+        let newer = ConfigBundle::read(&path);
+        bundle.as_object_mut().unwrap().remove("version");
+        crate::persist::write_json(&path, &bundle).unwrap();
+        let unversioned = ConfigBundle::read(&path);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        assert!(
+            matches!(newer, Err(AppError::Validation(message)) if message.contains("Unsupported"))
+        );
+        assert!(
+            matches!(unversioned, Err(AppError::Validation(message)) if message.contains("missing"))
+        );
+    }
+}
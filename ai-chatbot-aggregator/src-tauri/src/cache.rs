@@ -0,0 +1,280 @@
+use crate::conversations::Message;
+use crate::error::AppError;
+use crate::ChatBotResponse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+const CAPACITY: usize = 256;
+
+/// Normalized prompt and system prompt, a hash of the conversation so far,
+/// bot id and model.
+type Key = (String, Option<String>, u64, String, Option<String>);
+
+struct Entry {
+    response: ChatBotResponse,
+    stored: Instant,
+    last_used: u64,
+}
+
+struct CacheState {
+    enabled: bool,
+    ttl: Duration,
+    /// Bumped on every hit or insert; the entry with the lowest `last_used` is evicted first.
+    clock: u64,
+    entries: HashMap<Key, Entry>,
+}
+
+/// Successful answers kept in memory so re-asking the same prompt of the same
+/// bot and model doesn't call the backend again. Least recently used entries
+/// are evicted once `CAPACITY` is reached.
+pub struct ResponseCache {
+    state: Mutex<CacheState>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                enabled: true,
+                ttl: DEFAULT_TTL,
+                clock: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl ResponseCache {
+    /// Returns a copy of the cached answer marked `from_cache`, if a fresh one
+    /// exists. `context` is the bot's view of the conversation, empty outside one.
+    pub fn get(
+        &self,
+        prompt: &str,
+        system_prompt: Option<&str>,
+        context: &[Message],
+        chatbot_id: &str,
+        model: Option<&str>,
+    ) -> Option<ChatBotResponse> {
+        let mut state = self.lock();
+        if !state.enabled {
+            return None;
+        }
+        let key = key(prompt, system_prompt, context, chatbot_id, model);
+        let ttl = state.ttl;
+        if state
+            .entries
+            .get(&key)
+            .is_some_and(|entry| entry.stored.elapsed() > ttl)
+        {
+            state.entries.remove(&key);
+            return None;
+        }
+
+        state.clock += 1;
+        let clock = state.clock;
+        let entry = state.entries.get_mut(&key)?;
+        entry.last_used = clock;
+        let mut response = entry.response.clone();
+        response.from_cache = true;
+        Some(response)
+    }
+
+    /// Stores `response` if it is a success; anything else is never cached.
+    pub fn insert(
+        &self,
+        prompt: &str,
+        system_prompt: Option<&str>,
+        context: &[Message],
+        chatbot_id: &str,
+        model: Option<&str>,
+        response: &ChatBotResponse,
+    ) {
+        if response.status != "success" {
+            return;
+        }
+        let mut state = self.lock();
+        if !state.enabled {
+            return;
+        }
+        let key = key(prompt, system_prompt, context, chatbot_id, model);
+        if !state.entries.contains_key(&key) && state.entries.len() >= CAPACITY {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+
+        state.clock += 1;
+        let entry = Entry {
+            response: response.clone(),
+            stored: Instant::now(),
+            last_used: state.clock,
+        };
+        state.entries.insert(key, entry);
+    }
+
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    /// Disabling also drops everything cached so far.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut state = self.lock();
+        state.enabled = enabled;
+        if !enabled {
+            state.entries.clear();
+        }
+    }
+
+    pub fn set_ttl(&self, seconds: u64) -> Result<(), AppError> {
+        if seconds == 0 {
+            return Err(AppError::Validation(
+                "Cache TTL must be at least 1 second".to_string(),
+            ));
+        }
+        self.lock().ttl = Duration::from_secs(seconds);
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Prompts differing only in surrounding or repeated whitespace share an
+/// entry; the same prompt later in a conversation, or in another one, doesn't.
+fn key(
+    prompt: &str,
+    system_prompt: Option<&str>,
+    context: &[Message],
+    chatbot_id: &str,
+    model: Option<&str>,
+) -> Key {
+    let mut hasher = DefaultHasher::new();
+    for message in context {
+        message.role.hash(&mut hasher);
+        message.content.hash(&mut hasher);
+    }
+    (
+        normalize(prompt),
+        system_prompt.map(normalize),
+        hasher.finish(),
+        chatbot_id.to_string(),
+        model.map(str::to_string),
+    )
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(text: &str) -> ChatBotResponse {
+        ChatBotResponse {
+            id: "a".to_string(),
+            response: text.to_string(),
+            status: "success".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn entries(cache: &ResponseCache) -> usize {
+        cache.lock().entries.len()
+    }
+
+    #[test]
+    fn same_prompt_in_two_conversations_is_cached_twice() {
+        let cache = ResponseCache::default();
+        let first = [Message::system("We're talking about maths.")];
+        let second = [Message::system("We're talking about music.")];
+        cache.insert(
+            "What's a scale?",
+            None,
+            &first,
+            "a",
+            None,
+            &answer("Ratios."),
+        );
+        cache.insert(
+            "What's a scale?",
+            None,
+            &second,
+            "a",
+            None,
+            &answer("Notes."),
+        );
+
+        assert_eq!(entries(&cache), 2);
+        let hit = |context: &[Message]| {
+            cache
+                .get("What's a scale?", None, context, "a", None)
+                .map(|r| r.response)
+        };
+        assert_eq!(hit(&first).as_deref(), Some("Ratios."));
+        assert_eq!(hit(&second).as_deref(), Some("Notes."));
+        assert_eq!(hit(&[]), None);
+    }
+
+    #[test]
+    fn whitespace_differences_share_an_entry() {
+        let cache = ResponseCache::default();
+        cache.insert("  Hello   world ", None, &[], "a", None, &answer("Hi"));
+        let hit = cache.get("Hello world", None, &[], "a", None).unwrap();
+        assert!(hit.from_cache);
+        assert_eq!(hit.response, "Hi");
+        assert!(cache
+            .get("Hello world", None, &[], "a", Some("gpt-4o"))
+            .is_none());
+        assert!(cache.get("Hello world", None, &[], "b", None).is_none());
+        assert!(cache
+            .get("Hello world", Some("Be brief"), &[], "a", None)
+            .is_none());
+    }
+
+    #[test]
+    fn only_successes_are_cached() {
+        let cache = ResponseCache::default();
+        let mut failed = answer("");
+        failed.status = "error".to_string();
+        cache.insert("Hi", None, &[], "a", None, &failed);
+        assert_eq!(entries(&cache), 0);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let cache = ResponseCache::default();
+        for i in 0..CAPACITY {
+            cache.insert(&i.to_string(), None, &[], "a", None, &answer("x"));
+        }
+        // Touch the oldest so the second oldest goes instead.
+        assert!(cache.get("0", None, &[], "a", None).is_some());
+        cache.insert("new", None, &[], "a", None, &answer("x"));
+
+        assert_eq!(entries(&cache), CAPACITY);
+        assert!(cache.get("0", None, &[], "a", None).is_some());
+        assert!(cache.get("1", None, &[], "a", None).is_none());
+    }
+
+    #[test]
+    fn disabling_drops_everything() {
+        let cache = ResponseCache::default();
+        cache.insert("Hi", None, &[], "a", None, &answer("x"));
+        cache.set_enabled(false);
+        assert_eq!(entries(&cache), 0);
+        cache.insert("Hi", None, &[], "a", None, &answer("x"));
+        assert!(cache.get("Hi", None, &[], "a", None).is_none());
+        assert!(cache.set_ttl(0).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::backend::ChatBackend;
+use crate::dispatch::DispatchContext;
+use crate::ChatBotConfig;
+use serde::Serialize;
+use std::sync::{Mutex, MutexGuard};
+
+/// What one bot can do. Features the backend reports come from it, since a
+/// tag can't make a backend send what it doesn't; vision and tools, which no
+/// backend reports, come from the bot's tags alone.
+#[derive(Debug, Clone, Serialize)]
+pub struct BotCapabilities {
+    id: String,
+    name: String,
+    /// The bot's configured `capabilities` tags, as given.
+    tags: Vec<String>,
+    streaming: bool,
+    vision: bool,
+    tools: bool,
+    system_prompt: bool,
+    seed: bool,
+    attachments: bool,
+}
+
+/// What the matrix was built from; it is rebuilt once any of this changes.
+#[derive(PartialEq)]
+struct Key {
+    backend: String,
+    chatbots: Vec<(String, String, Vec<String>, Option<String>)>,
+}
+
+impl Key {
+    fn new(backend: &str, chatbots: &[ChatBotConfig]) -> Self {
+        Self {
+            backend: backend.to_string(),
+            chatbots: chatbots
+                .iter()
+                .map(|c| {
+                    (
+                        c.id.clone(),
+                        c.name.clone(),
+                        c.capabilities.clone(),
+                        c.command_template.clone(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The capability matrix of the current bots, kept for the session.
+#[derive(Default)]
+pub struct CapabilityCache {
+    matrix: Mutex<Option<(Key, Vec<BotCapabilities>)>>,
+}
+
+impl CapabilityCache {
+    /// One entry per bot in `ctx.chatbots`, in list order; `backend` is the
+    /// kind of `ctx.backend`.
+    pub fn matrix(&self, ctx: &DispatchContext<'_>, backend: &str) -> Vec<BotCapabilities> {
+        let key = Key::new(backend, ctx.chatbots);
+        let mut cached = self.lock();
+        match &*cached {
+            Some((built_from, matrix)) if *built_from == key => matrix.clone(),
+            _ => {
+                let matrix: Vec<BotCapabilities> =
+                    ctx.chatbots.iter().map(|c| capabilities(ctx, c)).collect();
+                *cached = Some((key, matrix.clone()));
+                matrix
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<(Key, Vec<BotCapabilities>)>> {
+        self.matrix.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn capabilities(ctx: &DispatchContext<'_>, config: &ChatBotConfig) -> BotCapabilities {
+    let tagged = |name: &str| {
+        config
+            .capabilities
+            .iter()
+            .any(|tag| tag.trim().eq_ignore_ascii_case(name))
+    };
+    // A bot whose command template doesn't parse can't be run at all.
+    let backend = ctx.backend_for(&config.id).ok();
+    let reports = |supports: fn(&dyn ChatBackend, &str) -> bool| {
+        backend
+            .as_deref()
+            .is_some_and(|backend| supports(backend, &config.id))
+    };
+    BotCapabilities {
+        id: config.id.clone(),
+        name: config.name.clone(),
+        tags: config.capabilities.clone(),
+        streaming: reports(|b, id| b.supports_streaming(id)),
+        vision: tagged("vision"),
+        tools: tagged("tools"),
+        system_prompt: reports(|b, id| b.supports_system_prompt(id)),
+        seed: reports(|b, id| b.supports_seed(id)),
+        attachments: reports(|b, id| b.supports_attachments(id)),
+    }
+}
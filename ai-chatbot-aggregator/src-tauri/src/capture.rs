@@ -0,0 +1,120 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt};
+
+/// Reads a child's stdout or stderr while counting it against
+/// `Settings::max_output_bytes`, so a runaway process can't exhaust memory.
+/// Never buffers more than one byte past the limit.
+pub struct Capture {
+    what: &'static str,
+    limit: usize,
+    captured: usize,
+}
+
+impl Capture {
+    /// `what` names the stream in errors, e.g. "AI backend stdout".
+    pub fn new(what: &'static str, limit: usize) -> Self {
+        Self {
+            what,
+            limit,
+            captured: 0,
+        }
+    }
+
+    /// The next line with its line ending, or `None` at the end of the output.
+    pub async fn next_line<R: AsyncBufRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Option<Vec<u8>>, String> {
+        let mut line = Vec::new();
+        let read = reader
+            .take(self.budget())
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", self.what, e))?;
+        self.count(read)?;
+        Ok((read > 0).then_some(line))
+    }
+
+    /// Everything left in `reader`. A read error ends the output early
+    /// rather than failing, as a closed pipe usually means the child exited.
+    pub async fn rest<R: AsyncRead + Unpin>(&mut self, reader: &mut R) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        let read = reader
+            .take(self.budget())
+            .read_to_end(&mut out)
+            .await
+            .unwrap_or(out.len());
+        self.count(read)?;
+        Ok(out)
+    }
+
+    fn budget(&self) -> u64 {
+        (self.limit.saturating_sub(self.captured) as u64).saturating_add(1)
+    }
+
+    fn count(&mut self, read: usize) -> Result<(), String> {
+        self.captured += read;
+        if self.captured > self.limit {
+            return Err(format!(
+                "{} exceeded the limit of {} bytes ({} bytes captured); the process was stopped",
+                self.what, self.limit, self.captured
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tauri::async_runtime::block_on(future)
+    }
+
+    #[test]
+    fn reads_lines_within_the_limit() {
+        let mut reader = &b"one\ntwo\nthree"[..];
+        let mut capture = Capture::new("stdout", 13);
+        assert_eq!(
+            block_on(capture.next_line(&mut reader)).unwrap(),
+            Some(b"one\n".to_vec())
+        );
+        assert_eq!(
+            block_on(capture.next_line(&mut reader)).unwrap(),
+            Some(b"two\n".to_vec())
+        );
+        assert_eq!(
+            block_on(capture.next_line(&mut reader)).unwrap(),
+            Some(b"three".to_vec())
+        );
+        assert_eq!(block_on(capture.next_line(&mut reader)).unwrap(), None);
+    }
+
+    #[test]
+    fn fails_one_byte_past_the_limit() {
+        let mut reader = &b"one\ntwo\nthree\n"[..];
+        let mut capture = Capture::new("AI backend stdout", 6);
+        assert!(block_on(capture.next_line(&mut reader)).is_ok());
+        let error = block_on(capture.next_line(&mut reader)).unwrap_err();
+        assert_eq!(
+            error,
+            "AI backend stdout exceeded the limit of 6 bytes (7 bytes captured); the process was stopped"
+        );
+    }
+
+    #[test]
+    fn rest_stops_reading_at_the_limit() {
+        let output = vec![b'x'; 5000];
+        let mut reader = &output[..];
+        assert_eq!(
+            block_on(Capture::new("stderr", 5000).rest(&mut reader))
+                .unwrap()
+                .len(),
+            5000
+        );
+        let mut reader = &output[..];
+        assert!(block_on(Capture::new("stderr", 999).rest(&mut reader)).is_err());
+        // Only one byte past the limit was taken out of the reader.
+        assert_eq!(reader.len(), 4000);
+    }
+}
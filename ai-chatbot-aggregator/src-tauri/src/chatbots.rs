@@ -0,0 +1,462 @@
+use crate::error::AppError;
+use crate::persist::{read_json, write_json};
+use crate::validation;
+use crate::{now_millis, ChatBotConfig};
+use chrono::Local;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    /// The whole list after the import.
+    chatbots: Vec<ChatBotConfig>,
+    /// Existing bots replaced by an imported one with the same id.
+    overwritten: Vec<String>,
+}
+
+/// The user's chatbot list, persisted as JSON in the app config dir.
+pub struct ChatbotStore {
+    path: PathBuf,
+    chatbots: Mutex<Vec<ChatBotConfig>>,
+}
+
+impl ChatbotStore {
+    /// Loads the persisted list, seeding it with the built-in bots on first launch.
+    pub fn load(path: PathBuf) -> Self {
+        let chatbots = match read_json(&path) {
+            Ok(Some(chatbots)) => chatbots,
+            Ok(None) => {
+                let defaults = default_chatbots();
+                if let Err(e) = write_json(&path, &defaults) {
+                    tracing::warn!("Failed to seed chatbot config: {}", e);
+                }
+                defaults
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable chatbot config: {}", e);
+                default_chatbots()
+            }
+        };
+
+        Self {
+            path,
+            chatbots: Mutex::new(chatbots),
+        }
+    }
+
+    /// All bots by ascending `order`; ties keep the order they were stored in.
+    pub fn list(&self) -> Vec<ChatBotConfig> {
+        self.wake_expired();
+        let mut chatbots = self.lock().clone();
+        chatbots.sort_by_key(|c| c.order);
+        chatbots
+    }
+
+    pub fn get(&self, id: &str) -> Result<ChatBotConfig, AppError> {
+        self.lock()
+            .iter()
+            .find(|c| c.id == id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("Unknown chatbot '{}'", id)))
+    }
+
+    /// New bots go last, whatever `order` they came with.
+    pub fn add(&self, mut config: ChatBotConfig) -> Result<(), AppError> {
+        validation::validate_chatbot(&config)?;
+
+        self.modify(|chatbots| {
+            if chatbots.iter().any(|c| c.id == config.id) {
+                return Err(AppError::Validation(format!(
+                    "A chatbot with id '{}' already exists",
+                    config.id
+                )));
+            }
+            config.order = chatbots.iter().map(|c| c.order + 1).max().unwrap_or(0);
+            chatbots.push(config);
+            Ok(())
+        })
+    }
+
+    /// Copies `id`'s config under `new_id` and `new_name`, placed last.
+    pub fn duplicate(
+        &self,
+        id: &str,
+        new_id: &str,
+        new_name: &str,
+    ) -> Result<ChatBotConfig, AppError> {
+        let mut copy = self.get(id)?;
+        copy.id = new_id.to_string();
+        copy.name = new_name.to_string();
+        self.add(copy)?;
+        self.get(new_id)
+    }
+
+    /// Replaces `config.id`'s config, except for its place in the list and
+    /// its snooze, which a client that leaves them out would otherwise reset;
+    /// `reorder` and `snooze` change those.
+    pub fn update(&self, config: ChatBotConfig) -> Result<(), AppError> {
+        validation::validate_chatbot(&config)?;
+        self.modify(|chatbots| {
+            let existing = chatbots
+                .iter_mut()
+                .find(|c| c.id == config.id)
+                .ok_or_else(|| AppError::NotFound(format!("Unknown chatbot '{}'", config.id)))?;
+            *existing = ChatBotConfig {
+                order: existing.order,
+                disabled_until: existing.disabled_until,
+                ..config
+            };
+            Ok(())
+        })
+    }
+
+    pub fn set_enabled(&self, id: &str, enabled: bool) -> Result<(), AppError> {
+        self.modify(|chatbots| {
+            let config = chatbots
+                .iter_mut()
+                .find(|c| c.id == id)
+                .ok_or_else(|| AppError::NotFound(format!("Unknown chatbot '{}'", id)))?;
+            config.is_enabled = enabled;
+            Ok(())
+        })
+    }
+
+    /// Skips `id` in prompts until `until` (milliseconds since the Unix
+    /// epoch); `None` wakes it straight away.
+    pub fn snooze(&self, id: &str, until: Option<u64>) -> Result<(), AppError> {
+        self.modify(|chatbots| {
+            let config = chatbots
+                .iter_mut()
+                .find(|c| c.id == id)
+                .ok_or_else(|| AppError::NotFound(format!("Unknown chatbot '{}'", id)))?;
+            config.disabled_until = until;
+            Ok(())
+        })
+    }
+
+    /// Puts `ordered_ids` first, in that order. Bots not listed follow in
+    /// their current relative order.
+    pub fn reorder(&self, ordered_ids: &[String]) -> Result<(), AppError> {
+        self.modify(|chatbots| {
+            if let Some(unknown) = ordered_ids
+                .iter()
+                .find(|id| !chatbots.iter().any(|c| &c.id == *id))
+            {
+                return Err(AppError::NotFound(format!("Unknown chatbot '{}'", unknown)));
+            }
+            chatbots.sort_by_key(|c| {
+                (
+                    ordered_ids
+                        .iter()
+                        .position(|id| *id == c.id)
+                        .unwrap_or(usize::MAX),
+                    c.order,
+                )
+            });
+            for (order, config) in chatbots.iter_mut().enumerate() {
+                config.order = order as u32;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), AppError> {
+        self.modify(|chatbots| {
+            let index = chatbots
+                .iter()
+                .position(|c| c.id == id)
+                .ok_or_else(|| AppError::NotFound(format!("Unknown chatbot '{}'", id)))?;
+            chatbots.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Replaces the list with the built-in bots. The current list is saved
+    /// first, next to the config as `<name>.<timestamp>.json`, so nothing is
+    /// lost; resets within the same millisecond get `-2`, `-3`, ... appended.
+    pub fn reset_to_defaults(&self) -> Result<Vec<ChatBotConfig>, AppError> {
+        let mut backup = PathBuf::new();
+        self.modify(|chatbots| {
+            // Picked under the store's lock, so concurrent resets can't pick the same name.
+            backup = self.backup_path();
+            write_json(&backup, chatbots).map_err(AppError::Storage)?;
+            *chatbots = default_chatbots();
+            Ok(())
+        })?;
+        tracing::info!(backup = %backup.display(), "reset chatbots to defaults");
+        Ok(self.list())
+    }
+
+    /// The first `<name>.<timestamp>[-n].json` next to the config that isn't taken.
+    fn backup_path(&self) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "chatbots".to_string());
+        let name = format!("{}.{}", stem, Local::now().format("%Y%m%d-%H%M%S%.3f"));
+        let mut backup = self.path.with_file_name(format!("{}.json", name));
+        let mut n = 1;
+        while backup.exists() {
+            n += 1;
+            backup = self.path.with_file_name(format!("{}-{}.json", name, n));
+        }
+        backup
+    }
+
+    /// Replaces the list with `imported`, or with `merge` adds them to it,
+    /// imported configs winning on id clashes. `imported` must already be
+    /// validated; the store changes all at once or not at all.
+    pub fn import(
+        &self,
+        imported: Vec<ChatBotConfig>,
+        merge: bool,
+    ) -> Result<ImportSummary, AppError> {
+        let mut overwritten = Vec::new();
+        self.modify(|chatbots| {
+            if !merge {
+                *chatbots = imported;
+                return Ok(());
+            }
+            for config in imported {
+                match chatbots.iter_mut().find(|c| c.id == config.id) {
+                    Some(existing) => {
+                        overwritten.push(config.id.clone());
+                        // Keep its place in the list.
+                        *existing = ChatBotConfig {
+                            order: existing.order,
+                            ..config
+                        };
+                    }
+                    None => {
+                        let order = chatbots.iter().map(|c| c.order + 1).max().unwrap_or(0);
+                        chatbots.push(ChatBotConfig { order, ..config });
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        Ok(ImportSummary {
+            chatbots: self.list(),
+            overwritten,
+        })
+    }
+
+    /// Applies `f` to a copy of the list and only keeps the result once it has
+    /// been written to disk, so a failed save never leaves memory and file out of sync.
+    fn modify(
+        &self,
+        f: impl FnOnce(&mut Vec<ChatBotConfig>) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        let mut chatbots = self.lock();
+        let mut updated = chatbots.clone();
+        f(&mut updated)?;
+        write_json(&self.path, &updated).map_err(AppError::Storage)?;
+        *chatbots = updated;
+        Ok(())
+    }
+
+    /// Drops snoozes that have run out. Dispatch compares against the clock
+    /// anyway, so a failed save only leaves a stale timestamp on disk.
+    fn wake_expired(&self) {
+        let now = now_millis();
+        let expired = |c: &ChatBotConfig| c.disabled_until.is_some_and(|until| until <= now);
+        if !self.lock().iter().any(expired) {
+            return;
+        }
+        let result = self.modify(|chatbots| {
+            for config in chatbots.iter_mut().filter(|c| expired(c)) {
+                config.disabled_until = None;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            tracing::warn!("Failed to clear expired snoozes: {}", e);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<ChatBotConfig>> {
+        self.chatbots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Ids of enabled bots, in list order, that have every capability in
+/// `required` (compared case-insensitively).
+pub fn with_capabilities(chatbots: &[ChatBotConfig], required: &[String]) -> Vec<String> {
+    chatbots
+        .iter()
+        .filter(|c| c.is_enabled)
+        .filter(|c| {
+            required.iter().all(|r| {
+                c.capabilities
+                    .iter()
+                    .any(|have| have.trim().eq_ignore_ascii_case(r.trim()))
+            })
+        })
+        .map(|c| c.id.clone())
+        .collect()
+}
+
+/// When `id`'s snooze ends, if it is snoozed at `now`.
+pub fn snoozed_until(chatbots: &[ChatBotConfig], id: &str, now: u64) -> Option<u64> {
+    chatbots
+        .iter()
+        .find(|c| c.id == id)
+        .and_then(|c| c.disabled_until)
+        .filter(|until| *until > now)
+}
+
+fn default_chatbots() -> Vec<ChatBotConfig> {
+    vec![
+        ChatBotConfig {
+            id: "chatgpt".to_string(),
+            name: "ChatGPT".to_string(),
+            url: "https://chat.openai.com".to_string(),
+            is_enabled: true,
+            model: None,
+            params: None,
+            order: 0,
+            capabilities: Vec::new(),
+            command_template: None,
+            headers: None,
+            disabled_until: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+        },
+        ChatBotConfig {
+            id: "claude".to_string(),
+            name: "Claude".to_string(),
+            url: "https://claude.ai".to_string(),
+            is_enabled: true,
+            model: None,
+            params: None,
+            order: 1,
+            capabilities: Vec::new(),
+            command_template: None,
+            headers: None,
+            disabled_until: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+        },
+        ChatBotConfig {
+            id: "gemini".to_string(),
+            name: "Gemini".to_string(),
+            url: "https://gemini.google.com".to_string(),
+            is_enabled: true,
+            model: None,
+            params: None,
+            order: 2,
+            capabilities: Vec::new(),
+            command_template: None,
+            headers: None,
+            disabled_until: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+        },
+        ChatBotConfig {
+            id: "perplexity".to_string(),
+            name: "Perplexity".to_string(),
+            url: "https://www.perplexity.ai".to_string(),
+            is_enabled: true,
+            model: None,
+            params: None,
+            order: 3,
+            capabilities: Vec::new(),
+            command_template: None,
+            headers: None,
+            disabled_until: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store in a fresh directory of its own, seeded with the defaults.
+    fn store() -> (ChatbotStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("chatbots-{}", uuid::Uuid::new_v4()));
+        (ChatbotStore::load(dir.join("chatbots.json")), dir)
+    }
+
+    fn ids(chatbots: &[ChatBotConfig]) -> Vec<&str> {
+        chatbots.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    #[test]
+    fn reset_restores_the_built_in_bots() {
+        let (store, dir) = store();
+        store.remove("claude").unwrap();
+        store.set_enabled("gemini", false).unwrap();
+        store.reorder(&["perplexity".to_string()]).unwrap();
+
+        let reset = store.reset_to_defaults().unwrap();
+        assert_eq!(ids(&reset), ["chatgpt", "claude", "gemini", "perplexity"]);
+        let defaults = default_chatbots();
+        for (config, default) in reset.iter().zip(&defaults) {
+            assert_eq!(config.name, default.name);
+            assert_eq!(config.url, default.url);
+            assert_eq!(config.order, default.order);
+            assert!(config.is_enabled);
+        }
+        assert_eq!(ids(&store.list()), ids(&reset));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn update_keeps_order_and_snooze() {
+        let (store, dir) = store();
+        store.reorder(&["gemini".to_string()]).unwrap();
+        store.snooze("gemini", Some(u64::MAX)).unwrap();
+        let mut config = store.get("gemini").unwrap();
+        config.name = "Gemini Pro".to_string();
+        config.disabled_until = None;
+        store.update(ChatBotConfig { order: 7, ..config }).unwrap();
+
+        let updated = store.get("gemini").unwrap();
+        assert_eq!(updated.name, "Gemini Pro");
+        assert_eq!(updated.order, 0);
+        assert_eq!(updated.disabled_until, Some(u64::MAX));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reset_backs_up_the_old_list() {
+        let (store, dir) = store();
+        store.remove("claude").unwrap();
+        store.reset_to_defaults().unwrap();
+
+        let backups: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().is_some_and(|n| n != "chatbots.json"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        let saved: Vec<ChatBotConfig> = read_json(&backups[0]).unwrap().unwrap();
+        assert_eq!(ids(&saved), ["chatgpt", "gemini", "perplexity"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quick_resets_keep_every_backup() {
+        let (store, dir) = store();
+        store.remove("claude").unwrap();
+        for _ in 0..3 {
+            store.reset_to_defaults().unwrap();
+        }
+
+        let mut backups: Vec<Vec<ChatBotConfig>> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().is_some_and(|n| n != "chatbots.json"))
+            .map(|path| read_json(&path).unwrap().unwrap())
+            .collect();
+        assert_eq!(backups.len(), 3);
+        backups.sort_by_key(Vec::len);
+        assert_eq!(ids(&backups[0]), ["chatgpt", "gemini", "perplexity"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
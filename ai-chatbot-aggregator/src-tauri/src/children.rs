@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tokio::process::Child;
+use tokio::sync::watch;
+
+#[derive(Default)]
+struct RequestChildren {
+    /// Flips to `true` once; backends without a child wait on it.
+    cancelled: watch::Sender<bool>,
+    /// Label of the window the request was sent for, if it named one.
+    window: Option<String>,
+    children: HashMap<String, Child>,
+}
+
+impl RequestChildren {
+    fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Returns whether the request wasn't cancelled already.
+    fn mark_cancelled(&self) -> bool {
+        !self.cancelled.send_replace(true)
+    }
+}
+
+/// Backend processes that are still running, grouped by the request that
+/// spawned them and keyed by chatbot id, so they can be killed from outside
+/// the dispatch loop.
+#[derive(Default)]
+pub struct ChildRegistry {
+    requests: Mutex<HashMap<String, RequestChildren>>,
+    /// Set on app exit; from then on every new child is handed straight back.
+    closed: AtomicBool,
+}
+
+impl ChildRegistry {
+    /// Marks a request as in flight, on behalf of `window` if it was sent for
+    /// one. Must be paired with [`ChildRegistry::finish`].
+    pub fn begin(&self, request_id: &str, window: Option<&str>) {
+        let window = window.map(str::trim).filter(|label| !label.is_empty());
+        self.lock()
+            .entry(request_id.to_string())
+            .or_default()
+            .window = window.map(String::from);
+    }
+
+    pub fn finish(&self, request_id: &str) {
+        self.lock().remove(request_id);
+    }
+
+    pub fn is_cancelled(&self, request_id: &str) -> bool {
+        self.lock()
+            .get(request_id)
+            .is_some_and(RequestChildren::is_cancelled)
+    }
+
+    /// Resolves once `request_id` is cancelled, for backends that have no
+    /// child of their own to kill. Never resolves for requests that aren't
+    /// in flight or finish without being cancelled.
+    pub async fn cancelled(&self, request_id: &str) {
+        let receiver = self
+            .lock()
+            .get(request_id)
+            .map(|request| request.cancelled.subscribe());
+        if let Some(mut receiver) = receiver {
+            if receiver.wait_for(|cancelled| *cancelled).await.is_ok() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+
+    /// Tracks a freshly spawned child. If the request was cancelled in the
+    /// meantime the child is handed back so the caller can kill it.
+    pub fn insert(&self, request_id: &str, chatbot_id: &str, child: Child) -> Option<Child> {
+        let mut requests = self.lock();
+        let request = requests.entry(request_id.to_string()).or_default();
+        if request.is_cancelled() || self.closed.load(Ordering::SeqCst) {
+            return Some(child);
+        }
+        request.children.insert(chatbot_id.to_string(), child);
+        None
+    }
+
+    /// Takes a child back out once its output has been consumed. Returns
+    /// `None` if it was killed in the meantime.
+    pub fn take(&self, request_id: &str, chatbot_id: &str) -> Option<Child> {
+        self.lock()
+            .get_mut(request_id)
+            .and_then(|request| request.children.remove(chatbot_id))
+    }
+
+    /// Kills and reaps a single bot's child, e.g. when it runs past its timeout.
+    pub async fn kill(&self, request_id: &str, chatbot_id: &str) {
+        if let Some(mut child) = self.take(request_id, chatbot_id) {
+            let _ = child.kill().await;
+        }
+    }
+
+    /// Cancels a request: running children are killed and reaped, and bots that
+    /// haven't started yet will not be spawned. Returns `false` for unknown ids.
+    pub async fn cancel(&self, request_id: &str) -> bool {
+        let children = {
+            let mut requests = self.lock();
+            let Some(request) = requests.get_mut(request_id) else {
+                return false;
+            };
+            request.mark_cancelled();
+            std::mem::take(&mut request.children)
+        };
+
+        for (_, mut child) in children {
+            let _ = child.kill().await;
+        }
+        true
+    }
+
+    /// Cancels every request in flight, as [`ChildRegistry::cancel`] does for
+    /// one. Returns how many requests were newly cancelled.
+    pub async fn cancel_all(&self) -> usize {
+        self.cancel_where(|_| true).await
+    }
+
+    /// Cancels the requests sent for the window labelled `label`.
+    pub async fn cancel_window(&self, label: &str) -> usize {
+        self.cancel_where(|request| request.window.as_deref() == Some(label))
+            .await
+    }
+
+    /// What closing the window labelled `label` cancels: its own requests,
+    /// or everything when it is the last window and nothing is left to show
+    /// the answers. Returns how many requests were newly cancelled.
+    pub async fn window_closed(&self, label: &str, last_window: bool) -> usize {
+        if last_window {
+            self.cancel_all().await
+        } else {
+            self.cancel_window(label).await
+        }
+    }
+
+    async fn cancel_where(&self, selected: impl Fn(&RequestChildren) -> bool) -> usize {
+        let (count, children) = {
+            let mut requests = self.lock();
+            let mut count = 0;
+            let mut children = Vec::new();
+            for request in requests.values_mut().filter(|request| selected(request)) {
+                if request.mark_cancelled() {
+                    count += 1;
+                }
+                children.extend(request.children.drain().map(|(_, child)| child));
+            }
+            (count, children)
+        };
+
+        for mut child in children {
+            let _ = child.kill().await;
+        }
+        count
+    }
+
+    /// Kills and reaps every running child but cancels nothing, so bots that
+    /// haven't started yet still run with fresh processes. Returns how many
+    /// were killed.
+    pub async fn kill_running(&self) -> usize {
+        let children: Vec<Child> = self
+            .lock()
+            .values_mut()
+            .flat_map(|request| request.children.drain().map(|(_, child)| child))
+            .collect();
+
+        let count = children.len();
+        for mut child in children {
+            let _ = child.kill().await;
+        }
+        count
+    }
+
+    /// Kills every tracked child without waiting for it to exit. Used on app
+    /// shutdown, where there is no runtime left to reap them on.
+    pub fn kill_all(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let mut requests = self.lock();
+        for request in requests.values_mut() {
+            request.mark_cancelled();
+            for (_, mut child) in request.children.drain() {
+                let _ = child.start_kill();
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, RequestChildren>> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::time::Duration;
+    use tokio::process::Command;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tauri::async_runtime::block_on(future)
+    }
+
+    fn sleeper() -> Child {
+        Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    /// Whether a process with this id is still running; a killed child
+    /// nobody has reaped yet is a zombie, which doesn't count.
+    fn alive(pid: u32) -> bool {
+        let output = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", &pid.to_string()])
+            .output()
+            .unwrap();
+        output.status.success() && !output.stdout.trim_ascii().starts_with(b"Z")
+    }
+
+    #[test]
+    fn cancel_kills_running_children() {
+        block_on(async {
+            let children = ChildRegistry::default();
+            children.begin("r", None);
+            let child = sleeper();
+            let pid = child.id().unwrap();
+            assert!(children.insert("r", "a", child).is_none());
+
+            assert!(children.cancel("r").await);
+            assert!(!alive(pid));
+            assert!(children.is_cancelled("r"));
+            assert!(children.take("r", "a").is_none());
+        });
+    }
+
+    #[test]
+    fn children_spawned_after_a_cancel_are_handed_back() {
+        block_on(async {
+            let children = ChildRegistry::default();
+            children.begin("r", None);
+            children.cancel("r").await;
+            let mut child = children.insert("r", "b", sleeper()).expect("handed back");
+            child.kill().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn cancel_all_reaches_every_request() {
+        block_on(async {
+            let children = ChildRegistry::default();
+            children.begin("r1", None);
+            children.begin("r2", None);
+            let (first, second) = (sleeper(), sleeper());
+            let pids = [first.id().unwrap(), second.id().unwrap()];
+            children.insert("r1", "a", first);
+            children.insert("r2", "a", second);
+
+            assert_eq!(children.cancel_all().await, 2);
+            assert!(pids.iter().all(|pid| !alive(*pid)));
+            assert_eq!(children.cancel_all().await, 0);
+        });
+    }
+
+    #[test]
+    fn closing_a_window_cancels_only_its_requests() {
+        block_on(async {
+            let children = ChildRegistry::default();
+            children.begin("compare", Some(" compare "));
+            children.begin("main", Some("main"));
+            children.begin("anywhere", None);
+            let mut pids = HashMap::new();
+            for request in ["compare", "main", "anywhere"] {
+                let child = sleeper();
+                pids.insert(request, child.id().unwrap());
+                children.insert(request, "a", child);
+            }
+
+            assert_eq!(children.window_closed("compare", false).await, 1);
+            assert!(!alive(pids["compare"]));
+            assert!(children.is_cancelled("compare"));
+            assert!(alive(pids["main"]) && alive(pids["anywhere"]));
+            assert!(!children.is_cancelled("main"));
+
+            // The last window takes everything with it.
+            assert_eq!(children.window_closed("main", true).await, 2);
+            assert!(!alive(pids["main"]) && !alive(pids["anywhere"]));
+        });
+    }
+
+    #[test]
+    fn closing_on_exit_kills_and_refuses_new_children() {
+        block_on(async {
+            let children = ChildRegistry::default();
+            children.begin("r", None);
+            let child = sleeper();
+            let pid = child.id().unwrap();
+            children.insert("r", "a", child);
+
+            children.kill_all();
+            assert!(children.is_cancelled("r"));
+            // The kill is only started, so give it a moment to land.
+            for _ in 0..100 {
+                if !alive(pid) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(!alive(pid));
+            let mut late = children
+                .insert("other", "a", sleeper())
+                .expect("handed back");
+            late.kill().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn cancelled_resolves_only_on_cancel() {
+        block_on(async {
+            let children = ChildRegistry::default();
+            children.begin("r", None);
+            let waiting = tokio::time::timeout(Duration::from_millis(50), children.cancelled("r"));
+            assert!(waiting.await.is_err());
+
+            // Resolves once the cancel lands, or the test hangs.
+            futures::future::join(children.cancelled("r"), children.cancel("r")).await;
+        });
+    }
+}
@@ -0,0 +1,116 @@
+use crate::error::AppError;
+use crate::ChatBotResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// One turn of a conversation as forwarded to a backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+    /// Which bot gave an assistant answer; each bot only sees its own answers.
+    #[serde(skip)]
+    chatbot_id: Option<String>,
+}
+
+impl Message {
+    /// A standing instruction, sent ahead of every other turn.
+    pub fn system(content: &str) -> Self {
+        Self {
+            role: Role::System,
+            content: content.to_string(),
+            chatbot_id: None,
+        }
+    }
+}
+
+/// In-memory message history per conversation. Conversations are not persisted.
+#[derive(Default)]
+pub struct ConversationStore {
+    conversations: Mutex<HashMap<String, Vec<Message>>>,
+}
+
+impl ConversationStore {
+    pub fn create(&self) -> String {
+        let id = Uuid::new_v4().to_string();
+        self.lock().insert(id.clone(), Vec::new());
+        id
+    }
+
+    /// A new conversation that already holds `messages`, seen by every bot.
+    pub fn start(&self, messages: Vec<Message>) -> String {
+        let id = Uuid::new_v4().to_string();
+        self.lock().insert(id.clone(), messages);
+        id
+    }
+
+    pub fn clear(&self, id: &str) -> Result<(), AppError> {
+        self.lock()
+            .remove(id)
+            .map(|_| ())
+            .ok_or_else(|| AppError::NotFound(format!("No conversation with id {}", id)))
+    }
+
+    pub fn require(&self, id: &str) -> Result<(), AppError> {
+        if self.lock().contains_key(id) {
+            Ok(())
+        } else {
+            Err(AppError::NotFound(format!(
+                "No conversation with id {}",
+                id
+            )))
+        }
+    }
+
+    /// Prior turns as `chatbot_id` saw them: every user prompt plus that bot's
+    /// own answers, oldest first.
+    pub fn context(&self, id: &str, chatbot_id: &str) -> Vec<Message> {
+        self.lock()
+            .get(id)
+            .map(|messages| {
+                messages
+                    .iter()
+                    .filter(|m| m.chatbot_id.as_deref().is_none_or(|c| c == chatbot_id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Appends the prompt and every successful answer to the conversation.
+    pub fn record(&self, id: &str, prompt: &str, results: &[ChatBotResponse]) {
+        let mut conversations = self.lock();
+        let Some(messages) = conversations.get_mut(id) else {
+            return;
+        };
+        messages.push(Message {
+            role: Role::User,
+            content: prompt.to_string(),
+            chatbot_id: None,
+        });
+        messages.extend(
+            results
+                .iter()
+                .filter(|r| r.status == "success")
+                .map(|r| Message {
+                    role: Role::Assistant,
+                    content: r.response.clone(),
+                    chatbot_id: Some(r.id.clone()),
+                }),
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Vec<Message>>> {
+        self.conversations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
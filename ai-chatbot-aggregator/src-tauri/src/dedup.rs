@@ -0,0 +1,101 @@
+use crate::ChatBotResponse;
+use std::collections::HashSet;
+
+/// Answers at least this similar are treated as the same answer.
+const DUPLICATE_THRESHOLD: f64 = 0.8;
+
+/// Token-set Jaccard similarity in `0.0..=1.0`, ignoring case and punctuation.
+/// Two empty texts count as identical.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = tokens(a);
+    let b = tokens(b);
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let shared = a.intersection(&b).count();
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
+
+/// Points each successful answer that near-duplicates an earlier one at that
+/// earlier bot via `duplicate_of`. Nothing is removed; the first answer of each
+/// group stays unannotated as its representative.
+pub fn annotate_duplicates(results: &mut [ChatBotResponse]) {
+    let mut representatives: Vec<(String, String)> = Vec::new();
+
+    for result in results.iter_mut().filter(|r| r.status == "success") {
+        let duplicate_of = representatives
+            .iter()
+            .find(|(_, text)| similarity(&result.response, text) >= DUPLICATE_THRESHOLD)
+            .map(|(id, _)| id.clone());
+
+        match duplicate_of {
+            Some(id) => result.duplicate_of = Some(id),
+            None => representatives.push((result.id.clone(), result.response.clone())),
+        }
+    }
+}
+
+/// Whether `a` and `b` have the same words in the same order, ignoring case,
+/// whitespace and punctuation.
+pub fn same_words(a: &str, b: &str) -> bool {
+    words(a).eq(words(b))
+}
+
+fn tokens(text: &str) -> HashSet<String> {
+    words(text).collect()
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(id: &str, status: &str, response: &str) -> ChatBotResponse {
+        ChatBotResponse {
+            id: id.to_string(),
+            status: status.to_string(),
+            response: response.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn similarity_ignores_case_and_punctuation() {
+        assert_eq!(similarity("Hello, world!", "hello world"), 1.0);
+        assert_eq!(similarity("", "  ...  "), 1.0);
+        assert_eq!(similarity("red green", "blue yellow"), 0.0);
+        assert_eq!(similarity("anything", ""), 0.0);
+    }
+
+    #[test]
+    fn similarity_is_jaccard_over_word_sets() {
+        // {a, b, c} vs {b, c, d}: 2 shared of 4.
+        assert_eq!(similarity("a b c", "b c d"), 0.5);
+        assert_eq!(similarity("a b c", "c b a a"), 1.0);
+        assert_eq!(similarity("x y", "y z"), similarity("y z", "x y"));
+    }
+
+    #[test]
+    fn annotates_near_copies_of_earlier_answers() {
+        let mut results = vec![
+            answer("a", "success", "Paris is the capital of France."),
+            answer("b", "success", "paris is the capital of france"),
+            answer("c", "success", "Berlin is the capital of Germany."),
+            answer("d", "error", "Paris is the capital of France."),
+        ];
+        annotate_duplicates(&mut results);
+        let duplicates: Vec<_> = results.iter().map(|r| r.duplicate_of.as_deref()).collect();
+        assert_eq!(duplicates, [None, Some("a"), None, None]);
+    }
+
+    #[test]
+    fn same_words_keeps_order() {
+        assert!(same_words("Yes, it is.", "yes it  is"));
+        assert!(!same_words("it is yes", "yes it is"));
+    }
+}
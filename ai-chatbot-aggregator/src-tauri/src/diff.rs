@@ -0,0 +1,159 @@
+use serde::Serialize;
+
+/// Past this many cells the LCS table is skipped and the differing middle is
+/// reported as one removal followed by one addition.
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DiffKind {
+    Equal,
+    Added,
+    Removed,
+}
+
+/// A run of consecutive lines that are in both texts, only in the second
+/// (`Added`) or only in the first (`Removed`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffHunk {
+    kind: DiffKind,
+    lines: Vec<String>,
+}
+
+/// Line-based diff from `a` to `b` using a longest common subsequence.
+/// Identical texts give a single `Equal` hunk; two empty texts give none.
+pub fn diff_lines(a: &str, b: &str) -> Vec<DiffHunk> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+
+    // The shared head and tail never need the table.
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut hunks = Vec::new();
+    push_lines(&mut hunks, DiffKind::Equal, &a[..prefix]);
+    for (kind, line) in diff_middle(a_mid, b_mid) {
+        push_lines(&mut hunks, kind, &[line]);
+    }
+    push_lines(&mut hunks, DiffKind::Equal, &a[a.len() - suffix..]);
+    hunks
+}
+
+fn diff_middle<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(DiffKind, &'a str)> {
+    if a.len().saturating_mul(b.len()) > MAX_TABLE_CELLS {
+        return a
+            .iter()
+            .map(|l| (DiffKind::Removed, *l))
+            .chain(b.iter().map(|l| (DiffKind::Added, *l)))
+            .collect();
+    }
+
+    // lcs[i][j] is the LCS length of a[i..] and b[j..].
+    let width = b.len() + 1;
+    let mut lcs = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * width + j] = if a[i] == b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::with_capacity(a.len() + b.len());
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            ops.push((DiffKind::Equal, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+            ops.push((DiffKind::Removed, a[i]));
+            i += 1;
+        } else {
+            ops.push((DiffKind::Added, b[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[i..].iter().map(|l| (DiffKind::Removed, *l)));
+    ops.extend(b[j..].iter().map(|l| (DiffKind::Added, *l)));
+    ops
+}
+
+/// Appends `lines` to the last hunk when it has the same kind.
+fn push_lines(hunks: &mut Vec<DiffHunk>, kind: DiffKind, lines: &[&str]) {
+    if lines.is_empty() {
+        return;
+    }
+    let lines = lines.iter().map(|l| l.to_string());
+    match hunks.last_mut() {
+        Some(last) if last.kind == kind => last.lines.extend(lines),
+        _ => hunks.push(DiffHunk {
+            kind,
+            lines: lines.collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(kind: DiffKind, lines: &[&str]) -> DiffHunk {
+        DiffHunk {
+            kind,
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn identical_and_empty_texts() {
+        assert_eq!(
+            diff_lines("a\nb", "a\nb"),
+            [hunk(DiffKind::Equal, &["a", "b"])]
+        );
+        assert!(diff_lines("", "").is_empty());
+        assert_eq!(diff_lines("", "new"), [hunk(DiffKind::Added, &["new"])]);
+    }
+
+    #[test]
+    fn groups_changes_between_shared_lines() {
+        let a = "intro\nold one\nkept\nold two\noutro";
+        let b = "intro\nnew one\nkept\noutro\nextra";
+        assert_eq!(
+            diff_lines(a, b),
+            [
+                hunk(DiffKind::Equal, &["intro"]),
+                hunk(DiffKind::Removed, &["old one"]),
+                hunk(DiffKind::Added, &["new one"]),
+                hunk(DiffKind::Equal, &["kept"]),
+                hunk(DiffKind::Removed, &["old two"]),
+                hunk(DiffKind::Equal, &["outro"]),
+                hunk(DiffKind::Added, &["extra"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn huge_middles_skip_the_table() {
+        let a: String = (0..2001).map(|i| format!("a{}\n", i)).collect();
+        let b: String = (0..2001).map(|i| format!("b{}\n", i)).collect();
+        let hunks = diff_lines(&format!("same\n{}", a), &format!("same\n{}", b));
+        assert_eq!(hunks.len(), 3);
+        assert_eq!(hunks[0], hunk(DiffKind::Equal, &["same"]));
+        assert_eq!(
+            (hunks[1].kind, hunks[1].lines.len()),
+            (DiffKind::Removed, 2001)
+        );
+        assert_eq!(
+            (hunks[2].kind, hunks[2].lines.len()),
+            (DiffKind::Added, 2001)
+        );
+    }
+}
@@ -0,0 +1,1177 @@
+use crate::activity::Activity;
+use crate::backend::{
+    spawn_env, BackendCall, BackendError, BotOptions, ChatBackend, CommandBackend, CommandTemplate,
+    DryRunCommand,
+};
+use crate::breaker::CircuitBreaker;
+use crate::cache::ResponseCache;
+use crate::children::ChildRegistry;
+use crate::conversations::{ConversationStore, Message};
+use crate::error::AppError;
+use crate::events::{Audience, EventSink};
+use crate::metrics::Metrics;
+use crate::post_process::{self, PostProcessors};
+use crate::ratelimit::RateLimiter;
+use crate::response_files::ResponseFiles;
+use crate::state::AppState;
+use crate::truncate::{truncate_response, truncate_response_tokens};
+use crate::{chatbots, lang, pricing, retry, schema, stats, validation};
+use crate::{now_millis, ChatBotConfig, ChatBotResponse, PromptRequest, PromptResponse};
+use futures::future::{join_all, select, Either};
+use jsonschema::Validator;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+use tokio::sync::{watch, Semaphore};
+use tokio::time::Instant;
+
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+const DEFAULT_MAX_RETRIES: u32 = 2;
+
+/// App-wide cap on how many backend processes may run at once.
+pub struct ConcurrencyLimit {
+    semaphore: Mutex<Arc<Semaphore>>,
+}
+
+impl Default for ConcurrencyLimit {
+    fn default() -> Self {
+        Self {
+            semaphore: Mutex::new(Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY))),
+        }
+    }
+}
+
+impl ConcurrencyLimit {
+    /// Replaces the semaphore rather than resizing it, so prompts already in
+    /// flight finish under the old limit and new ones use the new one.
+    pub fn set(&self, permits: usize) -> Result<(), AppError> {
+        if permits == 0 {
+            return Err(AppError::Validation(
+                "Max concurrency must be at least 1".to_string(),
+            ));
+        }
+        *self.lock() = Arc::new(Semaphore::new(permits));
+        Ok(())
+    }
+
+    fn current(&self) -> Arc<Semaphore> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Arc<Semaphore>> {
+        self.semaphore.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Everything a dispatch needs from Tauri managed state.
+pub struct DispatchContext<'a> {
+    /// `None` for a context put together outside the app, as in tests; it
+    /// emits no events and can't set sessions up again.
+    pub app: Option<&'a AppHandle>,
+    pub backend: Arc<dyn ChatBackend>,
+    pub children: &'a ChildRegistry,
+    pub limit: &'a ConcurrencyLimit,
+    pub conversations: &'a ConversationStore,
+    pub rate_limiter: &'a RateLimiter,
+    pub breaker: &'a CircuitBreaker,
+    pub cache: &'a ResponseCache,
+    pub metrics: &'a Metrics,
+    pub activity: &'a Activity,
+    pub post_processors: &'a PostProcessors,
+    pub response_files: &'a ResponseFiles,
+    pub chatbots: &'a [ChatBotConfig],
+    /// Environment for bots with a `command_template`.
+    pub command_env: Vec<(String, String)>,
+    pub max_output_bytes: usize,
+    /// Used for requests without their own `timeout_ms`.
+    pub default_timeout_ms: Option<u64>,
+    pub detect_language: bool,
+    pub normalize_responses: bool,
+}
+
+impl<'a> DispatchContext<'a> {
+    pub fn new(app: &'a AppHandle, chatbots: &'a [ChatBotConfig]) -> Self {
+        let state = app.state::<AppState>().inner();
+        let settings = state.settings.get();
+        Self {
+            app: Some(app),
+            backend: state.backend.get(),
+            children: &state.children,
+            limit: &state.limit,
+            conversations: &state.conversations,
+            rate_limiter: &state.rate_limiter,
+            breaker: &state.breaker,
+            cache: &state.cache,
+            metrics: &state.metrics,
+            activity: &state.activity,
+            post_processors: &state.post_processors,
+            response_files: &state.response_files,
+            chatbots,
+            command_env: spawn_env(&state.backend_config, &settings.network),
+            max_output_bytes: settings.max_output_bytes,
+            default_timeout_ms: Some(settings.default_timeout_ms).filter(|ms| *ms > 0),
+            detect_language: settings.detect_language,
+            normalize_responses: settings.normalize_responses,
+        }
+    }
+
+    /// The shared backend, unless `chatbot_id` has its own `command_template`.
+    pub fn backend_for(&self, chatbot_id: &str) -> Result<Arc<dyn ChatBackend>, BackendError> {
+        let template = self
+            .chatbots
+            .iter()
+            .find(|c| c.id == chatbot_id)
+            .and_then(|c| c.command_template.as_deref());
+        match template {
+            Some(template) => {
+                let template = CommandTemplate::parse(template).map_err(BackendError::Failed)?;
+                Ok(Arc::new(CommandBackend::new(
+                    template,
+                    self.command_env.clone(),
+                )))
+            }
+            None => Ok(self.backend.clone()),
+        }
+    }
+
+    /// Where a request's events go: the window it was sent for, or everywhere.
+    fn events(&self, window: Option<&str>) -> EventSink<'a> {
+        match self.app {
+            Some(app) => EventSink::new(app, Audience::from_window(window)),
+            None => EventSink::silent(),
+        }
+    }
+
+    /// Only bots without a `command_template` need the shared backend to be runnable.
+    fn check_ready(&self, chatbot_ids: &[String]) -> Result<(), AppError> {
+        let needs_shared = chatbot_ids.iter().any(|id| {
+            !self
+                .chatbots
+                .iter()
+                .any(|c| c.id == *id && c.command_template.is_some())
+        });
+        if needs_shared {
+            self.backend.ready().map_err(AppError::BackendSpawn)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lifecycle of a single bot within a request, emitted as `chatbot-progress`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+    request_id: String,
+    chatbot_id: String,
+    phase: ProgressPhase,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProgressPhase {
+    Started,
+    Completed,
+    Failed {
+        status: String,
+        error: Option<String>,
+    },
+}
+
+/// Emitted once as `prompt-complete` after every bot in a request has
+/// resolved, however it resolved.
+#[derive(Debug, Default, Serialize)]
+struct PromptComplete<'a> {
+    request_id: &'a str,
+    elapsed_ms: u64,
+    success: usize,
+    error: usize,
+    timeout: usize,
+    cancelled: usize,
+    rate_limited: usize,
+    snoozed: usize,
+}
+
+impl<'a> PromptComplete<'a> {
+    fn new(request_id: &'a str, elapsed_ms: u64, results: &[ChatBotResponse]) -> Self {
+        let mut event = Self {
+            request_id,
+            elapsed_ms,
+            ..Self::default()
+        };
+        for result in results {
+            match result.status.as_str() {
+                "success" => event.success += 1,
+                "timeout" => event.timeout += 1,
+                "cancelled" => event.cancelled += 1,
+                "rate_limited" => event.rate_limited += 1,
+                "snoozed" => event.snoozed += 1,
+                _ => event.error += 1,
+            }
+        }
+        event
+    }
+}
+
+/// A streamed chunk of a bot's answer, emitted as `chatbot-delta`. The final
+/// `chatbot-response` still carries the full text.
+#[derive(Serialize)]
+struct DeltaEvent<'a> {
+    request_id: &'a str,
+    chatbot_id: &'a str,
+    delta: &'a str,
+}
+
+fn emit_progress(events: &EventSink<'_>, request_id: &str, chatbot_id: &str, phase: ProgressPhase) {
+    let event = ProgressEvent {
+        request_id: request_id.to_string(),
+        chatbot_id: chatbot_id.to_string(),
+        phase,
+    };
+    events.emit("chatbot-progress", &event);
+}
+
+/// Calls the backend once per selected bot, concurrently but bounded, and
+/// collects their answers in the bots' configured order. A crashing or slow bot only
+/// affects its own entry.
+pub async fn dispatch_prompt(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+) -> Result<PromptResponse, AppError> {
+    ctx.check_ready(&request.chatbots)?;
+    let prepared = PreparedRequest::new(request)?;
+    tracing::info!(request_id, chatbots = ?request.chatbots, "dispatching prompt");
+    let started = Instant::now();
+
+    let events = ctx.events(request.event_window.as_deref());
+    ctx.children
+        .begin(request_id, request.event_window.as_deref());
+    ctx.activity
+        .start(request_id, &request.prompt, &request.chatbots, now_millis());
+    events.emit("prompt-started", request_id);
+
+    let limit = ctx.limit.current();
+    let use_cache = cacheable(request);
+    let diagnostics = Mutex::new(Vec::new());
+    let race = request.mode.as_deref() == Some("race");
+    let winner = Mutex::new(None);
+    // Flipped once a race is won; watchers see it even if they start late.
+    let (stop, stopped) = watch::channel(false);
+    let mut results = join_all(request.chatbots.iter().map(|chatbot_id| {
+        let limit = &limit;
+        let prepared = &prepared;
+        let diagnostics = &diagnostics;
+        let winner = &winner;
+        let stop = &stop;
+        let events = &events;
+        let mut stopped = stopped.clone();
+        async move {
+            // Every delta streamed so far, for answers that end without text.
+            let streamed = Mutex::new(String::new());
+            let work = async {
+                let model = chatbot_model(ctx.chatbots, chatbot_id);
+                let prompt = framed_prompt(ctx.chatbots, chatbot_id, &request.prompt);
+                // Taken before dispatch, as the conversation grows once answered.
+                let context = match (&request.conversation_id, use_cache) {
+                    (Some(id), true) => ctx.conversations.context(id, chatbot_id),
+                    _ => Vec::new(),
+                };
+                let cached = use_cache
+                    .then(|| {
+                        ctx.cache.get(
+                            &prompt,
+                            request.system_prompt.as_deref(),
+                            &context,
+                            chatbot_id,
+                            model,
+                        )
+                    })
+                    .flatten();
+                // Wait out the rate limit before taking a concurrency slot, so a
+                // throttled bot doesn't hold up the others.
+                let response = if let Some(snoozed) = snoozed_response(ctx, chatbot_id) {
+                    snoozed
+                } else if let Some(cached) = cached {
+                    cached
+                } else if let Some(open) = circuit_open_response(ctx, chatbot_id) {
+                    open
+                } else if ctx.rate_limiter.acquire(chatbot_id).await {
+                    let _permit = match limit.try_acquire() {
+                        Ok(permit) => permit,
+                        Err(_) => {
+                            let queued = status_response(ctx, chatbot_id, "queued", None);
+                            events.emit("chatbot-response", &queued);
+                            limit.acquire().await.expect("semaphore is never closed")
+                        }
+                    };
+                    emit_progress(events, request_id, chatbot_id, ProgressPhase::Started);
+                    let on_delta = |id: &str, delta: &str| {
+                        if id == chatbot_id {
+                            streamed
+                                .lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .push_str(delta);
+                        }
+                        let event = DeltaEvent {
+                            request_id,
+                            chatbot_id: id,
+                            delta,
+                        };
+                        events.emit("chatbot-delta", &event);
+                    };
+                    let on_diagnostics = |text: &str| {
+                        diagnostics
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .push(format!("[{}] {}", chatbot_id, text));
+                    };
+                    let on_retry = || streamed.lock().unwrap_or_else(|e| e.into_inner()).clear();
+                    let response = dispatch_bot(
+                        ctx,
+                        prepared,
+                        request_id,
+                        chatbot_id,
+                        &on_delta,
+                        &on_diagnostics,
+                        &on_retry,
+                    )
+                    .await;
+                    ctx.breaker.record(&response);
+                    if use_cache {
+                        ctx.cache.insert(
+                            &prompt,
+                            request.system_prompt.as_deref(),
+                            &context,
+                            chatbot_id,
+                            model,
+                            &response,
+                        );
+                    }
+                    response
+                } else {
+                    rate_limited_response(ctx, chatbot_id)
+                };
+                response
+            };
+            // Bots that don't spawn a child, such as HTTP ones, have to be
+            // stopped by dropping their future.
+            let stopped = async move {
+                let _ = stopped.wait_for(|won| *won).await;
+            };
+            let mut response = if race {
+                match select(Box::pin(work), Box::pin(stopped)).await {
+                    Either::Left((response, _)) => response,
+                    Either::Right(_) => status_response(
+                        ctx,
+                        chatbot_id,
+                        "cancelled",
+                        Some("Another bot answered first".to_string()),
+                    ),
+                }
+            } else {
+                work.await
+            };
+            keep_streamed(
+                &mut response,
+                streamed.into_inner().unwrap_or_else(|e| e.into_inner()),
+            );
+            if race && response.status == "success" {
+                let first = winner
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get_or_insert_with(|| chatbot_id.clone())
+                    == chatbot_id;
+                // The other futures are dropped with their children still
+                // registered; cancelling the request kills those.
+                if first {
+                    let _ = stop.send(true);
+                    ctx.children.cancel(request_id).await;
+                }
+            }
+            let phase = if response.status == "success" {
+                ProgressPhase::Completed
+            } else {
+                ProgressPhase::Failed {
+                    status: response.status.clone(),
+                    error: response.error.clone(),
+                }
+            };
+            emit_progress(events, request_id, chatbot_id, phase);
+            finish_response(ctx, &mut response);
+            events.emit("chatbot-response", &response);
+            response
+        }
+    }))
+    .await;
+    results.sort_by_key(|r| {
+        ctx.chatbots
+            .iter()
+            .position(|c| c.id == r.id)
+            .unwrap_or(usize::MAX)
+    });
+
+    // A won race cancels its own request to stop the losers.
+    let won = winner.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+    ctx.activity.finish(
+        request_id,
+        ctx.children.is_cancelled(request_id) && !won,
+        &results,
+    );
+    ctx.children.finish(request_id);
+    let diagnostics = diagnostics.into_inner().unwrap_or_else(|e| e.into_inner());
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    events.emit(
+        "prompt-complete",
+        &PromptComplete::new(request_id, elapsed_ms, &results),
+    );
+    tracing::info!(
+        request_id,
+        elapsed_ms,
+        statuses = ?results.iter().map(|r| (&r.id, &r.status)).collect::<Vec<_>>(),
+        "prompt finished"
+    );
+
+    let mut cancelled = Vec::new();
+    if let Some(winner) = winner.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        cancelled = results
+            .iter()
+            .filter(|r| r.status == "cancelled")
+            .map(|r| r.id.clone())
+            .collect();
+        results.retain(|r| r.id == winner);
+    }
+
+    Ok(PromptResponse {
+        request_id: request_id.to_string(),
+        prompt: request.prompt.clone(),
+        results,
+        timestamp: now_millis(),
+        diagnostics: Some(diagnostics.join("\n")).filter(|d| !d.is_empty()),
+        history_id: None,
+        tags: Vec::new(),
+        seed: request.seed,
+        cancelled,
+        dry_run: None,
+        possible_duplicate: false,
+        replay_of: None,
+    })
+}
+
+/// Runs a single bot outside the usual per-prompt events, e.g. for follow-up
+/// work on answers the user already has. Still subject to the concurrency and
+/// rate limits, and can be cancelled via `request_id`.
+pub async fn dispatch_one(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+    chatbot_id: &str,
+) -> Result<ChatBotResponse, AppError> {
+    dispatch_single(ctx, request, request_id, chatbot_id, None, &|_| {}, &|| {}).await
+}
+
+/// `dispatch_one` with the per-bot events of `dispatch_prompt`, handing each
+/// streamed chunk to `on_delta` too. `on_retry` is called before each retry,
+/// whose chunks start the answer over.
+pub async fn dispatch_streaming(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+    chatbot_id: &str,
+    on_delta: &(dyn Fn(&str) + Send + Sync),
+    on_retry: &(dyn Fn() + Send + Sync),
+) -> Result<ChatBotResponse, AppError> {
+    let events = ctx.events(request.event_window.as_deref());
+    dispatch_single(
+        ctx,
+        request,
+        request_id,
+        chatbot_id,
+        Some(&events),
+        on_delta,
+        on_retry,
+    )
+    .await
+}
+
+async fn dispatch_single(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+    chatbot_id: &str,
+    events: Option<&EventSink<'_>>,
+    on_delta: &(dyn Fn(&str) + Send + Sync),
+    on_retry: &(dyn Fn() + Send + Sync),
+) -> Result<ChatBotResponse, AppError> {
+    ctx.check_ready(&[chatbot_id.to_string()])?;
+    let prepared = PreparedRequest::new(request)?;
+
+    let skipped = match snoozed_response(ctx, chatbot_id)
+        .or_else(|| circuit_open_response(ctx, chatbot_id))
+    {
+        Some(skipped) => Some(skipped),
+        None if !ctx.rate_limiter.acquire(chatbot_id).await => {
+            Some(rate_limited_response(ctx, chatbot_id))
+        }
+        None => None,
+    };
+    if let Some(skipped) = skipped {
+        if let Some(events) = events {
+            events.emit("chatbot-response", &skipped);
+        }
+        return Ok(skipped);
+    }
+
+    ctx.children
+        .begin(request_id, request.event_window.as_deref());
+    let limit = ctx.limit.current();
+    let mut response = {
+        let _permit = limit.acquire().await.expect("semaphore is never closed");
+        if let Some(events) = events {
+            emit_progress(events, request_id, chatbot_id, ProgressPhase::Started);
+        }
+        let on_delta = |id: &str, delta: &str| {
+            if id != chatbot_id {
+                return;
+            }
+            on_delta(delta);
+            if let Some(events) = events {
+                let event = DeltaEvent {
+                    request_id,
+                    chatbot_id: id,
+                    delta,
+                };
+                events.emit("chatbot-delta", &event);
+            }
+        };
+        dispatch_bot(
+            ctx,
+            &prepared,
+            request_id,
+            chatbot_id,
+            &on_delta,
+            &|_| {},
+            on_retry,
+        )
+        .await
+    };
+    ctx.children.finish(request_id);
+    ctx.breaker.record(&response);
+    finish_response(ctx, &mut response);
+    if let Some(events) = events {
+        let phase = if response.status == "success" {
+            ProgressPhase::Completed
+        } else {
+            ProgressPhase::Failed {
+                status: response.status.clone(),
+                error: response.error.clone(),
+            }
+        };
+        emit_progress(events, request_id, chatbot_id, phase);
+        events.emit("chatbot-response", &response);
+    }
+
+    Ok(response)
+}
+
+/// Reports what the backend would run for each bot in `request`, without
+/// running anything.
+pub fn dry_run(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+) -> Result<Vec<DryRunCommand>, AppError> {
+    let mut commands = Vec::new();
+    for chatbot_id in &request.chatbots {
+        let bot = BotCall::new(ctx, request, request_id, chatbot_id)
+            .map_err(|e| AppError::Validation(e.message()))?;
+        let call = bot.call(ctx, &|_, _| {}, &|_| {});
+        commands.extend(
+            bot.backend
+                .describe(&call)
+                .map_err(|e| AppError::BackendSpawn(e.message()))?,
+        );
+    }
+    Ok(commands)
+}
+
+/// Runs `request` once per bot without retries and returns what each
+/// backend printed, in request order, for debugging a changed output format.
+/// Values of credential-looking backend variables are masked; anything else
+/// a backend echoes, such as a session cookie, is returned as printed.
+pub async fn dispatch_raw(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+) -> Result<String, AppError> {
+    ctx.check_ready(&request.chatbots)?;
+    ctx.children
+        .begin(request_id, request.event_window.as_deref());
+    let raw = async {
+        let mut raw = String::new();
+        for chatbot_id in &request.chatbots {
+            let bot = BotCall::new(ctx, request, request_id, chatbot_id)?;
+            let call = bot.call(ctx, &|_, _| {}, &|_| {});
+            raw.push_str(&bot.backend.dispatch_raw(&call).await?);
+        }
+        Ok(raw)
+    }
+    .await;
+    ctx.children.finish(request_id);
+    raw.map_err(|e| match e {
+        BackendError::Timeout(_) => AppError::Timeout(e.message()),
+        _ => AppError::BackendExit(e.message()),
+    })
+}
+
+/// Everything a `BackendCall` for a single bot borrows, resolved from the
+/// request and that bot's config.
+struct BotCall<'a> {
+    backend: Arc<dyn ChatBackend>,
+    request: &'a PromptRequest,
+    request_id: &'a str,
+    chatbots: [String; 1],
+    /// The request's prompt with this bot's prefix and suffix, if any.
+    prompt: Cow<'a, str>,
+    system_prompt: Option<&'a str>,
+    context: Vec<Message>,
+    options: BotOptions<'a>,
+    attachments_supported: bool,
+    seed_supported: bool,
+}
+
+impl<'a> BotCall<'a> {
+    fn new(
+        ctx: &DispatchContext<'a>,
+        request: &'a PromptRequest,
+        request_id: &'a str,
+        chatbot_id: &str,
+    ) -> Result<Self, BackendError> {
+        let backend = ctx.backend_for(chatbot_id)?;
+        let config = ctx.chatbots.iter().find(|c| c.id == chatbot_id);
+        let mut context: Vec<Message> = request
+            .conversation_id
+            .as_deref()
+            .map(|id| ctx.conversations.context(id, chatbot_id))
+            .unwrap_or_default();
+        let mut system_prompt = request.system_prompt.as_deref();
+        if !backend.supports_system_prompt(chatbot_id) {
+            if let Some(system) = system_prompt.take() {
+                context.insert(0, Message::system(system));
+            }
+        }
+        Ok(Self {
+            request,
+            request_id,
+            chatbots: [chatbot_id.to_string()],
+            prompt: framed_prompt(ctx.chatbots, chatbot_id, &request.prompt),
+            system_prompt,
+            context,
+            options: BotOptions {
+                model: config.and_then(|c| c.model.as_deref()),
+                params: config.and_then(|c| c.params.as_ref()),
+                headers: config.and_then(|c| c.headers.as_ref()),
+            },
+            attachments_supported: backend.supports_attachments(chatbot_id),
+            seed_supported: backend.supports_seed(chatbot_id),
+            backend,
+        })
+    }
+
+    fn call<'b>(
+        &'b self,
+        ctx: &'b DispatchContext<'_>,
+        on_delta: &'b (dyn Fn(&str, &str) + Send + Sync),
+        on_diagnostics: &'b (dyn Fn(&str) + Send + Sync),
+    ) -> BackendCall<'b> {
+        BackendCall {
+            request_id: self.request_id,
+            prompt: &self.prompt,
+            system_prompt: self.system_prompt,
+            context: &self.context,
+            chatbots: &self.chatbots,
+            timeout_ms: self.request.timeout_ms.or(ctx.default_timeout_ms),
+            max_output_bytes: ctx.max_output_bytes,
+            options: self.options,
+            seed: self.request.seed.filter(|_| self.seed_supported),
+            response_format: self.request.response_format.as_deref(),
+            attachments: if self.attachments_supported {
+                &self.request.attachments
+            } else {
+                &[]
+            },
+            on_delta,
+            on_diagnostics,
+            children: ctx.children,
+        }
+    }
+}
+
+/// A request with its response schema compiled, once for all its bots.
+struct PreparedRequest<'a> {
+    request: &'a PromptRequest,
+    schema: Option<Validator>,
+}
+
+impl<'a> PreparedRequest<'a> {
+    fn new(request: &'a PromptRequest) -> Result<Self, AppError> {
+        let schema = request
+            .response_schema
+            .as_ref()
+            .map(schema::compile)
+            .transpose()
+            .map_err(|e| AppError::Validation(format!("Invalid response schema: {}", e)))?;
+        Ok(Self { request, schema })
+    }
+}
+
+impl std::ops::Deref for PreparedRequest<'_> {
+    type Target = PromptRequest;
+
+    fn deref(&self) -> &PromptRequest {
+        self.request
+    }
+}
+
+async fn dispatch_bot(
+    ctx: &DispatchContext<'_>,
+    request: &PreparedRequest<'_>,
+    request_id: &str,
+    chatbot_id: &str,
+    on_delta: &(dyn Fn(&str, &str) + Send + Sync),
+    on_diagnostics: &(dyn Fn(&str) + Send + Sync),
+    on_retry: &(dyn Fn() + Send + Sync),
+) -> ChatBotResponse {
+    let started = Instant::now();
+    let max_retries = request.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+    let bot = match BotCall::new(ctx, request, request_id, chatbot_id) {
+        Ok(bot) => bot,
+        Err(failure) => return failed_response(ctx, chatbot_id, failure, 0, 0),
+    };
+    let call = bot.call(ctx, on_delta, on_diagnostics);
+    let (bot, call) = (&bot, &call);
+    let mut started_attempts = 0;
+    let (result, attempts) = retry::with_backoff(max_retries, BackendError::is_retryable, || {
+        started_attempts += 1;
+        let retrying = started_attempts > 1;
+        async move {
+            // Whatever the failed attempt streamed is superseded by this one.
+            if retrying {
+                on_retry();
+            }
+            if ctx.children.is_cancelled(request_id) {
+                return Err(BackendError::Cancelled);
+            }
+            let responses = bot.backend.dispatch(call).await?;
+            let response = responses
+                .into_iter()
+                .find(|r| r.id == chatbot_id)
+                .ok_or_else(|| {
+                    BackendError::Failed("AI backend returned no response".to_string())
+                })?;
+            validation::validate_response(&response).map_err(BackendError::Failed)?;
+            Ok(response)
+        }
+    })
+    .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(mut response) => {
+            // Unsupported attachments are dropped, not fatal; say so next to the answer.
+            if !bot.attachments_supported && !request.attachments.is_empty() {
+                add_note(
+                    &mut response,
+                    format!(
+                        "{} attachment(s) ignored: this bot does not support attachments",
+                        request.attachments.len()
+                    ),
+                );
+            }
+            response.prompt_transformed = matches!(bot.prompt, Cow::Owned(_));
+            if !bot.seed_supported && request.seed.is_some() {
+                add_note(
+                    &mut response,
+                    "seed ignored: this bot does not support seeding".to_string(),
+                );
+            }
+            for failure in ctx.response_files.save(&mut response) {
+                add_note(&mut response, failure);
+            }
+            // Not every backend honours max_tokens, so it is enforced here too.
+            let max_tokens = bot
+                .options
+                .params
+                .and_then(|params| params.get("max_tokens"))
+                .and_then(serde_json::Value::as_u64);
+            if let Some(max) = max_tokens {
+                let model = bot.options.model.unwrap_or(chatbot_id);
+                let (text, truncated) =
+                    truncate_response_tokens(&response.response, model, max as usize);
+                response.response = text;
+                response.truncated |= truncated;
+            }
+            if let Some(max) = request.max_response_chars {
+                let (text, truncated) = truncate_response(&response.response, max);
+                response.response = text;
+                response.truncated |= truncated;
+            }
+            if request.response_format.as_deref() == Some("json") && response.status == "success" {
+                if let Err(e) = serde_json::from_str::<serde_json::Value>(&response.response) {
+                    response.status = "invalid_json".to_string();
+                    add_note(&mut response, format!("Response is not valid JSON: {}", e));
+                }
+            }
+            if let (Some(schema), "success") = (&request.schema, response.status.as_str()) {
+                let conforms = serde_json::from_str(&response.response)
+                    .map_err(|e| format!("Response is not valid JSON: {}", e))
+                    .and_then(|value| schema::validate(schema, &value));
+                if let Err(e) = conforms {
+                    response.status = "schema_violation".to_string();
+                    add_note(
+                        &mut response,
+                        format!("Response violates the schema: {}", e),
+                    );
+                }
+            }
+            response.char_count = stats::char_count(&response.response);
+            response.word_count = stats::word_count(&response.response);
+            if ctx.detect_language && response.status == "success" {
+                response.detected_language = lang::detect(&response.response).map(str::to_string);
+            }
+            if response.name.is_empty() {
+                response.name = chatbot_name(ctx.chatbots, chatbot_id);
+            }
+            if response.latency_ms == 0 {
+                response.latency_ms = latency_ms;
+            }
+            // Backends that know their model price the call themselves.
+            if let (Some(model), Some(prompt), Some(completion), 0.0) = (
+                bot.options.model,
+                response.prompt_tokens,
+                response.completion_tokens,
+                response.estimated_cost_usd,
+            ) {
+                response.estimated_cost_usd = pricing::estimate_cost(model, prompt, completion);
+            }
+            response
+        }
+        Err(failure) => failed_response(ctx, chatbot_id, failure, attempts, latency_ms),
+    }
+}
+
+/// The entry for a bot that produced no answer. `attempts` is 0 when the
+/// backend was never called.
+fn failed_response(
+    ctx: &DispatchContext<'_>,
+    chatbot_id: &str,
+    failure: BackendError,
+    attempts: u32,
+    latency_ms: u64,
+) -> ChatBotResponse {
+    ChatBotResponse {
+        id: chatbot_id.to_string(),
+        name: chatbot_name(ctx.chatbots, chatbot_id),
+        status: failure.status().to_string(),
+        error: Some(match failure {
+            BackendError::Transient(_) | BackendError::Failed(_) if attempts > 0 => format!(
+                "{} ({} attempt{})",
+                failure.message(),
+                attempts,
+                if attempts == 1 { "" } else { "s" }
+            ),
+            _ => failure.message(),
+        }),
+        timestamp: now_millis(),
+        latency_ms,
+        ..Default::default()
+    }
+}
+
+/// Fills in the text streamed for an answer that ended without any, as
+/// bots that time out or are cancelled mid-stream do.
+fn keep_streamed(response: &mut ChatBotResponse, streamed: String) {
+    if !response.response.is_empty() || streamed.is_empty() {
+        return;
+    }
+    if response.status != "success" {
+        add_note(
+            response,
+            "Partial answer streamed before the bot stopped".to_string(),
+        );
+    }
+    response.response = streamed;
+    response.char_count = stats::char_count(&response.response);
+    response.word_count = stats::word_count(&response.response);
+}
+
+/// Appends `note` to the response's `error` without touching its status.
+pub fn add_note(response: &mut ChatBotResponse, note: String) {
+    response.error = Some(match response.error.take() {
+        Some(error) => format!("{}; {}", error, note),
+        None => note,
+    });
+}
+
+/// The "snoozed" entry for `chatbot_id`, if prompts are skipping it.
+fn snoozed_response(ctx: &DispatchContext<'_>, chatbot_id: &str) -> Option<ChatBotResponse> {
+    let now = now_millis();
+    let until = chatbots::snoozed_until(ctx.chatbots, chatbot_id, now)?;
+    let minutes = until.saturating_sub(now).div_ceil(60_000);
+    let note = format!("Snoozed for another {} min", minutes);
+    Some(status_response(ctx, chatbot_id, "snoozed", Some(note)))
+}
+
+/// The "circuit_open" entry for `chatbot_id`, if its circuit breaker is
+/// refusing calls. Letting a call through may make it the probe, so only ask
+/// right before calling the backend.
+fn circuit_open_response(ctx: &DispatchContext<'_>, chatbot_id: &str) -> Option<ChatBotResponse> {
+    let wait = ctx.breaker.allow(chatbot_id).err()?;
+    let note = format!(
+        "Skipped after repeated failures; retrying in {} s",
+        wait.as_secs().max(1)
+    );
+    Some(status_response(ctx, chatbot_id, "circuit_open", Some(note)))
+}
+
+/// Counts an answer, then runs it through normalization and the
+/// post-processors. Cached answers are stored before this, so they go through
+/// the pipeline as it is when they are served.
+fn finish_response(ctx: &DispatchContext<'_>, response: &mut ChatBotResponse) {
+    ctx.metrics.record(response);
+    if ctx.normalize_responses {
+        post_process::normalize(response);
+    }
+    ctx.post_processors.apply(response);
+}
+
+fn rate_limited_response(ctx: &DispatchContext<'_>, chatbot_id: &str) -> ChatBotResponse {
+    status_response(
+        ctx,
+        chatbot_id,
+        "rate_limited",
+        Some(format!(
+            "{} is over its rate limit; try again shortly",
+            chatbot_id
+        )),
+    )
+}
+
+/// An answer-less entry that only reports a status, such as "queued".
+fn status_response(
+    ctx: &DispatchContext<'_>,
+    chatbot_id: &str,
+    status: &str,
+    error: Option<String>,
+) -> ChatBotResponse {
+    ChatBotResponse {
+        id: chatbot_id.to_string(),
+        name: chatbot_name(ctx.chatbots, chatbot_id),
+        status: status.to_string(),
+        error,
+        timestamp: now_millis(),
+        ..Default::default()
+    }
+}
+
+/// Only plain prompts and conversation turns are cached: attachments,
+/// truncation, seeds, formats and schemas all change the answer without changing the cache key.
+fn cacheable(request: &PromptRequest) -> bool {
+    !request.skip_cache.unwrap_or(false)
+        && request.attachments.is_empty()
+        && request.max_response_chars.is_none()
+        && request.seed.is_none()
+        && request.response_format.is_none()
+        && request.response_schema.is_none()
+}
+
+/// `prompt` wrapped in the bot's `prompt_prefix` and `prompt_suffix`;
+/// borrowed as-is when it has neither.
+fn framed_prompt<'a>(chatbots: &[ChatBotConfig], id: &str, prompt: &'a str) -> Cow<'a, str> {
+    let non_empty = |text: &Option<String>| text.clone().filter(|t| !t.is_empty());
+    let Some(config) = chatbots.iter().find(|c| c.id == id) else {
+        return Cow::Borrowed(prompt);
+    };
+    match (
+        non_empty(&config.prompt_prefix),
+        non_empty(&config.prompt_suffix),
+    ) {
+        (None, None) => Cow::Borrowed(prompt),
+        (prefix, suffix) => Cow::Owned(
+            [prefix.as_deref(), Some(prompt), suffix.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        ),
+    }
+}
+
+fn chatbot_model<'a>(chatbots: &'a [ChatBotConfig], id: &str) -> Option<&'a str> {
+    chatbots
+        .iter()
+        .find(|c| c.id == id)
+        .and_then(|c| c.model.as_deref())
+}
+
+fn chatbot_name(chatbots: &[ChatBotConfig], id: &str) -> String {
+    chatbots
+        .iter()
+        .find(|c| c.id == id)
+        .map(|c| c.name.clone())
+        .unwrap_or_else(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Fails every call, counting them.
+    #[derive(Default)]
+    struct DownBackend {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ChatBackend for DownBackend {
+        fn describe(&self, _: &BackendCall<'_>) -> Result<Vec<DryRunCommand>, BackendError> {
+            Ok(Vec::new())
+        }
+
+        async fn dispatch(
+            &self,
+            _: &BackendCall<'_>,
+        ) -> Result<Vec<ChatBotResponse>, BackendError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(BackendError::Failed("Backend is down".to_string()))
+        }
+    }
+
+    /// What `AppState` would own, for a context built without the app.
+    struct Managed {
+        children: ChildRegistry,
+        limit: ConcurrencyLimit,
+        conversations: ConversationStore,
+        rate_limiter: RateLimiter,
+        breaker: CircuitBreaker,
+        cache: ResponseCache,
+        metrics: Metrics,
+        activity: Activity,
+        post_processors: PostProcessors,
+        response_files: ResponseFiles,
+    }
+
+    impl Managed {
+        fn new(breaker: CircuitBreaker) -> Self {
+            Self {
+                children: ChildRegistry::default(),
+                limit: ConcurrencyLimit::default(),
+                conversations: ConversationStore::default(),
+                rate_limiter: RateLimiter::default(),
+                breaker,
+                cache: ResponseCache::default(),
+                metrics: Metrics::default(),
+                activity: Activity::default(),
+                post_processors: PostProcessors::default(),
+                response_files: ResponseFiles::new(PathBuf::from("unused")),
+            }
+        }
+
+        fn context(&self, backend: Arc<dyn ChatBackend>) -> DispatchContext<'_> {
+            DispatchContext {
+                app: None,
+                backend,
+                children: &self.children,
+                limit: &self.limit,
+                conversations: &self.conversations,
+                rate_limiter: &self.rate_limiter,
+                breaker: &self.breaker,
+                cache: &self.cache,
+                metrics: &self.metrics,
+                activity: &self.activity,
+                post_processors: &self.post_processors,
+                response_files: &self.response_files,
+                chatbots: &[],
+                command_env: Vec::new(),
+                max_output_bytes: 1024 * 1024,
+                default_timeout_ms: None,
+                detect_language: false,
+                normalize_responses: false,
+            }
+        }
+    }
+
+    #[test]
+    fn an_open_circuit_answers_without_calling_the_backend() {
+        tauri::async_runtime::block_on(async {
+            let managed = Managed::new(CircuitBreaker::new(1, Duration::from_secs(60)));
+            let backend = Arc::new(DownBackend::default());
+            let ctx = managed.context(backend.clone());
+            let request = PromptRequest {
+                prompt: "Hello".to_string(),
+                chatbots: vec!["a".to_string()],
+                ..Default::default()
+            };
+
+            let failed = dispatch_one(&ctx, &request, "r1", "a").await.unwrap();
+            assert_eq!(failed.status, "error");
+
+            let skipped = dispatch_one(&ctx, &request, "r2", "a").await.unwrap();
+            assert_eq!(skipped.status, "circuit_open");
+            let note = skipped.error.unwrap_or_default();
+            assert!(
+                note.starts_with("Skipped after repeated failures"),
+                "{note}"
+            );
+            let response = dispatch_prompt(&ctx, &request, "r3").await.unwrap();
+            assert_eq!(response.results[0].status, "circuit_open");
+            assert_eq!(backend.calls.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    fn ended(status: &str, text: &str) -> ChatBotResponse {
+        ChatBotResponse {
+            id: "a".to_string(),
+            status: status.to_string(),
+            response: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn streamed_text_fills_an_empty_answer() {
+        let mut response = ended("success", "");
+        keep_streamed(&mut response, "Hello, world".to_string());
+        assert_eq!(response.response, "Hello, world");
+        assert_eq!(response.error, None);
+        assert_eq!((response.char_count, response.word_count), (12, 2));
+    }
+
+    #[test]
+    fn stopped_bots_keep_their_partial_text() {
+        for status in ["timeout", "cancelled"] {
+            let mut response = ended(status, "");
+            keep_streamed(&mut response, "Hello, wor".to_string());
+            assert_eq!(response.status, status);
+            assert_eq!(response.response, "Hello, wor");
+            assert_eq!(
+                response.error.as_deref(),
+                Some("Partial answer streamed before the bot stopped")
+            );
+        }
+    }
+
+    #[test]
+    fn returned_text_wins_over_streamed_text() {
+        let mut response = ended("success", "Final");
+        keep_streamed(&mut response, "Fin".to_string());
+        assert_eq!(response.response, "Final");
+
+        let mut response = ended("timeout", "");
+        keep_streamed(&mut response, String::new());
+        assert_eq!(response.error, None);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::i18n;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+/// Error returned by every command. Serialized as
+/// `{ "kind": "not_found", "code": "conversation_not_found", "message": "..." }`
+/// so the frontend can branch on the kind and still show the message as-is.
+/// The message is in the current [`i18n`] locale; `code` is its catalog id,
+/// or the kind for messages without one, for frontends translating themselves.
+#[derive(Debug, Clone)]
+pub enum AppError {
+    /// The backend could not be started, e.g. Node.js is missing.
+    BackendSpawn(String),
+    /// The backend ran but exited unsuccessfully or produced no answer.
+    BackendExit(String),
+    /// Input that was supposed to be structured could not be parsed.
+    ParseFailure(String),
+    Timeout(String),
+    /// The request itself is invalid and retrying it unchanged won't help.
+    Validation(String),
+    NotFound(String),
+    /// Reading or writing config, history or secrets failed.
+    Storage(String),
+}
+
+impl AppError {
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BackendSpawn(m)
+            | AppError::BackendExit(m)
+            | AppError::ParseFailure(m)
+            | AppError::Timeout(m)
+            | AppError::Validation(m)
+            | AppError::NotFound(m)
+            | AppError::Storage(m) => m,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::BackendSpawn(_) => "backend_spawn",
+            AppError::BackendExit(_) => "backend_exit",
+            AppError::ParseFailure(_) => "parse_failure",
+            AppError::Timeout(_) => "timeout",
+            AppError::Validation(_) => "validation",
+            AppError::NotFound(_) => "not_found",
+            AppError::Storage(_) => "storage",
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (code, message) = i18n::localize(self.message());
+        let mut error = serializer.serialize_struct("AppError", 3)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("code", code.unwrap_or(self.kind()))?;
+        error.serialize_field("message", &message)?;
+        error.end()
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
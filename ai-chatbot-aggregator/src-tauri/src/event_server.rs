@@ -0,0 +1,329 @@
+use crate::error::AppError;
+use futures::future::{select, Either};
+use futures::{SinkExt, StreamExt};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, EventId, Listener};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+/// Forwarded to clients as text frames, payload unchanged.
+const FORWARDED_EVENT: &str = "chatbot-response";
+/// How many events a slow client may fall behind before it misses some.
+const BACKLOG: usize = 256;
+/// Clients only send control frames, so anything bigger is a misbehaving client.
+const MAX_CLIENT_MESSAGE: usize = 64 * 1024;
+
+struct Running {
+    port: u16,
+    listener: EventId,
+    stop: watch::Sender<bool>,
+}
+
+/// An opt-in WebSocket server on loopback that pushes every answer the
+/// frontend receives to local tools, e.g. `websocat ws://127.0.0.1:9001`.
+/// It only sends; client messages other than ping and close are ignored.
+#[derive(Default)]
+pub struct EventServer {
+    running: Mutex<Option<Running>>,
+}
+
+impl EventServer {
+    /// Listens on `127.0.0.1:port` (0 picks a free port) and returns the port.
+    pub async fn start(&self, app: &AppHandle, port: u16) -> Result<u16, AppError> {
+        if let Some(running) = self.lock().as_ref() {
+            return Err(already_running(running.port));
+        }
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.map_err(|e| {
+            AppError::Validation(format!("Failed to listen on 127.0.0.1:{}: {}", port, e))
+        })?;
+        let port = listener.local_addr().map(|a| a.port()).unwrap_or(port);
+
+        let mut running = self.lock();
+        // Another start may have won while this one was binding.
+        if let Some(running) = running.as_ref() {
+            return Err(already_running(running.port));
+        }
+        let (events, _) = broadcast::channel(BACKLOG);
+        let sender = events.clone();
+        let listener_id = app.listen_any(FORWARDED_EVENT, move |event| {
+            // No subscribers just means no client is connected.
+            let _ = sender.send(event.payload().to_string());
+        });
+        let (stop, stopped) = watch::channel(false);
+        *running = Some(Running {
+            port,
+            listener: listener_id,
+            stop,
+        });
+        tauri::async_runtime::spawn(accept(listener, events, stopped));
+        tracing::info!(port, "event server started");
+        Ok(port)
+    }
+
+    /// Closes the server and every client connection; false if it wasn't running.
+    pub fn stop(&self, app: &AppHandle) -> bool {
+        let Some(running) = self.lock().take() else {
+            return false;
+        };
+        app.unlisten(running.listener);
+        let _ = running.stop.send(true);
+        tracing::info!(port = running.port, "event server stopped");
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Running>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn already_running(port: u16) -> AppError {
+    AppError::Validation(format!("Event server is already running on port {}", port))
+}
+
+async fn accept(
+    listener: TcpListener,
+    events: broadcast::Sender<String>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    loop {
+        let accepted = match select(
+            Box::pin(listener.accept()),
+            Box::pin(stopped.wait_for(|stop| *stop)),
+        )
+        .await
+        {
+            Either::Left((accepted, _)) => accepted,
+            Either::Right(_) => break,
+        };
+        match accepted {
+            Ok((stream, addr)) => {
+                tracing::debug!(%addr, "event client connected");
+                tauri::async_runtime::spawn(serve(stream, events.subscribe(), stopped.clone()));
+            }
+            Err(e) => tracing::warn!("Event server failed to accept a connection: {}", e),
+        }
+    }
+}
+
+enum Outgoing {
+    Message(Message),
+    /// The client closed the connection or it dropped.
+    Gone,
+}
+
+/// Runs one client: events and replies to its messages go through one
+/// queue, so frames are never interleaved on the socket. Pings are answered
+/// and a client's close is echoed by the WebSocket layer while reading.
+async fn serve(
+    stream: TcpStream,
+    mut events: broadcast::Receiver<String>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_CLIENT_MESSAGE))
+        .max_frame_size(Some(MAX_CLIENT_MESSAGE));
+    let socket = match tokio_tungstenite::accept_hdr_async_with_config(
+        stream,
+        refuse_browsers,
+        Some(config),
+    )
+    .await
+    {
+        Ok(socket) => socket,
+        Err(e) => {
+            tracing::debug!("event client rejected: {}", e);
+            return;
+        }
+    };
+    let (mut writer, mut reader) = socket.split();
+    let (outgoing, mut frames) = mpsc::channel(BACKLOG);
+
+    let replies = outgoing.clone();
+    let read = tauri::async_runtime::spawn(async move {
+        let reply = loop {
+            match reader.next().await {
+                // Data messages are ignored.
+                Some(Ok(_)) => continue,
+                Some(Err(e @ (WsError::Protocol(_) | WsError::Capacity(_)))) => {
+                    tracing::debug!("event client broke the protocol: {}", e);
+                    break Outgoing::Message(Message::Close(Some(CloseFrame {
+                        code: CloseCode::Protocol,
+                        reason: "".into(),
+                    })));
+                }
+                Some(Err(_)) | None => break Outgoing::Gone,
+            }
+        };
+        let _ = replies.send(reply).await;
+    });
+    let forward = tauri::async_runtime::spawn(async move {
+        loop {
+            let message = match select(
+                Box::pin(events.recv()),
+                Box::pin(stopped.wait_for(|stop| *stop)),
+            )
+            .await
+            {
+                Either::Left((Ok(payload), _)) => Message::Text(payload.into()),
+                Either::Left((Err(broadcast::error::RecvError::Lagged(missed)), _)) => {
+                    tracing::warn!(missed, "event client fell behind");
+                    continue;
+                }
+                Either::Left((Err(broadcast::error::RecvError::Closed), _)) | Either::Right(_) => {
+                    Message::Close(None)
+                }
+            };
+            let close = matches!(message, Message::Close(_));
+            if outgoing.send(Outgoing::Message(message)).await.is_err() || close {
+                break;
+            }
+        }
+    });
+
+    while let Some(Outgoing::Message(message)) = frames.recv().await {
+        let close = matches!(message, Message::Close(_));
+        if writer.send(message).await.is_err() || close {
+            break;
+        }
+    }
+    read.abort();
+    forward.abort();
+    tracing::debug!("event client disconnected");
+}
+
+/// Refuses upgrade requests carrying an `Origin` header: they come from a
+/// browser page, which must not be able to read answers just because it
+/// runs on the same machine. The signature is tungstenite's `Callback`.
+#[allow(clippy::result_large_err)]
+fn refuse_browsers(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    if !request.headers().contains_key("origin") {
+        return Ok(response);
+    }
+    let mut forbidden = ErrorResponse::new(None);
+    *forbidden.status_mut() = StatusCode::FORBIDDEN;
+    Err(forbidden)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::WebSocketStream;
+
+    /// A server on a free loopback port with its event channel and stop switch.
+    struct Fixture {
+        port: u16,
+        events: broadcast::Sender<String>,
+        _stop: watch::Sender<bool>,
+    }
+
+    async fn fixture() -> Fixture {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (events, _) = broadcast::channel(BACKLOG);
+        let (stop, stopped) = watch::channel(false);
+        tauri::async_runtime::spawn(accept(listener, events.clone(), stopped));
+        Fixture {
+            port,
+            events,
+            _stop: stop,
+        }
+    }
+
+    async fn connect(port: u16) -> WebSocketStream<TcpStream> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!("ws://127.0.0.1:{}", port)
+            .into_client_request()
+            .unwrap();
+        tokio_tungstenite::client_async(request, stream)
+            .await
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn forwards_events_and_answers_pings() {
+        tauri::async_runtime::block_on(async {
+            let server = fixture().await;
+            let mut client = connect(server.port).await;
+
+            client
+                .send(Message::Ping(b"hi".to_vec().into()))
+                .await
+                .unwrap();
+            assert_eq!(
+                client.next().await.unwrap().unwrap(),
+                Message::Pong(b"hi".to_vec().into())
+            );
+
+            server.events.send(r#"{"id":"a"}"#.to_string()).unwrap();
+            assert_eq!(
+                client.next().await.unwrap().unwrap(),
+                Message::Text(r#"{"id":"a"}"#.into())
+            );
+        });
+    }
+
+    /// Sends `request` over a raw socket and returns everything the server
+    /// wrote before closing.
+    async fn raw(port: u16, request: &[u8]) -> Vec<u8> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut reply = Vec::new();
+        let _ = stream.read_to_end(&mut reply).await;
+        reply
+    }
+
+    const UPGRADE: &str = "GET / HTTP/1.1\r\nHost: 127.0.0.1\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n";
+
+    #[test]
+    fn refuses_browser_pages() {
+        tauri::async_runtime::block_on(async {
+            let server = fixture().await;
+            let request = format!("{}Origin: https://example.com\r\n\r\n", UPGRADE);
+            let reply = raw(server.port, request.as_bytes()).await;
+            assert!(
+                reply.starts_with(b"HTTP/1.1 403"),
+                "{:?}",
+                String::from_utf8_lossy(&reply)
+            );
+        });
+    }
+
+    #[test]
+    fn closes_with_protocol_error_on_unmasked_frames() {
+        tauri::async_runtime::block_on(async {
+            let server = fixture().await;
+            let mut stream = TcpStream::connect(("127.0.0.1", server.port))
+                .await
+                .unwrap();
+            stream
+                .write_all(format!("{}\r\n", UPGRADE).as_bytes())
+                .await
+                .unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(stream.read_u8().await.unwrap());
+            }
+            assert!(head.starts_with(b"HTTP/1.1 101"));
+            // The accept key from the example in RFC 6455, section 1.3.
+            assert!(String::from_utf8_lossy(&head)
+                .to_ascii_lowercase()
+                .contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="));
+
+            // A text frame without the mask clients must set.
+            stream.write_all(&[0x81, 2, b'h', b'i']).await.unwrap();
+            let mut reply = Vec::new();
+            let _ = stream.read_to_end(&mut reply).await;
+            assert_eq!(&reply[..4], &[0x88, 2, 0x03, 0xEA]);
+        });
+    }
+}
@@ -0,0 +1,87 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, EventTarget};
+
+/// Who receives a request's events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Audience {
+    /// Every window, and listeners such as the event server.
+    All,
+    /// Only the window with this label.
+    Window(String),
+}
+
+impl Audience {
+    /// `All` unless a window label is given.
+    pub fn from_window(label: Option<&str>) -> Self {
+        match label.map(str::trim).filter(|l| !l.is_empty()) {
+            Some(label) => Audience::Window(label.to_string()),
+            None => Audience::All,
+        }
+    }
+
+    /// Where Tauri delivers this audience's events. A label matches a
+    /// window, webview or webview window of that name.
+    pub fn target(&self) -> EventTarget {
+        match self {
+            Audience::All => EventTarget::Any,
+            Audience::Window(label) => EventTarget::AnyLabel {
+                label: label.clone(),
+            },
+        }
+    }
+}
+
+/// Emits events to an `Audience`; failures to deliver are ignored, since no
+/// window having listened is not an error.
+pub struct EventSink<'a> {
+    app: Option<&'a AppHandle>,
+    audience: Audience,
+}
+
+impl<'a> EventSink<'a> {
+    pub fn new(app: &'a AppHandle, audience: Audience) -> Self {
+        Self {
+            app: Some(app),
+            audience,
+        }
+    }
+
+    /// A sink that drops every event, for work done outside the app.
+    pub fn silent() -> Self {
+        Self {
+            app: None,
+            audience: Audience::All,
+        }
+    }
+
+    pub fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Some(app) = self.app {
+            let _ = app.emit_to(self.audience.target(), event, payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_go_everywhere_without_a_window() {
+        for label in [None, Some(""), Some("  ")] {
+            assert_eq!(Audience::from_window(label), Audience::All);
+        }
+        assert_eq!(Audience::All.target(), EventTarget::Any);
+    }
+
+    #[test]
+    fn a_window_label_scopes_events_to_it() {
+        let audience = Audience::from_window(Some(" compare "));
+        assert_eq!(audience, Audience::Window("compare".to_string()));
+        assert_eq!(
+            audience.target(),
+            EventTarget::AnyLabel {
+                label: "compare".to_string()
+            }
+        );
+    }
+}
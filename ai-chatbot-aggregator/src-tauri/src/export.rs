@@ -0,0 +1,216 @@
+use crate::PromptResponse;
+use chrono::DateTime;
+
+/// Renders a prompt's results as a Markdown document, one `##` section per bot.
+pub fn response_to_markdown(response: &PromptResponse) -> String {
+    let mut out = format!("# Results for {}\n\n", format_timestamp(response.timestamp));
+
+    if !response.prompt.is_empty() {
+        for line in response.prompt.lines() {
+            out.push_str("> ");
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+
+    for result in &response.results {
+        out.push_str(&format!("## {}\n\n", result.name));
+
+        if result.status == "success" {
+            out.push_str(&format!("**Status:** {}\n\n", result.status));
+        } else {
+            out.push_str(&format!("**Status:** {} (failed)\n\n", result.status));
+            if let Some(error) = &result.error {
+                out.push_str(&format!("**Error:** {}\n\n", error));
+            }
+        }
+
+        if !result.response.is_empty() {
+            out.push_str(result.response.trim_end());
+            out.push_str("\n\n");
+        }
+    }
+
+    out
+}
+
+/// Serializes history entries as a pretty-printed JSON array.
+pub fn history_to_json(entries: &[PromptResponse]) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(entries)
+}
+
+/// How `history_to_csv` lays out its bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOptions {
+    /// Starts the file with a UTF-8 byte order mark, which some spreadsheet
+    /// tools need to detect the encoding.
+    pub bom: bool,
+    /// Ends rows with CRLF, as RFC 4180 says, instead of LF.
+    pub crlf: bool,
+}
+
+/// One RFC 4180 row per bot response, with a header row. Line breaks inside
+/// fields are kept as they are.
+pub fn history_to_csv(entries: &[PromptResponse], options: CsvOptions) -> String {
+    let newline = if options.crlf { "\r\n" } else { "\n" };
+    let mut out = String::new();
+    if options.bom {
+        out.push('\u{feff}');
+    }
+    out.push_str("prompt,chatbot_id,status,latency_ms,response,timestamp");
+    out.push_str(newline);
+    for entry in entries {
+        for result in &entry.results {
+            let row = [
+                csv_field(&entry.prompt),
+                csv_field(&result.id),
+                csv_field(&result.status),
+                result.latency_ms.to_string(),
+                csv_field(&result.response),
+                csv_field(&format_timestamp(result.timestamp)),
+            ];
+            out.push_str(&row.join(","));
+            out.push_str(newline);
+        }
+    }
+    out
+}
+
+/// Quotes a field when it contains a comma, quote or line break, doubling any quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_timestamp(timestamp_ms: u64) -> String {
+    DateTime::from_timestamp_millis(timestamp_ms as i64)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp_ms.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatBotResponse;
+
+    /// 2024-01-15 10:30:00 UTC.
+    const TIMESTAMP: u64 = 1_705_314_600_000;
+
+    fn entry(prompt: &str, results: Vec<ChatBotResponse>) -> PromptResponse {
+        PromptResponse {
+            prompt: prompt.to_string(),
+            results,
+            timestamp: TIMESTAMP,
+            ..Default::default()
+        }
+    }
+
+    fn result(id: &str, status: &str, response: &str) -> ChatBotResponse {
+        ChatBotResponse {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            status: status.to_string(),
+            response: response.to_string(),
+            latency_ms: 1200,
+            timestamp: TIMESTAMP,
+            ..Default::default()
+        }
+    }
+
+    const LF: CsvOptions = CsvOptions {
+        bom: false,
+        crlf: false,
+    };
+
+    #[test]
+    fn csv_has_one_row_per_result() {
+        let entries = [
+            entry(
+                "Hi",
+                vec![result("a", "success", "Hello"), result("b", "error", "")],
+            ),
+            entry("Bye", vec![result("a", "success", "Goodbye")]),
+        ];
+        assert_eq!(
+            history_to_csv(&entries, LF),
+            "prompt,chatbot_id,status,latency_ms,response,timestamp\n\
+             Hi,a,success,1200,Hello,2024-01-15 10:30:00 UTC\n\
+             Hi,b,error,1200,,2024-01-15 10:30:00 UTC\n\
+             Bye,a,success,1200,Goodbye,2024-01-15 10:30:00 UTC\n"
+        );
+    }
+
+    #[test]
+    fn csv_quotes_fields_that_need_it() {
+        let entries = [entry(
+            "Say \"hi\", please",
+            vec![result("a", "success", "line one\nline two")],
+        )];
+        let csv = history_to_csv(&entries, LF);
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("\"Say \"\"hi\"\", please\",a,success,1200,\"line one")
+        );
+        assert!(csv.ends_with("line two\",2024-01-15 10:30:00 UTC\n"));
+    }
+
+    #[test]
+    fn csv_without_entries_is_just_the_header() {
+        assert_eq!(
+            history_to_csv(&[], LF),
+            "prompt,chatbot_id,status,latency_ms,response,timestamp\n"
+        );
+    }
+
+    #[test]
+    fn csv_bytes_follow_the_options() {
+        let entries = [entry("Hi", vec![result("a", "success", "two\nlines")])];
+        let body = "prompt,chatbot_id,status,latency_ms,response,timestamp{nl}\
+                    Hi,a,success,1200,\"two\nlines\",2024-01-15 10:30:00 UTC{nl}";
+        for (bom, crlf) in [(false, false), (true, false), (false, true), (true, true)] {
+            let csv = history_to_csv(&entries, CsvOptions { bom, crlf });
+            let mut expected = if bom {
+                vec![0xEF, 0xBB, 0xBF]
+            } else {
+                Vec::new()
+            };
+            expected.extend_from_slice(
+                body.replace("{nl}", if crlf { "\r\n" } else { "\n" })
+                    .as_bytes(),
+            );
+            assert_eq!(csv.as_bytes(), expected, "bom: {}, crlf: {}", bom, crlf);
+        }
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let entries = vec![entry("Hi", vec![result("a", "success", "Hello")])];
+        let json = history_to_json(&entries).unwrap();
+        let parsed: Vec<PromptResponse> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].prompt, "Hi");
+        assert_eq!(parsed[0].results[0].response, "Hello");
+        assert_eq!(parsed[0].timestamp, TIMESTAMP);
+    }
+
+    #[test]
+    fn markdown_reports_failures_with_their_error() {
+        let mut failed = result("b", "timeout", "");
+        failed.error = Some("took too long".to_string());
+        let markdown = response_to_markdown(&entry(
+            "Hi",
+            vec![result("a", "success", "Hello  "), failed],
+        ));
+        assert_eq!(
+            markdown,
+            "# Results for 2024-01-15 10:30:00 UTC\n\n\
+             > Hi\n\n\
+             ## A\n\n**Status:** success\n\nHello\n\n\
+             ## B\n\n**Status:** timeout (failed)\n\n**Error:** took too long\n\n"
+        );
+    }
+}
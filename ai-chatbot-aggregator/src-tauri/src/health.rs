@@ -0,0 +1,186 @@
+use crate::backend::{spawn_env, ActiveBackend, BackendConfig};
+use crate::breaker::{CircuitBreaker, CircuitState};
+use crate::error::AppError;
+use crate::settings::NetworkSettings;
+use crate::ChatBotConfig;
+use futures::future::join_all;
+use serde::Serialize;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+const SELFTEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize)]
+pub struct ChatBotHealth {
+    id: String,
+    reachable: bool,
+    latency_ms: u64,
+    detail: Option<String>,
+    /// Whether dispatch is currently skipping this bot after repeated failures.
+    circuit: CircuitState,
+}
+
+/// A checklist of the pieces needed to spawn the Node backend. Every field is
+/// filled in even when an earlier check fails.
+#[derive(Debug, Serialize)]
+pub struct BackendDiagnostics {
+    node_found: bool,
+    node_version: Option<String>,
+    script_found: bool,
+    script_path: String,
+    selftest_passed: bool,
+    selftest_error: Option<String>,
+}
+
+/// Which backend prompts go through and whether it could run one right now.
+#[derive(Debug, Serialize)]
+pub struct BackendStatus {
+    kind: String,
+    healthy: bool,
+    detail: Option<String>,
+}
+
+/// Checks the active backend with its cheap `ready` probe; nothing is spawned.
+pub fn backend_status(backend: &ActiveBackend) -> BackendStatus {
+    let ready = backend.get().ready();
+    BackendStatus {
+        kind: backend.kind(),
+        healthy: ready.is_ok(),
+        detail: ready.err(),
+    }
+}
+
+/// Runs `node --version`, checks the script exists and invokes it with `--selftest`.
+pub async fn diagnose(backend: &BackendConfig) -> BackendDiagnostics {
+    let script_found = Path::new(&backend.script_path).is_file();
+    let node = backend.node_program();
+
+    let node_version = match &node {
+        Ok(node) => run(node, &["--version"], None).await.ok(),
+        Err(_) => None,
+    };
+    let selftest = match &node {
+        Ok(_) if !script_found => Err(format!("Script not found at '{}'", backend.script_path)),
+        Ok(node) => run(
+            node,
+            &[&backend.script_path, "--selftest"],
+            backend.working_dir.as_deref(),
+        )
+        .await
+        .map(|_| ()),
+        Err(e) => Err(e.clone()),
+    };
+
+    BackendDiagnostics {
+        node_found: node.is_ok(),
+        node_version,
+        script_found,
+        script_path: backend.script_path.clone(),
+        selftest_passed: selftest.is_ok(),
+        selftest_error: selftest.err(),
+    }
+}
+
+/// Runs `program` to completion and returns its trimmed stdout, or why it failed.
+async fn run(program: &Path, args: &[&str], working_dir: Option<&Path>) -> Result<String, String> {
+    let mut command = Command::new(program);
+    if let Some(dir) = working_dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    match tokio::time::timeout(SELFTEST_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(Ok(output)) => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            Err(if stderr.is_empty() {
+                output.status.to_string()
+            } else {
+                stderr
+            })
+        }
+        Ok(Err(e)) => Err(format!("Failed to execute {}: {}", program.display(), e)),
+        Err(_) => Err(format!(
+            "No answer within {} ms",
+            SELFTEST_TIMEOUT.as_millis()
+        )),
+    }
+}
+
+/// Pings every enabled bot concurrently through the backend's `--ping` flag.
+pub async fn check_all(
+    backend: &BackendConfig,
+    chatbots: &[ChatBotConfig],
+    network: &NetworkSettings,
+    breaker: &CircuitBreaker,
+) -> Result<Vec<ChatBotHealth>, AppError> {
+    let env = spawn_env(backend, network);
+    let mut checks = Vec::new();
+    for config in chatbots.iter().filter(|c| c.is_enabled) {
+        let command = backend.node_command().map_err(AppError::BackendSpawn)?;
+        let circuit = breaker.state(&config.id);
+        checks.push(ping(
+            command,
+            &backend.script_path,
+            &env,
+            &config.id,
+            circuit,
+        ));
+    }
+    Ok(join_all(checks).await)
+}
+
+async fn ping(
+    mut command: Command,
+    script_path: &str,
+    env: &[(String, String)],
+    chatbot_id: &str,
+    circuit: CircuitState,
+) -> ChatBotHealth {
+    let started = Instant::now();
+    let output = command
+        .envs(env.iter().cloned())
+        .arg(script_path)
+        .arg("--ping")
+        .arg(chatbot_id)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let (reachable, detail) = match tokio::time::timeout(PING_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => (true, None),
+        Ok(Ok(output)) => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            (
+                false,
+                Some(if stderr.is_empty() {
+                    output.status.to_string()
+                } else {
+                    stderr
+                }),
+            )
+        }
+        Ok(Err(e)) => (false, Some(format!("Failed to execute AI backend: {}", e))),
+        Err(_) => (
+            false,
+            Some(format!("No answer within {} ms", PING_TIMEOUT.as_millis())),
+        ),
+    };
+
+    ChatBotHealth {
+        id: chatbot_id.to_string(),
+        reachable,
+        latency_ms: started.elapsed().as_millis() as u64,
+        detail,
+        circuit,
+    }
+}
@@ -0,0 +1,818 @@
+use crate::error::AppError;
+use crate::metrics::Counters;
+use crate::{stats, ChatBotResponse, PromptResponse};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Schema migrations, applied in order. The database's `user_version` records
+/// how many have already run, so new steps must only ever be appended.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS entries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        prompt TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS responses (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+        chatbot_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        response TEXT NOT NULL,
+        status TEXT NOT NULL,
+        error TEXT,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS responses_entry_id ON responses(entry_id);",
+    "ALTER TABLE responses ADD COLUMN latency_ms INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE responses ADD COLUMN prompt_tokens INTEGER;
+    ALTER TABLE responses ADD COLUMN completion_tokens INTEGER;
+    ALTER TABLE responses ADD COLUMN estimated_cost_usd REAL NOT NULL DEFAULT 0;",
+    "CREATE TABLE IF NOT EXISTS entry_tags (
+        entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (entry_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS entry_tags_tag ON entry_tags(tag);",
+    "ALTER TABLE responses ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE IF NOT EXISTS favorites (
+        entry_id INTEGER NOT NULL REFERENCES entries(id) ON DELETE CASCADE,
+        chatbot_id TEXT NOT NULL,
+        PRIMARY KEY (entry_id, chatbot_id)
+    );",
+    "ALTER TABLE entries ADD COLUMN seed INTEGER;",
+    // Rows written before this keep their text inline in `responses.response`.
+    "CREATE TABLE IF NOT EXISTS response_blobs (
+        hash TEXT PRIMARY KEY,
+        body TEXT NOT NULL
+    );
+    ALTER TABLE responses ADD COLUMN response_hash TEXT REFERENCES response_blobs(hash);",
+    "CREATE TABLE IF NOT EXISTS bot_metrics (
+        chatbot_id TEXT PRIMARY KEY,
+        requests INTEGER NOT NULL,
+        successes INTEGER NOT NULL,
+        failures INTEGER NOT NULL,
+        total_latency_ms INTEGER NOT NULL
+    );",
+    "ALTER TABLE responses ADD COLUMN prompt_transformed INTEGER NOT NULL DEFAULT 0;",
+];
+
+/// An `entries` row: id, prompt, timestamp and seed.
+type EntryRow = (i64, String, i64, Option<i64>);
+
+/// Columns read by `response_row`, from `responses r` left-joined to
+/// `response_blobs b` and `favorites f`.
+const RESPONSE_COLUMNS: &str =
+    "r.chatbot_id, r.name, COALESCE(b.body, r.response), r.status, r.error, r.timestamp,
+    r.latency_ms, r.prompt_tokens, r.completion_tokens, r.estimated_cost_usd, r.truncated,
+    f.entry_id IS NOT NULL, r.prompt_transformed";
+
+/// Totals over the prompts in a time window; see `History::stats`.
+#[derive(Debug, Default, Serialize)]
+pub struct HistoryStats {
+    total_prompts: u64,
+    per_bot: HashMap<String, BotStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BotStats {
+    responses: u64,
+    successes: u64,
+    /// Cancelled answers count as responses but neither succeed nor fail.
+    failures: u64,
+    mean_latency_ms: f64,
+}
+
+/// Upper bound on search results, however broad the query.
+const SEARCH_LIMIT: usize = 200;
+
+/// Prompt/response history stored in a SQLite file. The database is opened,
+/// and its schema created, on first use rather than at startup.
+pub struct History {
+    path: PathBuf,
+    conn: Mutex<Option<Connection>>,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            conn: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, response: &PromptResponse) -> Result<i64, AppError> {
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT INTO entries (prompt, timestamp, seed) VALUES (?1, ?2, ?3)",
+                // Seeds above i64::MAX wrap; the bits round-trip through `load_entries`.
+                params![
+                    response.prompt,
+                    response.timestamp as i64,
+                    response.seed.map(|s| s as i64)
+                ],
+            )?;
+            let entry_id = tx.last_insert_rowid();
+
+            for result in &response.results {
+                // Identical answers (the same bot asked twice, or several bots
+                // agreeing) share one blob; `response` is left empty.
+                let hash = content_hash(&result.response);
+                tx.execute(
+                    "INSERT OR IGNORE INTO response_blobs (hash, body) VALUES (?1, ?2)",
+                    params![hash, result.response],
+                )?;
+                tx.execute(
+                    "INSERT INTO responses (entry_id, chatbot_id, name, response, response_hash, status, error, timestamp,
+                                            latency_ms, prompt_tokens, completion_tokens, estimated_cost_usd, truncated,
+                                            prompt_transformed)
+                     VALUES (?1, ?2, ?3, '', ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    params![
+                        entry_id,
+                        result.id,
+                        result.name,
+                        hash,
+                        result.status,
+                        result.error,
+                        result.timestamp as i64,
+                        result.latency_ms as i64,
+                        result.prompt_tokens.map(|t| t as i64),
+                        result.completion_tokens.map(|t| t as i64),
+                        result.estimated_cost_usd,
+                        result.truncated,
+                        result.prompt_transformed,
+                    ],
+                )?;
+            }
+
+            tx.commit()?;
+            Ok(entry_id)
+        })
+    }
+
+    /// Returns the `limit` most recent entries, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<PromptResponse>, AppError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, prompt, timestamp, seed FROM entries ORDER BY timestamp DESC, id DESC LIMIT ?1",
+            )?;
+            let entries = stmt
+                .query_map(params![limit as i64], entry_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            load_entries(conn, entries)
+        })
+    }
+
+    pub fn entry(&self, id: i64) -> Result<PromptResponse, AppError> {
+        let entries = self.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT id, prompt, timestamp, seed FROM entries WHERE id = ?1")?;
+            let entries = stmt
+                .query_map(params![id], entry_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            load_entries(conn, entries)
+        })?;
+        entries
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::NotFound(format!("No history entry with id {}", id)))
+    }
+
+    /// The prompt of the newest entry, without loading its answers.
+    pub fn latest_prompt(&self) -> Result<Option<String>, AppError> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT prompt FROM entries ORDER BY timestamp DESC, id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+    }
+
+    /// Every entry, newest first.
+    pub fn all(&self) -> Result<Vec<PromptResponse>, AppError> {
+        self.recent(i64::MAX as usize)
+    }
+
+    /// Entries whose prompt contains `query` (case-insensitive, for any
+    /// script; see `register_functions`) and, when
+    /// `tags` is non-empty, that carry at least one of them. Newest first.
+    pub fn search(&self, query: &str, tags: &[String]) -> Result<Vec<PromptResponse>, AppError> {
+        let tags = normalize_tags(tags);
+        self.with_conn(|conn| {
+            let mut sql = String::from(
+                "SELECT id, prompt, timestamp, seed FROM entries WHERE instr(unicode_lower(prompt), unicode_lower(?1)) > 0",
+            );
+            if !tags.is_empty() {
+                let placeholders = vec!["?"; tags.len()].join(", ");
+                sql.push_str(&format!(
+                    " AND id IN (SELECT entry_id FROM entry_tags WHERE tag IN ({}))",
+                    placeholders
+                ));
+            }
+            sql.push_str(&format!(
+                " ORDER BY timestamp DESC, id DESC LIMIT {}",
+                SEARCH_LIMIT
+            ));
+
+            let mut stmt = conn.prepare(&sql)?;
+            let params = std::iter::once(query.trim()).chain(tags.iter().map(String::as_str));
+            let entries = stmt
+                .query_map(params_from_iter(params), entry_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            load_entries(conn, entries)
+        })
+    }
+
+    /// Replaces the tags on an entry. Tags are trimmed and lowercased.
+    pub fn tag(&self, entry_id: i64, tags: &[String]) -> Result<(), AppError> {
+        let tags = normalize_tags(tags);
+        let found = self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            let exists = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM entries WHERE id = ?1)",
+                params![entry_id],
+                |row| row.get::<_, bool>(0),
+            )?;
+            if !exists {
+                return Ok(false);
+            }
+
+            tx.execute(
+                "DELETE FROM entry_tags WHERE entry_id = ?1",
+                params![entry_id],
+            )?;
+            for tag in &tags {
+                tx.execute(
+                    "INSERT INTO entry_tags (entry_id, tag) VALUES (?1, ?2)",
+                    params![entry_id, tag],
+                )?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })?;
+
+        if found {
+            Ok(())
+        } else {
+            Err(AppError::NotFound(format!(
+                "No history entry with id {}",
+                entry_id
+            )))
+        }
+    }
+
+    /// Marks or unmarks one bot's answer in an entry as a favorite.
+    pub fn favorite(
+        &self,
+        entry_id: i64,
+        chatbot_id: &str,
+        favorite: bool,
+    ) -> Result<(), AppError> {
+        let found = self.with_conn(|conn| {
+            let exists = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM responses WHERE entry_id = ?1 AND chatbot_id = ?2)",
+                params![entry_id, chatbot_id],
+                |row| row.get::<_, bool>(0),
+            )?;
+            if !exists {
+                return Ok(false);
+            }
+
+            if favorite {
+                conn.execute(
+                    "INSERT OR IGNORE INTO favorites (entry_id, chatbot_id) VALUES (?1, ?2)",
+                    params![entry_id, chatbot_id],
+                )?;
+            } else {
+                conn.execute(
+                    "DELETE FROM favorites WHERE entry_id = ?1 AND chatbot_id = ?2",
+                    params![entry_id, chatbot_id],
+                )?;
+            }
+            Ok(true)
+        })?;
+
+        if found {
+            Ok(())
+        } else {
+            Err(AppError::NotFound(format!(
+                "No response from '{}' in history entry {}",
+                chatbot_id, entry_id
+            )))
+        }
+    }
+
+    /// Every favorited answer, most recently answered first.
+    pub fn favorites(&self) -> Result<Vec<ChatBotResponse>, AppError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM responses r
+                 LEFT JOIN response_blobs b ON b.hash = r.response_hash
+                 JOIN favorites f ON f.entry_id = r.entry_id AND f.chatbot_id = r.chatbot_id
+                 ORDER BY r.timestamp DESC, r.id DESC",
+                RESPONSE_COLUMNS
+            ))?;
+            let results = stmt.query_map([], response_row)?.collect();
+            results
+        })
+    }
+
+    /// Totals over prompts sent at or after `from_ts` and before `to_ts`, in
+    /// milliseconds since the Unix epoch. An empty window gives zeros.
+    pub fn stats(&self, from_ts: u64, to_ts: u64) -> Result<HistoryStats, AppError> {
+        let bound = |ts: u64| ts.min(i64::MAX as u64) as i64;
+        let window = params![bound(from_ts), bound(to_ts)];
+        self.with_conn(|conn| {
+            let total_prompts: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM entries WHERE timestamp >= ?1 AND timestamp < ?2",
+                window,
+                |row| row.get(0),
+            )?;
+            let mut stmt = conn.prepare(
+                "SELECT r.chatbot_id, COUNT(*),
+                        SUM(r.status = 'success'),
+                        SUM(r.status NOT IN ('success', 'cancelled')),
+                        AVG(r.latency_ms)
+                 FROM responses r JOIN entries e ON e.id = r.entry_id
+                 WHERE e.timestamp >= ?1 AND e.timestamp < ?2
+                 GROUP BY r.chatbot_id",
+            )?;
+            let per_bot = stmt
+                .query_map(window, |row| {
+                    Ok((
+                        row.get(0)?,
+                        BotStats {
+                            responses: row.get::<_, i64>(1)? as u64,
+                            successes: row.get::<_, i64>(2)? as u64,
+                            failures: row.get::<_, i64>(3)? as u64,
+                            mean_latency_ms: row.get(4)?,
+                        },
+                    ))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(HistoryStats {
+                total_prompts: total_prompts as u64,
+                per_bot,
+            })
+        })
+    }
+
+    pub fn load_metrics(&self) -> Result<Vec<(String, Counters)>, AppError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT chatbot_id, requests, successes, failures, total_latency_ms FROM bot_metrics",
+            )?;
+            let metrics = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        Counters {
+                            requests: row.get::<_, i64>(1)? as u64,
+                            successes: row.get::<_, i64>(2)? as u64,
+                            failures: row.get::<_, i64>(3)? as u64,
+                            total_latency_ms: row.get::<_, i64>(4)? as u64,
+                        },
+                    ))
+                })?
+                .collect();
+            metrics
+        })
+    }
+
+    /// Replaces the stored totals of every bot in `metrics`.
+    pub fn save_metrics(&self, metrics: &[(String, Counters)]) -> Result<(), AppError> {
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            for (chatbot_id, c) in metrics {
+                tx.execute(
+                    "INSERT OR REPLACE INTO bot_metrics (chatbot_id, requests, successes, failures, total_latency_ms)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        chatbot_id,
+                        c.requests as i64,
+                        c.successes as i64,
+                        c.failures as i64,
+                        c.total_latency_ms as i64
+                    ],
+                )?;
+            }
+            tx.commit()
+        })
+    }
+
+    pub fn clear_metrics(&self) -> Result<(), AppError> {
+        self.with_conn(|conn| conn.execute_batch("DELETE FROM bot_metrics;"))
+    }
+
+    pub fn clear(&self) -> Result<(), AppError> {
+        self.with_conn(|conn| {
+            conn.execute_batch(
+                "DELETE FROM favorites; DELETE FROM entry_tags; DELETE FROM responses; DELETE FROM entries;
+                 DELETE FROM response_blobs;",
+            )
+        })
+    }
+
+    /// Deletes entries sent before `before_ts` and all but the newest
+    /// `max_entries`, returning how many entries went. Entries with a
+    /// favorited answer are always kept and don't count towards the limit.
+    /// `vacuum` gives the freed space back to the file system.
+    pub fn prune(
+        &self,
+        max_entries: Option<usize>,
+        before_ts: Option<u64>,
+        vacuum: bool,
+    ) -> Result<usize, AppError> {
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            let mut removed = 0;
+            if let Some(ts) = before_ts {
+                removed += tx.execute(
+                    "DELETE FROM entries WHERE timestamp < ?1
+                     AND id NOT IN (SELECT entry_id FROM favorites)",
+                    params![ts.min(i64::MAX as u64) as i64],
+                )?;
+            }
+            if let Some(max) = max_entries {
+                removed += tx.execute(
+                    "DELETE FROM entries WHERE id NOT IN (SELECT entry_id FROM favorites)
+                     AND id NOT IN (
+                         SELECT id FROM entries WHERE id NOT IN (SELECT entry_id FROM favorites)
+                         ORDER BY timestamp DESC, id DESC LIMIT ?1
+                     )",
+                    params![max.min(i64::MAX as usize) as i64],
+                )?;
+            }
+            // Responses, tags and favorites go with their entry by cascade;
+            // bodies are shared, so only unreferenced ones can go.
+            tx.execute(
+                "DELETE FROM response_blobs WHERE hash NOT IN
+                 (SELECT response_hash FROM responses WHERE response_hash IS NOT NULL)",
+                [],
+            )?;
+            tx.commit()?;
+            if vacuum {
+                conn.execute_batch("VACUUM;")?;
+            }
+            Ok(removed)
+        })
+    }
+
+    fn with_conn<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, AppError> {
+        let mut guard = self
+            .conn
+            .lock()
+            .map_err(|_| AppError::Storage("History database lock poisoned".to_string()))?;
+
+        if guard.is_none() {
+            *guard = Some(self.open().map_err(AppError::Storage)?);
+        }
+
+        let conn = guard.as_ref().expect("connection was just opened");
+        f(conn).map_err(|e| AppError::Storage(format!("History database error: {}", e)))
+    }
+
+    fn open(&self) -> Result<Connection, String> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create history directory: {}", e))?;
+        }
+
+        let conn = Connection::open(&self.path)
+            .map_err(|e| format!("Failed to open history database: {}", e))?;
+        register_functions(&conn)
+            .map_err(|e| format!("Failed to set up history database: {}", e))?;
+        migrate(&conn).map_err(|e| format!("Failed to create history schema: {}", e))?;
+        Ok(conn)
+    }
+}
+
+/// Adds `unicode_lower(text)`, which lowercases with Rust's Unicode rules.
+/// SQLite's own `lower()` only folds ASCII, so "ÉCOLE" wouldn't match "école".
+fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "unicode_lower",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(ctx.get::<Option<String>>(0)?.map(|s| s.to_lowercase())),
+    )
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index as i64 + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Lowercase hex SHA-256 of `s`, the key of its `response_blobs` row.
+pub fn content_hash(s: &str) -> String {
+    Sha256::digest(s.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn entry_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<EntryRow> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+}
+
+fn load_entries(
+    conn: &Connection,
+    entries: Vec<EntryRow>,
+) -> rusqlite::Result<Vec<PromptResponse>> {
+    entries
+        .into_iter()
+        .map(|(id, prompt, timestamp, seed)| {
+            Ok(PromptResponse {
+                request_id: String::new(),
+                prompt,
+                results: load_results(conn, id)?,
+                timestamp: timestamp as u64,
+                diagnostics: None,
+                history_id: Some(id),
+                tags: load_tags(conn, id)?,
+                seed: seed.map(|s| s as u64),
+                cancelled: Vec::new(),
+                dry_run: None,
+                possible_duplicate: false,
+                replay_of: None,
+            })
+        })
+        .collect()
+}
+
+fn load_tags(conn: &Connection, entry_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT tag FROM entry_tags WHERE entry_id = ?1 ORDER BY tag")?;
+    let tags = stmt
+        .query_map(params![entry_id], |row| row.get(0))?
+        .collect();
+    tags
+}
+
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+fn load_results(conn: &Connection, entry_id: i64) -> rusqlite::Result<Vec<ChatBotResponse>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM responses r
+         LEFT JOIN response_blobs b ON b.hash = r.response_hash
+         LEFT JOIN favorites f ON f.entry_id = r.entry_id AND f.chatbot_id = r.chatbot_id
+         WHERE r.entry_id = ?1 ORDER BY r.id",
+        RESPONSE_COLUMNS
+    ))?;
+    let results = stmt.query_map(params![entry_id], response_row)?.collect();
+    results
+}
+
+fn response_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ChatBotResponse> {
+    let response: String = row.get(2)?;
+    Ok(ChatBotResponse {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        char_count: stats::char_count(&response),
+        word_count: stats::word_count(&response),
+        response,
+        status: row.get(3)?,
+        error: row.get(4)?,
+        timestamp: row.get::<_, i64>(5)? as u64,
+        latency_ms: row.get::<_, i64>(6)? as u64,
+        prompt_tokens: row.get::<_, Option<i64>>(7)?.map(|t| t as u64),
+        completion_tokens: row.get::<_, Option<i64>>(8)?.map(|t| t as u64),
+        estimated_cost_usd: row.get(9)?,
+        duplicate_of: None,
+        truncated: row.get(10)?,
+        from_cache: false,
+        favorite: row.get(11)?,
+        encoding_warning: false,
+        attachments: Vec::new(),
+        detected_language: None,
+        normalized: false,
+        prompt_transformed: row.get(12)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A history in an in-memory database, migrated like a file would be.
+    fn history() -> History {
+        History::new(PathBuf::from(":memory:"))
+    }
+
+    fn entry(prompt: &str, timestamp: u64, answers: &[(&str, &str)]) -> PromptResponse {
+        PromptResponse {
+            prompt: prompt.to_string(),
+            timestamp,
+            results: answers
+                .iter()
+                .map(|(id, response)| ChatBotResponse {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    response: response.to_string(),
+                    status: "success".to_string(),
+                    timestamp,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn count(history: &History, table: &str) -> i64 {
+        history
+            .with_conn(|conn| {
+                conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn content_hash_is_hex_sha256() {
+        assert_eq!(
+            content_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(content_hash("abc"), content_hash("abc"));
+        assert_ne!(content_hash("abc"), content_hash("abd"));
+    }
+
+    #[test]
+    fn identical_answers_share_one_blob() {
+        let history = history();
+        history
+            .record(&entry("Hi", 1, &[("a", "Hello"), ("b", "Hello")]))
+            .unwrap();
+        history
+            .record(&entry("Hi again", 2, &[("a", "Hello")]))
+            .unwrap();
+        history
+            .record(&entry("Bye", 3, &[("a", "Goodbye")]))
+            .unwrap();
+
+        assert_eq!(count(&history, "responses"), 4);
+        assert_eq!(count(&history, "response_blobs"), 2);
+        let recent = history.recent(10).unwrap();
+        assert_eq!(recent[1].results[0].response, "Hello");
+        assert_eq!(recent[2].results[1].response, "Hello");
+    }
+
+    #[test]
+    fn prompt_transforms_are_kept_without_an_error() {
+        let history = history();
+        let mut response = entry("Hi", 1, &[("a", "Hello"), ("b", "Hey")]);
+        response.results[0].prompt_transformed = true;
+        let id = history.record(&response).unwrap();
+
+        let stored = history.entry(id).unwrap();
+        assert_eq!(stored.prompt, "Hi");
+        assert!(stored.results[0].prompt_transformed);
+        assert_eq!(stored.results[0].error, None);
+        assert!(!stored.results[1].prompt_transformed);
+    }
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn schema(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY name")
+            .unwrap();
+        let sql = stmt.query_map([], |row| row.get(0)).unwrap();
+        sql.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn migrations_run_once_and_in_order() {
+        let fresh = Connection::open_in_memory().unwrap();
+        migrate(&fresh).unwrap();
+        assert_eq!(user_version(&fresh), MIGRATIONS.len() as i64);
+        let migrated = schema(&fresh);
+        migrate(&fresh).unwrap();
+        assert_eq!(user_version(&fresh), MIGRATIONS.len() as i64);
+        assert_eq!(schema(&fresh), migrated);
+
+        // A database left behind by the first release picks up the rest.
+        let old = Connection::open_in_memory().unwrap();
+        old.execute_batch(MIGRATIONS[0]).unwrap();
+        old.pragma_update(None, "user_version", 1).unwrap();
+        old.execute(
+            "INSERT INTO entries (prompt, timestamp) VALUES ('kept', 1)",
+            [],
+        )
+        .unwrap();
+        migrate(&old).unwrap();
+        assert_eq!(user_version(&old), MIGRATIONS.len() as i64);
+        assert_eq!(schema(&old), migrated);
+        let prompt: String = old
+            .query_row("SELECT prompt FROM entries", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(prompt, "kept");
+    }
+
+    fn prompts(entries: &[PromptResponse]) -> Vec<&str> {
+        entries.iter().map(|e| e.prompt.as_str()).collect()
+    }
+
+    #[test]
+    fn prune_keeps_favorites_and_the_newest_entries() {
+        let history = history();
+        let mut ids = Vec::new();
+        for (prompt, ts) in [("one", 10), ("two", 20), ("three", 30), ("four", 40)] {
+            let answer = format!("answer {}", prompt);
+            ids.push(
+                history
+                    .record(&entry(prompt, ts, &[("a", &answer)]))
+                    .unwrap(),
+            );
+        }
+        history.favorite(ids[0], "a", true).unwrap();
+
+        // "one" is older than the cutoff but a favorite.
+        assert_eq!(history.prune(None, Some(25), false).unwrap(), 1);
+        assert_eq!(prompts(&history.all().unwrap()), ["four", "three", "one"]);
+
+        // The favorite doesn't count towards the limit.
+        assert_eq!(history.prune(Some(1), None, true).unwrap(), 1);
+        assert_eq!(prompts(&history.all().unwrap()), ["four", "one"]);
+        assert_eq!(count(&history, "response_blobs"), 2);
+        assert_eq!(history.favorites().unwrap()[0].response, "answer one");
+
+        assert_eq!(history.prune(Some(0), Some(u64::MAX), false).unwrap(), 1);
+        assert_eq!(prompts(&history.all().unwrap()), ["one"]);
+    }
+
+    #[test]
+    fn search_ignores_case_in_any_script() {
+        let history = history();
+        history
+            .record(&entry("Une ÉCOLE à Paris", 1, &[("a", "x")]))
+            .unwrap();
+        history
+            .record(&entry("МОСКВА и école", 2, &[("a", "x")]))
+            .unwrap();
+        history.record(&entry("Ecole", 3, &[("a", "x")])).unwrap();
+
+        let found = history.search(" école ", &[]).unwrap();
+        assert_eq!(prompts(&found), ["МОСКВА и école", "Une ÉCOLE à Paris"]);
+        let found = history.search("москва", &[]).unwrap();
+        assert_eq!(prompts(&found), ["МОСКВА и école"]);
+        assert!(history.search("schule", &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_filters_by_any_tag() {
+        let history = history();
+        let rust = history
+            .record(&entry("Rust lifetimes", 1, &[("a", "x")]))
+            .unwrap();
+        let go = history
+            .record(&entry("Go lifetimes", 2, &[("a", "x")]))
+            .unwrap();
+        history
+            .record(&entry("C lifetimes", 3, &[("a", "x")]))
+            .unwrap();
+        history.tag(rust, &[" Work ".to_string()]).unwrap();
+        history
+            .tag(go, &["home".to_string(), "work".to_string()])
+            .unwrap();
+
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let found = history.search("lifetimes", &tags(&["WORK"])).unwrap();
+        assert_eq!(prompts(&found), ["Go lifetimes", "Rust lifetimes"]);
+        let found = history.search("", &tags(&["home", "other"])).unwrap();
+        assert_eq!(prompts(&found), ["Go lifetimes"]);
+        assert_eq!(history.search("lifetimes", &[]).unwrap().len(), 3);
+        assert!(history.tag(999, &tags(&["work"])).is_err());
+    }
+}
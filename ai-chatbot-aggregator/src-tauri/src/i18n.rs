@@ -0,0 +1,319 @@
+use crate::error::AppError;
+use std::sync::RwLock;
+
+/// Locales with a catalog; the first is the fallback.
+pub const LOCALES: &[&str] = &["en", "vi"];
+
+/// (locale, template) pairs.
+type Translations = &'static [(&'static str, &'static str)];
+
+/// Error messages by id. Each English template is what the code already
+/// writes, so an error is localized by matching its text against them; `{}`
+/// marks an argument, filled into the translation in the same order.
+const CATALOG: &[(&str, &str, Translations)] = &[
+    (
+        "backend_spawn_failed",
+        "Failed to execute AI backend: {}",
+        &[("vi", "Không thể chạy AI backend: {}")],
+    ),
+    (
+        "backend_no_response",
+        "AI backend returned no response",
+        &[("vi", "AI backend không trả về phản hồi nào")],
+    ),
+    (
+        "unknown_chatbot",
+        "Unknown chatbot '{}'",
+        &[("vi", "Không tìm thấy chatbot '{}'")],
+    ),
+    (
+        "chatbot_exists",
+        "A chatbot with id '{}' already exists",
+        &[("vi", "Đã có chatbot với id '{}'")],
+    ),
+    (
+        "empty_chatbot_id",
+        "Chatbot id must not be empty",
+        &[("vi", "Id chatbot không được để trống")],
+    ),
+    (
+        "empty_prompt",
+        "Prompt must not be empty",
+        &[("vi", "Câu hỏi không được để trống")],
+    ),
+    (
+        "prompt_too_long",
+        "Prompt is {} characters long; the limit is {}",
+        &[("vi", "Câu hỏi dài {} ký tự; giới hạn là {}")],
+    ),
+    (
+        "no_chatbots_selected",
+        "Select at least one chatbot or capability",
+        &[("vi", "Hãy chọn ít nhất một chatbot hoặc khả năng")],
+    ),
+    (
+        "conversation_not_found",
+        "No conversation with id {}",
+        &[("vi", "Không có cuộc hội thoại với id {}")],
+    ),
+    (
+        "history_entry_not_found",
+        "No history entry with id {}",
+        &[("vi", "Không có mục lịch sử với id {}")],
+    ),
+    (
+        "queue_stopped",
+        "Prompt queue stopped",
+        &[("vi", "Hàng đợi câu hỏi đã dừng")],
+    ),
+    (
+        "setup_spawn_failed",
+        "Failed to setup sessions: {}",
+        &[("vi", "Không thể thiết lập phiên: {}")],
+    ),
+    (
+        "setup_failed",
+        "Session setup error: {}",
+        &[("vi", "Lỗi thiết lập phiên: {}")],
+    ),
+    (
+        "file_not_found",
+        "No file at {}",
+        &[("vi", "Không có tệp tại {}")],
+    ),
+    (
+        "write_failed",
+        "Failed to write {}: {}",
+        &[("vi", "Không thể ghi {}: {}")],
+    ),
+];
+
+static LOCALE: RwLock<&str> = RwLock::new("en");
+
+/// Switches the locale messages are produced in. `None` detects it from the
+/// OS; an unsupported detected locale falls back to English.
+pub fn set_locale(lang: Option<&str>) -> Result<&'static str, AppError> {
+    let locale = match lang {
+        Some(lang) => supported(lang).ok_or_else(|| {
+            AppError::Validation(format!(
+                "Unsupported locale '{}'; expected one of {}",
+                lang,
+                LOCALES.join(", ")
+            ))
+        })?,
+        None => detect(),
+    };
+    *LOCALE.write().unwrap_or_else(|e| e.into_inner()) = locale;
+    Ok(locale)
+}
+
+pub fn locale() -> &'static str {
+    *LOCALE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The catalog id and localized text for `message`; uncatalogued messages
+/// come back unchanged with no id.
+pub fn localize(message: &str) -> (Option<&'static str>, String) {
+    localize_in(locale(), message)
+}
+
+fn localize_in(locale: &str, message: &str) -> (Option<&'static str>, String) {
+    for (code, english, translations) in CATALOG {
+        let Some(args) = match_template(english, message) else {
+            continue;
+        };
+        let text = translations
+            .iter()
+            .find(|(lang, _)| *lang == locale)
+            .map_or_else(|| message.to_string(), |(_, t)| fill(t, &args));
+        return (Some(code), text);
+    }
+    (None, message.to_string())
+}
+
+/// The supported locale `lang` names, if any: "vi", "vi-VN" and "vi_VN.UTF-8"
+/// all mean "vi".
+pub fn supported(lang: &str) -> Option<&'static str> {
+    let lang = lang
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    LOCALES.iter().copied().find(|l| *l == lang)
+}
+
+fn detect() -> &'static str {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| supported(&value))
+        .unwrap_or(LOCALES[0])
+}
+
+/// The arguments `template` was filled with to produce `message`, if it was.
+fn match_template<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let mut pieces = template.split("{}");
+    let first = pieces.next().unwrap_or_default();
+    let mut rest = message.strip_prefix(first)?;
+    let pieces: Vec<&str> = pieces.collect();
+    let Some((last, middle)) = pieces.split_last() else {
+        return rest.is_empty().then(Vec::new);
+    };
+
+    let mut args = Vec::with_capacity(pieces.len());
+    for piece in middle {
+        let at = rest.find(piece)?;
+        args.push(&rest[..at]);
+        rest = &rest[at + piece.len()..];
+    }
+    args.push(rest.strip_suffix(last)?);
+    Some(args)
+}
+
+fn fill(template: &str, args: &[&str]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut pieces = template.split("{}");
+    out.push_str(pieces.next().unwrap_or_default());
+    for piece in pieces {
+        out.push_str(args.next().copied().unwrap_or_default());
+        out.push_str(piece);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::ConfigBundle;
+    use crate::chatbots::ChatbotStore;
+    use crate::conversations::ConversationStore;
+    use crate::history::History;
+    use crate::queue::PromptQueue;
+    use crate::ratelimit::RateLimiter;
+    use crate::{persist, validation, PromptRequest};
+    use std::path::PathBuf;
+
+    /// One error per catalog entry, raised by the code that raises it in the app.
+    fn raised() -> Vec<(&'static str, AppError)> {
+        let dir = std::env::temp_dir().join(format!("i18n-{}", uuid::Uuid::new_v4()));
+        let chatbots = ChatbotStore::load(dir.join("chatbots.json"));
+        let existing = chatbots.list().remove(0);
+        let request = |prompt: &str, chatbots: &[&str]| PromptRequest {
+            prompt: prompt.to_string(),
+            chatbots: chatbots.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        };
+        let (queue, jobs) = PromptQueue::new();
+        drop(jobs);
+        // Renaming a file over a directory fails.
+        std::fs::create_dir_all(dir.join("taken.json")).unwrap();
+
+        let errors = vec![
+            ("unknown_chatbot", chatbots.get("nobody").unwrap_err()),
+            ("chatbot_exists", chatbots.add(existing).unwrap_err()),
+            (
+                "empty_chatbot_id",
+                RateLimiter::default().set(" ", 1).unwrap_err(),
+            ),
+            (
+                "empty_prompt",
+                validation::validate_request(&request(" ", &["a"]), &[], 100).unwrap_err(),
+            ),
+            (
+                "prompt_too_long",
+                validation::validate_request(&request("Hello", &["a"]), &[], 3).unwrap_err(),
+            ),
+            (
+                "no_chatbots_selected",
+                validation::validate_request(&request("Hello", &[]), &[], 100).unwrap_err(),
+            ),
+            (
+                "conversation_not_found",
+                ConversationStore::default().clear("c1").unwrap_err(),
+            ),
+            (
+                "history_entry_not_found",
+                History::new(PathBuf::from(":memory:"))
+                    .entry(7)
+                    .unwrap_err(),
+            ),
+            (
+                "queue_stopped",
+                queue.enqueue(request("Hello", &["a"])).unwrap_err(),
+            ),
+            (
+                "file_not_found",
+                ConfigBundle::read(&dir.join("missing.json")).unwrap_err(),
+            ),
+            (
+                "write_failed",
+                AppError::Storage(persist::write_json(&dir.join("taken.json"), &1).unwrap_err()),
+            ),
+            // Only a backend process failing raises these, so they are written
+            // the way the spawning code writes them.
+            (
+                "backend_spawn_failed",
+                AppError::BackendSpawn("Failed to execute AI backend: No such file".to_string()),
+            ),
+            (
+                "backend_no_response",
+                AppError::BackendExit("AI backend returned no response".to_string()),
+            ),
+            (
+                "setup_spawn_failed",
+                AppError::BackendSpawn("Failed to setup sessions: No such file".to_string()),
+            ),
+            (
+                "setup_failed",
+                AppError::BackendExit("Session setup error: browser crashed".to_string()),
+            ),
+        ];
+        let _ = std::fs::remove_dir_all(dir);
+        errors
+    }
+
+    #[test]
+    fn every_catalog_entry_matches_the_error_it_is_for() {
+        let raised = raised();
+        for (code, _, _) in CATALOG {
+            assert!(
+                raised.iter().any(|(id, _)| id == code),
+                "no error raised for {code}"
+            );
+        }
+        for (code, error) in &raised {
+            let message = error.message();
+            assert_eq!(
+                localize_in("en", message),
+                (Some(*code), message.to_string())
+            );
+            let (id, translated) = localize_in("vi", message);
+            assert_eq!(id, Some(*code), "{message}");
+            assert_ne!(translated, message);
+            assert!(!translated.contains("{}"), "{translated}");
+        }
+    }
+
+    #[test]
+    fn arguments_are_carried_into_the_translation() {
+        let (_, translated) = localize_in("vi", "Prompt is 5 characters long; the limit is 3");
+        assert_eq!(translated, "Câu hỏi dài 5 ký tự; giới hạn là 3");
+    }
+
+    #[test]
+    fn unknown_messages_pass_through_unchanged() {
+        for message in ["Something else went wrong", "", "Prompt must not be empty!"] {
+            assert_eq!(localize_in("vi", message), (None, message.to_string()));
+        }
+    }
+
+    #[test]
+    fn locales_are_read_from_their_language_part() {
+        for lang in ["vi", "vi-VN", "vi_VN.UTF-8", "VI@latin"] {
+            assert_eq!(supported(lang), Some("vi"));
+        }
+        assert_eq!(supported("fr_FR"), None);
+    }
+}
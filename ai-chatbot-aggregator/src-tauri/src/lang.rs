@@ -0,0 +1,219 @@
+/// Fewer letters than this are too little to tell languages apart.
+const MIN_LETTERS: usize = 3;
+
+/// Frequent short words of the Latin-script languages `detect` tells apart
+/// by counting them.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "of", "to", "in", "that", "it", "you", "for", "with",
+            "this", "not",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "les", "et", "est", "des", "une", "que", "pas", "pour", "dans", "vous", "il",
+            "sont", "du",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "mit", "sie", "ich",
+            "den", "auf",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "es", "que", "en", "una", "por", "para", "con", "no", "del",
+            "está",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "as", "e", "é", "que", "em", "um", "uma", "para", "com", "não", "do", "da",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "gli", "e", "è", "di", "che", "un", "una", "per", "con", "non", "del", "sono",
+            "della",
+        ],
+    ),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    /// Hiragana or katakana, which only Japanese uses.
+    Kana,
+    /// A script that, for our purposes, means a single language.
+    Of(&'static str),
+}
+
+/// ISO 639-1 code of the language `text` is most likely in, or `None` when
+/// there is too little text or nothing to go on. Non-Latin scripts decide by
+/// themselves (Cyrillic is reported as Russian); Latin text is told apart by
+/// Vietnamese letters and common words. Cheap enough to run on every answer.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for script in text.chars().filter_map(script) {
+        match counts.iter_mut().find(|(s, _)| *s == script) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+    // Japanese mixes kana into mostly Han text. It takes a tenth of that to
+    // be kana, and most of the letters to be either, so a kana word quoted
+    // in another language doesn't make it Japanese.
+    let count = |script: Script| {
+        counts
+            .iter()
+            .find(|(s, _)| *s == script)
+            .map_or(0, |(_, n)| *n)
+    };
+    let kana = count(Script::Kana);
+    let japanese = kana + count(Script::Of("zh"));
+    if kana > 0 && kana * 10 >= japanese && japanese * 2 >= counts.iter().map(|(_, n)| n).sum() {
+        return Some("ja");
+    }
+    let (script, letters) = counts.into_iter().max_by_key(|(_, n)| *n)?;
+    if letters < MIN_LETTERS {
+        return None;
+    }
+    match script {
+        Script::Latin => latin(text, letters),
+        Script::Kana => Some("ja"),
+        Script::Of(code) => Some(code),
+    }
+}
+
+fn latin(text: &str, letters: usize) -> Option<&'static str> {
+    let vietnamese = text.chars().filter(|c| is_vietnamese(*c)).count();
+    if vietnamese * 10 >= letters {
+        return Some("vi");
+    }
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut best = None;
+    let mut best_hits = 0;
+    for (code, stopwords) in STOPWORDS {
+        let hits = words
+            .iter()
+            .filter(|w| stopwords.contains(&w.as_str()))
+            .count();
+        if hits > best_hits {
+            best = Some(*code);
+            best_hits = hits;
+        }
+    }
+    best
+}
+
+fn script(c: char) -> Option<Script> {
+    if !c.is_alphabetic() {
+        return None;
+    }
+    Some(match c as u32 {
+        0x3040..=0x30FF => Script::Kana,
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Of("ko"),
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF => Script::Of("zh"),
+        0x0370..=0x03FF => Script::Of("el"),
+        0x0400..=0x04FF => Script::Of("ru"),
+        0x0590..=0x05FF => Script::Of("he"),
+        0x0600..=0x06FF => Script::Of("ar"),
+        0x0900..=0x097F => Script::Of("hi"),
+        0x0E00..=0x0E7F => Script::Of("th"),
+        0x0041..=0x024F | 0x1E00..=0x1EFF => Script::Latin,
+        _ => return None,
+    })
+}
+
+/// Letters other Latin-script languages rarely or never use: ă, đ, ơ, ư and
+/// the stacked tone marks of Latin Extended Additional.
+fn is_vietnamese(c: char) -> bool {
+    matches!(c.to_lowercase().next(), Some('ă' | 'đ' | 'ơ' | 'ư'))
+        || matches!(c as u32, 0x1EA0..=0x1EF9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_latin_languages_by_their_words() {
+        let samples = [
+            ("en", "The cache is cleared when you restart the app."),
+            (
+                "fr",
+                "Le cache est vidé quand vous redémarrez les applications.",
+            ),
+            (
+                "de",
+                "Der Cache wird nicht geleert, wenn die App neu startet.",
+            ),
+            (
+                "es",
+                "La caché se vacía cuando reinicias la aplicación con el botón.",
+            ),
+            (
+                "pt",
+                "O cache é limpo quando você reinicia o aplicativo com um clique.",
+            ),
+            (
+                "it",
+                "La cache si svuota quando riavvii l'app, non prima della chiusura.",
+            ),
+            ("vi", "Bộ nhớ đệm được xóa khi bạn khởi động lại ứng dụng."),
+        ];
+        for (code, text) in samples {
+            assert_eq!(detect(text), Some(code), "{}", text);
+        }
+    }
+
+    #[test]
+    fn detects_other_scripts() {
+        let samples = [
+            ("ja", "キャッシュはアプリを再起動すると消去されます。"),
+            ("zh", "重新启动应用程序时会清除缓存。"),
+            ("ko", "앱을 다시 시작하면 캐시가 지워집니다."),
+            ("ru", "Кэш очищается при перезапуске приложения."),
+            ("el", "Η προσωρινή μνήμη διαγράφεται κατά την επανεκκίνηση."),
+            ("ar", "يتم مسح ذاكرة التخزين المؤقت عند إعادة التشغيل."),
+            ("he", "המטמון נמחק כאשר מפעילים מחדש את היישום."),
+            ("hi", "ऐप को पुनः प्रारंभ करने पर कैश साफ़ हो जाता है।"),
+            ("th", "แคชจะถูกล้างเมื่อคุณรีสตาร์ทแอป"),
+        ];
+        for (code, text) in samples {
+            assert_eq!(detect(text), Some(code), "{}", text);
+        }
+    }
+
+    #[test]
+    fn a_quoted_word_does_not_change_the_language() {
+        assert_eq!(
+            detect("In Japanese, thank you is ありがとう and that is all you need."),
+            Some("en")
+        );
+        assert_eq!(
+            detect("The Russian word for yes is да, and it is short."),
+            Some("en")
+        );
+    }
+
+    #[test]
+    fn gives_up_on_too_little_text() {
+        assert_eq!(detect(""), None);
+        assert_eq!(detect("ok"), None);
+        assert_eq!(detect("```\n42 + 17\n```"), None);
+        assert_eq!(detect("Xyzzy plugh"), None);
+    }
+}
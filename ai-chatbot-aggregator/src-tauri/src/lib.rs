@@ -1,34 +1,310 @@
+mod activity;
+mod backend;
+mod batch;
+mod benchmark;
+mod breaker;
+mod bundle;
+mod cache;
+mod capabilities;
+mod capture;
+mod chatbots;
+mod children;
+mod conversations;
+mod dedup;
+mod diff;
+mod dispatch;
+mod error;
+mod event_server;
+mod events;
+mod export;
+mod health;
+mod history;
+mod i18n;
+mod lang;
+mod logging;
+mod metrics;
+mod models;
+mod persist;
+mod post_process;
+mod preflight;
+mod pricing;
+mod queue;
+mod rank;
+mod ratelimit;
+mod response_files;
+mod retry;
+mod schema;
+mod score;
+mod secrets;
+mod selection;
+mod settings;
+mod setup;
+mod sort;
+mod state;
+mod stats;
+mod stream_file;
+mod summary;
+mod templates;
+mod tokenize;
+mod truncate;
+mod validation;
+
+use activity::{Activity, RequestInfo};
+use backend::{ActiveBackend, BackendConfig, DryRunCommand};
+use benchmark::BenchmarkResult;
+use breaker::CircuitBreaker;
+use bundle::ConfigBundle;
+use cache::ResponseCache;
+use capabilities::{BotCapabilities, CapabilityCache};
+use chatbots::{ChatbotStore, ImportSummary};
+use children::ChildRegistry;
+use conversations::{ConversationStore, Message, Role};
+use diff::DiffHunk;
+use dispatch::{dispatch_prompt, ConcurrencyLimit, DispatchContext};
+use error::AppError;
+use event_server::EventServer;
+use health::{BackendDiagnostics, BackendStatus, ChatBotHealth};
+use history::{History, HistoryStats};
+use logging::Logging;
+use metrics::{BotMetrics, Metrics};
+use models::ModelCatalog;
+use post_process::PostProcessors;
+use preflight::Preflight;
+use queue::{PromptQueue, QueueStatus};
+use rank::RankedResponse;
+use ratelimit::RateLimiter;
+use response_files::{ResponseAttachment, ResponseFiles};
+use secrets::{SecretStoreKind, Secrets};
+use selection::SelectionStore;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use settings::{HistoryRetention, NetworkSettings, Settings, SettingsStore};
+use setup::{SessionStatus, Sessions, SetupSummary};
+use state::AppState;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::ipc::Invoke;
+use tauri::{AppHandle, Manager, RunEvent, State, WindowEvent};
+use templates::{Template, TemplateStore};
+use tokenize::TokenCount;
+use uuid::Uuid;
+use validation::ConfigValidationIssue;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct ChatBotResponse {
     id: String,
     name: String,
     response: String,
     status: String,
     error: Option<String>,
+    /// Milliseconds since the Unix epoch; see [`now_millis`].
     timestamp: u64,
+    /// Wall-clock time until this bot's answer was received. Taken from the
+    /// backend when it reports it, otherwise measured from process spawn.
+    #[serde(default)]
+    latency_ms: u64,
+    /// Token usage as reported by the backend; `None` when it doesn't report any.
+    #[serde(default)]
+    prompt_tokens: Option<u64>,
+    #[serde(default)]
+    completion_tokens: Option<u64>,
+    /// Derived from the usage via `pricing`; 0.0 when usage or the model is unknown.
+    #[serde(default)]
+    estimated_cost_usd: f64,
+    /// Set when `deduplicate` was requested and this answer is a near-copy of that bot's.
+    #[serde(default)]
+    duplicate_of: Option<String>,
+    /// Whether `response` was cut to the request's `max_response_chars`.
+    #[serde(default)]
+    truncated: bool,
+    /// Served from the response cache instead of asking the bot again.
+    #[serde(default)]
+    from_cache: bool,
+    /// Marked as a keeper via `favorite_response`; only ever set on history entries.
+    #[serde(default)]
+    favorite: bool,
+    /// Length of `response` in characters, after any truncation.
+    #[serde(default)]
+    char_count: usize,
+    /// Words in `response`; see `stats::word_count` for how unspaced scripts count.
+    #[serde(default)]
+    word_count: usize,
+    /// The backend's output for this bot wasn't valid UTF-8; the bad bytes
+    /// were replaced with U+FFFD. Not kept in history.
+    #[serde(default)]
+    encoding_warning: bool,
+    /// Images or files the bot generated. Not kept in history.
+    #[serde(default)]
+    attachments: Vec<ResponseAttachment>,
+    /// ISO 639-1 code of the answer's language, e.g. "vi"; see `lang::detect`.
+    /// Only set on successful answers while `Settings::detect_language` is on.
+    /// Not kept in history.
+    #[serde(default)]
+    detected_language: Option<String>,
+    /// Line endings or trailing whitespace in `response` were changed; see
+    /// `post_process::normalize_whitespace`. Not kept in history.
+    #[serde(default)]
+    normalized: bool,
+    /// The bot was sent the prompt wrapped in its `prompt_prefix` and
+    /// `prompt_suffix`; history keeps the prompt as the user wrote it.
+    #[serde(default)]
+    prompt_transformed: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 struct PromptRequest {
     prompt: String,
     chatbots: Vec<String>,
+    /// Per bot; `Settings::default_timeout_ms` when omitted.
+    timeout_ms: Option<u64>,
+    /// Lets the caller pick the id used with `cancel_prompt`; generated when omitted.
+    request_id: Option<String>,
+    /// Retries per bot after a spawn failure or non-zero exit; defaults to 2.
+    max_retries: Option<u32>,
+    /// Continues a conversation started with `new_conversation`, sending its earlier turns along.
+    conversation_id: Option<String>,
+    /// Annotates near-identical answers with `duplicate_of`; off by default.
+    deduplicate: Option<bool>,
+    /// Reports the backend commands in `PromptResponse.dry_run` instead of running them.
+    dry_run: Option<bool>,
+    /// Caps each answer's length in characters so huge responses can't stall the UI.
+    max_response_chars: Option<usize>,
+    /// Always asks the bots, ignoring and not filling the response cache.
+    skip_cache: Option<bool>,
+    /// Standing instruction such as "Answer concisely in Markdown"; the
+    /// configured default when omitted. Blank counts as omitted.
+    system_prompt: Option<String>,
+    /// Sends to every enabled bot with all of these capabilities, narrowed to
+    /// `chatbots` when that is non-empty.
+    #[serde(default)]
+    require_capabilities: Vec<String>,
+    /// Passed to bots that can sample deterministically; noted in `error` by the rest.
+    seed: Option<u64>,
+    /// "markdown", "plain" or "json". With "json", answers that don't parse
+    /// get the status "invalid_json".
+    response_format: Option<String>,
+    /// "all" (the default) waits for every bot; "race" keeps the first
+    /// successful answer and cancels the others.
+    mode: Option<String>,
+    /// Acknowledges sending to more than `max_selected_bots` bots at once.
+    confirm_large_batch: Option<bool>,
+    /// When a bot fails because its session expired, sets the session up
+    /// again and retries that bot once.
+    auto_resetup: Option<bool>,
+    /// "latency", "config", "status" or "name"; the backend's order when omitted.
+    sort_by: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// A JSON Schema successful answers must parse and conform to; those that
+    /// don't get the status "schema_violation". Any draft `jsonschema` supports.
+    response_schema: Option<serde_json::Value>,
+    /// Label of the only window that gets this prompt's events; every window
+    /// does when omitted. Closing that window cancels the prompt.
+    event_window: Option<String>,
+}
+
+/// A prompt given as turns instead of one string, for automation. Bots whose
+/// backend can't take earlier turns, such as a custom command without a
+/// `{context}` placeholder, just get the last message as a plain prompt.
+#[derive(Debug, Deserialize)]
+struct StructuredPrompt {
+    system: Option<String>,
+    /// Oldest first; the last one is the user turn being asked.
+    messages: Vec<Message>,
+    /// Answers are asked for as JSON and checked against this, as with
+    /// `PromptRequest::response_schema`.
+    schema: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A local file sent along with a prompt, to bots whose backend can take files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Attachment {
+    filename: String,
+    mime: String,
+    path: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct PromptResponse {
+    #[serde(default)]
+    request_id: String,
+    #[serde(default)]
+    prompt: String,
     results: Vec<ChatBotResponse>,
+    /// Milliseconds since the Unix epoch; see [`now_millis`].
     timestamp: u64,
+    /// Everything the backend printed to stderr, prefixed by bot id; kept even
+    /// when every bot succeeded so warnings aren't lost.
+    #[serde(default)]
+    diagnostics: Option<String>,
+    /// Row id in the history database, used with `tag_history_entry`.
+    #[serde(default)]
+    history_id: Option<i64>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// The request's seed, echoed back and kept in history for reproducibility.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// Bots stopped because another answered first in "race" mode; `results`
+    /// then only holds the winner.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cancelled: Vec<String>,
+    /// Only set for dry runs, which leave `results` empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dry_run: Option<Vec<DryRunCommand>>,
+    /// The prompt repeats the one before it in history; it was sent anyway.
+    /// Not kept in history.
+    #[serde(default)]
+    possible_duplicate: bool,
+    /// The history entry this one re-asked; see `replay_history_entry`. Not
+    /// kept in history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replay_of: Option<i64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ChatBotConfig {
     id: String,
     name: String,
     url: String,
     is_enabled: bool,
+    /// Model override, e.g. "gpt-4o"; the backend's default when omitted.
+    model: Option<String>,
+    /// Extra sampling options such as temperature or max_tokens, passed through as-is.
+    params: Option<serde_json::Value>,
+    /// Position in the list and in prompt results, lowest first; see `reorder_chatbots`.
+    #[serde(default)]
+    order: u32,
+    /// Free-form tags such as "vision" that prompts can select bots by.
+    #[serde(default)]
+    capabilities: Vec<String>,
+    /// Runs this program instead of the Node backend, e.g.
+    /// `my-bot --ask {prompt}`; see `CommandTemplate` for the placeholders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command_template: Option<String>,
+    /// Extra headers on requests the app makes itself, e.g. `X-Org`. An
+    /// `Authorization` header here replaces the stored API key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    headers: Option<HashMap<String, String>>,
+    /// Milliseconds since the Unix epoch until which prompts skip this bot,
+    /// reporting it as "snoozed"; see `snooze_chatbot`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disabled_until: Option<u64>,
+    /// Text sent before and after every prompt to this bot only, each
+    /// separated from the prompt by a blank line; empty means none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prompt_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prompt_suffix: Option<String>,
+}
+
+/// A configured chatbot plus whether it is part of the user's saved selection.
+#[derive(Debug, Serialize)]
+struct ChatBotListEntry {
+    #[serde(flatten)]
+    config: ChatBotConfig,
+    selected: bool,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -37,85 +313,1644 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Waits for any prompts queued before this one, then runs it.
+#[tauri::command]
+async fn send_prompt_to_chatbots(
+    state: State<'_, AppState>,
+    request: PromptRequest,
+) -> Result<PromptResponse, AppError> {
+    state.queue.submit(request).await
+}
+
+/// Sends `prompt` to `chatbots` through the queue like any other. Its earlier
+/// turns are held in a conversation that lasts only as long as the request.
+#[tauri::command]
+async fn send_structured_prompt(
+    state: State<'_, AppState>,
+    prompt: StructuredPrompt,
+    chatbots: Vec<String>,
+) -> Result<PromptResponse, AppError> {
+    let StructuredPrompt {
+        system,
+        mut messages,
+        schema,
+    } = prompt;
+    let last = match messages.pop() {
+        Some(last) if last.role == Role::User => last,
+        _ => {
+            return Err(AppError::Validation(
+                "The last message must be the user's".to_string(),
+            ))
+        }
+    };
+    let conversation_id = state.conversations.start(messages);
+    let request = PromptRequest {
+        prompt: last.content,
+        chatbots,
+        conversation_id: Some(conversation_id.clone()),
+        system_prompt: system,
+        response_format: schema.as_ref().map(|_| "json".to_string()),
+        response_schema: schema,
+        ..Default::default()
+    };
+    let response = state.queue.submit(request).await;
+    let _ = state.conversations.clear(&conversation_id);
+    response
+}
+
+/// Queues a prompt and returns its request id at once; the outcome arrives
+/// as a `prompt-result` event.
+#[tauri::command]
+async fn enqueue_prompt(
+    state: State<'_, AppState>,
+    request: PromptRequest,
+) -> Result<String, AppError> {
+    state.queue.enqueue(request)
+}
+
+#[tauri::command]
+async fn get_queue_status(state: State<'_, AppState>) -> Result<QueueStatus, AppError> {
+    Ok(state.queue.status())
+}
+
+#[tauri::command]
+async fn run_prompt_batch(
+    app: AppHandle,
+    prompts: Vec<String>,
+    chatbots: Vec<String>,
+    stop_on_error: bool,
+) -> Result<Vec<PromptResponse>, AppError> {
+    batch::run_batch(&app, prompts, chatbots, stop_on_error).await
+}
+
+/// Validates, dispatches and records one prompt: everything behind
+/// `send_prompt_to_chatbots`, shared with batch runs.
+async fn run_prompt(
+    app: &AppHandle,
+    mut request: PromptRequest,
+) -> Result<PromptResponse, AppError> {
+    let state = app.state::<AppState>();
+    let history = &state.history;
+    let conversations = &state.conversations;
+
+    let chatbots = state.chatbots.list();
+    let settings = state.settings.get();
+    resolve_request(&state, &mut request, &chatbots, &settings)?;
+    // Counted after capability expansion, which is where a surprise fan-out comes from.
+    if request.chatbots.len() > settings.max_selected_bots
+        && !request.confirm_large_batch.unwrap_or(false)
+    {
+        return Err(AppError::Validation(format!(
+            "{} chatbots selected, more than the limit of {}; set confirm_large_batch to send anyway",
+            request.chatbots.len(),
+            settings.max_selected_bots
+        )));
+    }
+
+    let request_id = request
+        .request_id
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let ctx = DispatchContext::new(app, &chatbots);
+    if request.dry_run.unwrap_or(false) {
+        return Ok(PromptResponse {
+            request_id: request_id.clone(),
+            prompt: request.prompt.clone(),
+            results: Vec::new(),
+            timestamp: now_millis(),
+            diagnostics: None,
+            history_id: None,
+            tags: Vec::new(),
+            seed: request.seed,
+            cancelled: Vec::new(),
+            dry_run: Some(dispatch::dry_run(&ctx, &request, &request_id)?),
+            possible_duplicate: false,
+            replay_of: None,
+        });
+    }
+    // Read before dispatch, which is when a slow duplicate would be recorded.
+    let possible_duplicate = match history.latest_prompt() {
+        Ok(latest) => latest.is_some_and(|latest| dedup::same_words(&latest, &request.prompt)),
+        Err(e) => {
+            tracing::warn!("Failed to check for a repeated prompt: {}", e);
+            false
+        }
+    };
+    let mut response = dispatch_prompt(&ctx, &request, &request_id).await?;
+    response.possible_duplicate = possible_duplicate;
+    if request.auto_resetup.unwrap_or(false) {
+        setup::retry_expired(&ctx, &request, &request_id, &mut response.results).await;
+    }
+    if request.deduplicate.unwrap_or(false) {
+        dedup::annotate_duplicates(&mut response.results);
+    }
+    if let Some(by) = &request.sort_by {
+        sort::sort_results(&mut response.results, by, &chatbots);
+    }
+
+    if let Some(id) = &request.conversation_id {
+        conversations.record(id, &response.prompt, &response.results);
+    }
+
+    // History is best-effort: a broken database must never cost the user their answers.
+    match history.record(&response) {
+        Ok(id) => response.history_id = Some(id),
+        Err(e) => tracing::error!("Failed to record prompt history: {}", e),
+    }
+
+    Ok(response)
+}
+
+/// Validates `request` and settles what it will be sent with: the system
+/// prompt and the enabled bots it ends up addressing.
+fn resolve_request(
+    state: &AppState,
+    request: &mut PromptRequest,
+    chatbots: &[ChatBotConfig],
+    settings: &Settings,
+) -> Result<(), AppError> {
+    validation::validate_request(request, chatbots, settings.max_prompt_chars)?;
+    if let Some(id) = &request.conversation_id {
+        state.conversations.require(id)?;
+    }
+    request.system_prompt = system_prompt(request.system_prompt.take(), settings);
+
+    if !request.require_capabilities.is_empty() {
+        let matching = chatbots::with_capabilities(chatbots, &request.require_capabilities);
+        request.chatbots = if request.chatbots.is_empty() {
+            matching
+        } else {
+            matching
+                .into_iter()
+                .filter(|id| request.chatbots.contains(id))
+                .collect()
+        };
+        if request.chatbots.is_empty() {
+            return Err(AppError::Validation(format!(
+                "No enabled chatbot has all of: {}",
+                request.require_capabilities.join(", ")
+            )));
+        }
+    }
+
+    // The frontend's selection is only a request; disabled bots are never queried.
+    request
+        .chatbots
+        .retain(|id| chatbots.iter().any(|c| &c.id == id && c.is_enabled));
+    if request.chatbots.is_empty() {
+        return Err(AppError::Validation(
+            "no enabled chatbots selected".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Estimated input tokens and cost per bot for `request`, without sending
+/// it. Invalid requests fail here just as they would when sent.
+#[tauri::command]
+async fn preflight(
+    state: State<'_, AppState>,
+    mut request: PromptRequest,
+) -> Result<Preflight, AppError> {
+    let chatbots = state.chatbots.list();
+    resolve_request(&state, &mut request, &chatbots, &state.settings.get())?;
+    Ok(preflight::estimate(
+        &request,
+        &chatbots,
+        &state.conversations,
+    ))
+}
+
+/// Sends `request` and returns the backends' stdout unparsed, for when the
+/// output format changed and parsing fails. Nothing is recorded; see
+/// `dispatch::dispatch_raw` for which secrets are masked.
+#[tauri::command]
+async fn send_prompt_raw(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    mut request: PromptRequest,
+) -> Result<String, AppError> {
+    let chatbots = state.chatbots.list();
+    resolve_request(&state, &mut request, &chatbots, &state.settings.get())?;
+    let request_id = request
+        .request_id
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let ctx = DispatchContext::new(&app, &chatbots);
+    dispatch::dispatch_raw(&ctx, &request, &request_id).await
+}
+
+/// Re-runs one bot through the same dispatch path as a batch, e.g. after a poor answer.
+#[tauri::command]
+async fn regenerate_chatbot(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    prompt: String,
+    chatbot_id: String,
+    conversation_id: Option<String>,
+) -> Result<ChatBotResponse, AppError> {
+    let chatbots = state.chatbots.list();
+    let mut request = PromptRequest {
+        prompt,
+        chatbots: vec![chatbot_id],
+        conversation_id,
+        // A regenerate that returned the cached answer would be pointless.
+        skip_cache: Some(true),
+        ..Default::default()
+    };
+    let settings = state.settings.get();
+    validation::validate_request(&request, &chatbots, settings.max_prompt_chars)?;
+    request.system_prompt = system_prompt(None, &settings);
+    if let Some(id) = &request.conversation_id {
+        state.conversations.require(id)?;
+    }
+
+    let request_id = Uuid::new_v4().to_string();
+    let ctx = DispatchContext::new(&app, &chatbots);
+    let response = dispatch_prompt(&ctx, &request, &request_id).await?;
+    response
+        .results
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::BackendExit("AI backend returned no response".to_string()))
+}
+
+/// Sends `prompt` to `chatbot_id` alone and writes the answer to `path` as it
+/// streams in, so a long one survives a crash. The usual per-bot events are
+/// emitted; pass `request_id` to be able to stop it with `cancel_prompt`.
+/// Nothing is recorded in history.
+#[tauri::command]
+async fn stream_to_file(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    prompt: String,
+    chatbot_id: String,
+    path: String,
+    request_id: Option<String>,
+) -> Result<ChatBotResponse, AppError> {
+    let chatbots = state.chatbots.list();
+    let mut request = PromptRequest {
+        prompt,
+        chatbots: vec![chatbot_id.clone()],
+        skip_cache: Some(true),
+        ..Default::default()
+    };
+    let settings = state.settings.get();
+    validation::validate_request(&request, &chatbots, settings.max_prompt_chars)?;
+    request.system_prompt = system_prompt(None, &settings);
+
+    let request_id = request_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let ctx = DispatchContext::new(&app, &chatbots);
+    stream_file::stream_to_file(&ctx, &request, &request_id, &chatbot_id, &path).await
+}
+
+/// Asks the bots of history entry `entry_id` its prompt again, with its seed,
+/// and records the answers as a new entry. Bots that have since been removed
+/// are skipped and listed in `diagnostics`.
+#[tauri::command]
+async fn replay_history_entry(
+    state: State<'_, AppState>,
+    entry_id: i64,
+) -> Result<PromptResponse, AppError> {
+    let entry = state.history.entry(entry_id)?;
+    let chatbots = state.chatbots.list();
+    let (kept, removed): (Vec<String>, Vec<String>) = entry
+        .results
+        .into_iter()
+        .map(|r| r.id)
+        .partition(|id| chatbots.iter().any(|c| &c.id == id));
+    if kept.is_empty() {
+        return Err(AppError::Validation(format!(
+            "None of the bots of history entry {} are configured any more",
+            entry_id
+        )));
+    }
+
+    let request = PromptRequest {
+        prompt: entry.prompt,
+        chatbots: kept,
+        // Comparing with today's answers is the point.
+        skip_cache: Some(true),
+        seed: entry.seed,
+        ..Default::default()
+    };
+    let mut response = state.queue.submit(request).await?;
+    response.replay_of = Some(entry_id);
+    if !removed.is_empty() {
+        let note = format!("Skipped bots no longer configured: {}", removed.join(", "));
+        response.diagnostics = Some(match response.diagnostics.take() {
+            Some(diagnostics) => format!("{}\n{}", diagnostics, note),
+            None => note,
+        });
+    }
+    Ok(response)
+}
+
+/// Asks `response`'s unsuccessful bots `prompt` again and merges their new
+/// answers in place of the old ones; successful answers are kept as they are.
+#[tauri::command]
+async fn retry_failed(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    mut response: PromptResponse,
+    prompt: String,
+) -> Result<PromptResponse, AppError> {
+    let failed: Vec<String> = response
+        .results
+        .iter()
+        .filter(|r| r.status != "success")
+        .map(|r| r.id.clone())
+        .collect();
+    if failed.is_empty() {
+        return Ok(response);
+    }
+
+    let chatbots = state.chatbots.list();
+    let mut request = PromptRequest {
+        prompt,
+        chatbots: failed,
+        // The failures may well be cached; they're what is being retried.
+        skip_cache: Some(true),
+        seed: response.seed,
+        ..Default::default()
+    };
+    let settings = state.settings.get();
+    validation::validate_request(&request, &chatbots, settings.max_prompt_chars)?;
+    request.system_prompt = system_prompt(None, &settings);
+
+    let ctx = DispatchContext::new(&app, &chatbots);
+    let retried = dispatch_prompt(&ctx, &request, &response.request_id).await?;
+    for fresh in retried.results {
+        match response.results.iter_mut().find(|r| r.id == fresh.id) {
+            Some(old) => *old = fresh,
+            None => response.results.push(fresh),
+        }
+    }
+    if let Some(more) = retried.diagnostics {
+        response.diagnostics = Some(match response.diagnostics.take() {
+            Some(old) => format!("{}\n{}", old, more),
+            None => more,
+        });
+    }
+    response.timestamp = retried.timestamp;
+    // The merged answers are no longer the recorded history row.
+    response.history_id = None;
+    Ok(response)
+}
+
+/// Sends `sample_prompt` to `config` as given, without saving it, so a new
+/// or edited bot can be tried out first. A failed try comes back as the
+/// response's status and error; nothing is kept in history or metrics.
+#[tauri::command]
+async fn test_chatbot_config(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    config: ChatBotConfig,
+    sample_prompt: String,
+) -> Result<ChatBotResponse, AppError> {
+    validation::validate_chatbot(&config)?;
+    let chatbot_id = config.id.clone();
+    let chatbots = vec![config];
+    let request = PromptRequest {
+        prompt: sample_prompt,
+        chatbots: vec![chatbot_id.clone()],
+        max_retries: Some(0),
+        skip_cache: Some(true),
+        system_prompt: system_prompt(None, &state.settings.get()),
+        ..Default::default()
+    };
+    validation::validate_request(&request, &chatbots, state.settings.get().max_prompt_chars)?;
+
+    let scratch = Metrics::default();
+    let ctx = DispatchContext {
+        metrics: &scratch,
+        ..DispatchContext::new(&app, &chatbots)
+    };
+    let request_id = Uuid::new_v4().to_string();
+    dispatch::dispatch_one(&ctx, &request, &request_id, &chatbot_id).await
+}
+
+/// Times `iterations` runs of a trivial prompt against the first enabled bot,
+/// to see what spawning a backend per request costs. Runs skip the cache and
+/// retries, and aren't kept in metrics.
+#[tauri::command]
+async fn benchmark_backend(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    iterations: u32,
+) -> Result<BenchmarkResult, AppError> {
+    if !(1..=benchmark::MAX_ITERATIONS).contains(&iterations) {
+        return Err(AppError::Validation(format!(
+            "Iterations must be between 1 and {}",
+            benchmark::MAX_ITERATIONS
+        )));
+    }
+    let chatbots = state.chatbots.list();
+    let chatbot_id = chatbots
+        .iter()
+        .find(|c| c.is_enabled)
+        .map(|c| c.id.clone())
+        .ok_or_else(|| AppError::Validation("No enabled chatbot to benchmark".to_string()))?;
+    let request = PromptRequest {
+        prompt: "Reply with OK.".to_string(),
+        chatbots: vec![chatbot_id.clone()],
+        max_retries: Some(0),
+        skip_cache: Some(true),
+        ..Default::default()
+    };
+
+    let scratch = Metrics::default();
+    let ctx = DispatchContext {
+        metrics: &scratch,
+        ..DispatchContext::new(&app, &chatbots)
+    };
+    benchmark::run(&ctx, &request, &chatbot_id, iterations).await
+}
+
+#[tauri::command]
+async fn summarize_responses(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    response: PromptResponse,
+) -> Result<ChatBotResponse, AppError> {
+    let chatbots = state.chatbots.list();
+    let summarizer = state.settings.get().summarizer;
+    if !chatbots.iter().any(|c| c.id == summarizer) {
+        return Err(AppError::NotFound(format!(
+            "Unknown summarizer chatbot: {}",
+            summarizer
+        )));
+    }
+
+    let ctx = DispatchContext::new(&app, &chatbots);
+    summary::summarize(&ctx, &response, &summarizer).await
+}
+
+/// Orders the successful answers in `response` by how well they meet
+/// `criteria`, as judged by the bot set in `Settings::ranker`.
+#[tauri::command]
+async fn rank_responses(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    response: PromptResponse,
+    criteria: String,
+) -> Result<Vec<RankedResponse>, AppError> {
+    if criteria.trim().is_empty() {
+        return Err(AppError::Validation(
+            "Ranking criteria must not be empty".to_string(),
+        ));
+    }
+    let chatbots = state.chatbots.list();
+    let ranker = state.settings.get().ranker;
+    if !chatbots.iter().any(|c| c.id == ranker) {
+        return Err(AppError::NotFound(format!(
+            "Unknown ranking chatbot: {}",
+            ranker
+        )));
+    }
+
+    let ctx = DispatchContext::new(&app, &chatbots);
+    rank::rank(&ctx, &response, &criteria, &ranker).await
+}
+
+/// Compares two answers line by line, from `a` to `b`.
 #[tauri::command]
-async fn send_prompt_to_chatbots(request: PromptRequest) -> Result<PromptResponse, String> {
-    // Execute the Node.js script to handle AI interactions
-    let output = Command::new("node")
-        .arg("ai-backend.js")
-        .arg("--prompt")
-        .arg(&request.prompt)
-        .arg("--chatbots")
-        .arg(request.chatbots.join(","))
-        .output()
-        .map_err(|e| format!("Failed to execute AI backend: {}", e))?;
+async fn diff_responses(a: ChatBotResponse, b: ChatBotResponse) -> Result<Vec<DiffHunk>, AppError> {
+    Ok(diff::diff_lines(&a.response, &b.response))
+}
+
+/// Scores each bot's answer against a gold `reference`, 0.0 to 1.0.
+#[tauri::command]
+async fn score_responses(
+    response: PromptResponse,
+    reference: String,
+) -> Result<Vec<(String, f64)>, AppError> {
+    Ok(score::score_all(&response.results, &reference))
+}
+
+/// Approximate size of `prompt` for `model`, to check it against context
+/// limits before sending.
+#[tauri::command]
+async fn count_prompt_tokens(prompt: String, model: String) -> Result<TokenCount, AppError> {
+    Ok(tokenize::count(&prompt, &model))
+}
 
-    if output.status.success() {
-        let response_str = String::from_utf8_lossy(&output.stdout);
-        let response: PromptResponse = serde_json::from_str(&response_str)
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-        Ok(response)
+#[tauri::command]
+async fn cancel_prompt(state: State<'_, AppState>, request_id: String) -> Result<(), AppError> {
+    let request_id = state.queue.coalesced_id(&request_id);
+    if state.children.cancel(&request_id).await {
+        Ok(())
     } else {
-        let error_str = String::from_utf8_lossy(&output.stderr);
-        Err(format!("AI backend error: {}", error_str))
-    }
-}
-
-#[tauri::command]
-async fn get_chatbots_list() -> Result<Vec<ChatBotConfig>, String> {
-    Ok(vec![
-        ChatBotConfig {
-            id: "chatgpt".to_string(),
-            name: "ChatGPT".to_string(),
-            url: "https://chat.openai.com".to_string(),
-            is_enabled: true,
-        },
-        ChatBotConfig {
-            id: "claude".to_string(),
-            name: "Claude".to_string(),
-            url: "https://claude.ai".to_string(),
-            is_enabled: true,
-        },
-        ChatBotConfig {
-            id: "gemini".to_string(),
-            name: "Gemini".to_string(),
-            url: "https://gemini.google.com".to_string(),
-            is_enabled: true,
-        },
-        ChatBotConfig {
-            id: "perplexity".to_string(),
-            name: "Perplexity".to_string(),
-            url: "https://www.perplexity.ai".to_string(),
-            is_enabled: true,
-        },
-    ])
-}
-
-#[tauri::command]
-async fn setup_chatbot_sessions() -> Result<String, String> {
-    let output = Command::new("node")
-        .arg("ai-backend.js")
-        .arg("--setup-sessions")
-        .output()
-        .map_err(|e| format!("Failed to setup sessions: {}", e))?;
-
-    if output.status.success() {
-        Ok("Sessions setup completed".to_string())
+        Err(AppError::NotFound(format!(
+            "No running prompt with id {}",
+            request_id
+        )))
+    }
+}
+
+/// Stops every prompt in flight; each still resolves with the answers it had.
+#[tauri::command]
+async fn cancel_all(state: State<'_, AppState>) -> Result<usize, AppError> {
+    Ok(state.children.cancel_all().await)
+}
+
+/// Kills every backend process still running, e.g. one stuck in a bad state,
+/// including the persistent backend's; the next call starts a fresh one.
+/// Returns how many were killed.
+#[tauri::command]
+async fn kill_all_backend_processes(state: State<'_, AppState>) -> Result<usize, AppError> {
+    let restarted = state.backend.get().restart().await;
+    let killed = state.children.kill_running().await + usize::from(restarted);
+    tracing::info!(killed, "killed all backend processes");
+    Ok(killed)
+}
+
+/// Stops the persistent backend's process so the next call starts a fresh
+/// one, e.g. after changing the script. Calls it was answering fall back to
+/// a process of their own. Returns whether one was running.
+#[tauri::command]
+async fn restart_backend(state: State<'_, AppState>) -> Result<bool, AppError> {
+    let restarted = state.backend.get().restart().await;
+    tracing::info!(restarted, "restarted backend");
+    Ok(restarted)
+}
+
+#[tauri::command]
+async fn new_conversation(state: State<'_, AppState>) -> Result<String, AppError> {
+    Ok(state.conversations.create())
+}
+
+#[tauri::command]
+async fn clear_conversation(state: State<'_, AppState>, id: String) -> Result<(), AppError> {
+    state.conversations.clear(&id)
+}
+
+#[tauri::command]
+async fn set_max_concurrency(state: State<'_, AppState>, n: usize) -> Result<(), AppError> {
+    state.limit.set(n)
+}
+
+#[tauri::command]
+async fn set_rate_limit(
+    state: State<'_, AppState>,
+    id: String,
+    per_minute: u32,
+) -> Result<(), AppError> {
+    state.rate_limiter.set(&id, per_minute)
+}
+
+/// Skips a bot for `cooldown_secs` after `threshold` consecutive failures;
+/// a threshold of 0 never skips.
+#[tauri::command]
+async fn set_circuit_breaker(
+    state: State<'_, AppState>,
+    threshold: u32,
+    cooldown_secs: u64,
+) -> Result<(), AppError> {
+    state.settings.update(|s| {
+        s.circuit_threshold = threshold;
+        s.circuit_cooldown_secs = cooldown_secs;
+    })?;
+    state
+        .breaker
+        .configure(threshold, Duration::from_secs(cooldown_secs));
+    Ok(())
+}
+
+/// Lets prompts reach `id` again at once, forgetting its failures.
+#[tauri::command]
+async fn reset_circuit(state: State<'_, AppState>, id: String) -> Result<(), AppError> {
+    state.breaker.reset(&id);
+    Ok(())
+}
+
+#[tauri::command]
+async fn clear_cache(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.cache.clear();
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_cache_enabled(state: State<'_, AppState>, enabled: bool) -> Result<(), AppError> {
+    state.cache.set_enabled(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_cache_ttl(state: State<'_, AppState>, seconds: u64) -> Result<(), AppError> {
+    state.cache.set_ttl(seconds)
+}
+
+#[tauri::command]
+async fn set_backend(state: State<'_, AppState>, kind: String) -> Result<(), AppError> {
+    let backend = backend::create(
+        &kind,
+        state.backend_config.clone(),
+        state.secrets.clone(),
+        &state.settings.get().network,
+    )?;
+    state.settings.update(|s| s.backend = kind.clone())?;
+    state.backend.set(&kind, backend);
+    Ok(())
+}
+
+/// The backend chosen with `set_backend`, and whether it is ready to run.
+#[tauri::command]
+async fn get_backend_status(state: State<'_, AppState>) -> Result<BackendStatus, AppError> {
+    Ok(health::backend_status(&state.backend))
+}
+
+/// What each configured bot supports, for enabling the matching controls;
+/// see `BotCapabilities`. Built once per set of bots and backend.
+#[tauri::command]
+async fn get_capability_matrix(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<BotCapabilities>, AppError> {
+    let chatbots = state.chatbots.list();
+    let ctx = DispatchContext::new(&app, &chatbots);
+    Ok(state.capabilities.matrix(&ctx, &state.backend.kind()))
+}
+
+/// Sends all backend traffic, HTTP and HTTPS alike, through `url`; `None`
+/// goes back to connecting directly. Takes effect for the next prompt.
+#[tauri::command]
+async fn set_proxy(state: State<'_, AppState>, url: Option<String>) -> Result<(), AppError> {
+    let url = url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    if let Some(url) = &url {
+        validation::validate_proxy_url(url)?;
+    }
+    let network = NetworkSettings {
+        http_proxy: url.clone(),
+        https_proxy: url,
+        ..state.settings.get().network
+    };
+    set_network(&state, network)
+}
+
+/// The `User-Agent` backend traffic is sent with; see `ClientIdentity`.
+/// Takes effect for the next prompt.
+#[tauri::command]
+async fn set_user_agent(state: State<'_, AppState>, user_agent: String) -> Result<(), AppError> {
+    let user_agent = user_agent.trim().to_string();
+    validation::validate_client_identity("user agent", &user_agent)?;
+    let mut network = state.settings.get().network;
+    network.client_identity.user_agent = user_agent;
+    set_network(&state, network)
+}
+
+/// An id sent along with backend traffic for providers that ask for one;
+/// `None` sends none.
+#[tauri::command]
+async fn set_client_id(
+    state: State<'_, AppState>,
+    client_id: Option<String>,
+) -> Result<(), AppError> {
+    let client_id = client_id.map(|id| id.trim().to_string());
+    if let Some(id) = &client_id {
+        validation::validate_client_identity("client id", id)?;
+    }
+    let mut network = state.settings.get().network;
+    network.client_identity.client_id = client_id;
+    set_network(&state, network)
+}
+
+/// Saves `network` and rebuilds the active backend with it.
+fn set_network(state: &AppState, network: NetworkSettings) -> Result<(), AppError> {
+    let kind = state.backend.kind();
+    let backend = backend::create(
+        &kind,
+        state.backend_config.clone(),
+        state.secrets.clone(),
+        &network,
+    )?;
+    state.settings.update(|s| s.network = network)?;
+    state.backend.set(&kind, backend);
+    Ok(())
+}
+
+/// Replaces the extra variables set on every Node backend process.
+#[tauri::command]
+async fn set_backend_env(
+    state: State<'_, AppState>,
+    vars: HashMap<String, String>,
+) -> Result<(), AppError> {
+    for (name, value) in &vars {
+        validation::validate_env_var(name, value)?;
+    }
+    state.settings.update(|s| s.backend_env = vars.clone())?;
+    state.backend_config.set_env(vars);
+    // A persistent backend process only reads its environment once.
+    let kind = state.backend.kind();
+    if kind == "node-persistent" {
+        let backend = backend::create(
+            &kind,
+            state.backend_config.clone(),
+            state.secrets.clone(),
+            &state.settings.get().network,
+        )?;
+        state.backend.set(&kind, backend);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_proxy(state: State<'_, AppState>) -> Result<NetworkSettings, AppError> {
+    Ok(state.settings.get().network)
+}
+
+#[tauri::command]
+async fn store_api_key(
+    state: State<'_, AppState>,
+    provider: String,
+    key: String,
+) -> Result<SecretStoreKind, AppError> {
+    state.secrets.store(&provider, &key)
+}
+
+#[tauri::command]
+async fn has_api_key(state: State<'_, AppState>, provider: String) -> Result<bool, AppError> {
+    Ok(state.secrets.has(&provider))
+}
+
+#[tauri::command]
+async fn get_secret_store(state: State<'_, AppState>) -> Result<SecretStoreKind, AppError> {
+    Ok(state.secrets.kind())
+}
+
+#[tauri::command]
+async fn get_log_path(state: State<'_, AppState>) -> Result<String, AppError> {
+    state
+        .logging
+        .path()
+        .map(|p| p.to_string_lossy().into_owned())
+}
+
+#[tauri::command]
+async fn set_log_level(state: State<'_, AppState>, level: String) -> Result<(), AppError> {
+    state.logging.set_level(&level)?;
+    tracing::info!(level = %level, "log level changed");
+    Ok(())
+}
+
+/// Sets the default system prompt; `None` or blank removes it.
+#[tauri::command]
+async fn set_system_prompt(
+    state: State<'_, AppState>,
+    prompt: Option<String>,
+) -> Result<(), AppError> {
+    state
+        .settings
+        .update(|s| s.system_prompt = prompt.filter(|p| !p.trim().is_empty()))
+}
+
+/// Sets the transformations applied to every answer, run in the given order,
+/// e.g. `["strip_code_fences", "trim_whitespace"]`. Empty turns them off.
+#[tauri::command]
+async fn set_post_processors(
+    state: State<'_, AppState>,
+    names: Vec<String>,
+) -> Result<(), AppError> {
+    state.post_processors.set(&names)?;
+    state.settings.update(|s| s.post_processors = names)
+}
+
+#[tauri::command]
+async fn get_system_prompt(state: State<'_, AppState>) -> Result<Option<String>, AppError> {
+    Ok(state.settings.get().system_prompt)
+}
+
+#[tauri::command]
+async fn set_max_prompt_length(
+    state: State<'_, AppState>,
+    max_chars: usize,
+) -> Result<(), AppError> {
+    state.settings.update(|s| s.max_prompt_chars = max_chars)
+}
+
+/// Sets the language of error messages, e.g. "vi"; `None` follows the OS.
+/// Returns the locale now in use.
+#[tauri::command]
+async fn set_locale(state: State<'_, AppState>, lang: Option<String>) -> Result<String, AppError> {
+    let locale = i18n::set_locale(lang.as_deref())?;
+    state.settings.update(|s| s.locale = lang)?;
+    Ok(locale.to_string())
+}
+
+/// Sets the timeout for requests that don't carry one; 0 turns it off.
+#[tauri::command]
+async fn set_default_timeout(state: State<'_, AppState>, ms: u64) -> Result<(), AppError> {
+    state.settings.update(|s| s.default_timeout_ms = ms)
+}
+
+/// Turns on `post_process::normalize_whitespace` for every answer.
+#[tauri::command]
+async fn set_normalize_responses(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<(), AppError> {
+    state.settings.update(|s| s.normalize_responses = enabled)
+}
+
+#[tauri::command]
+async fn set_detect_language(state: State<'_, AppState>, enabled: bool) -> Result<(), AppError> {
+    state.settings.update(|s| s.detect_language = enabled)
+}
+
+#[tauri::command]
+async fn get_default_timeout(state: State<'_, AppState>) -> Result<u64, AppError> {
+    Ok(state.settings.get().default_timeout_ms)
+}
+
+/// Caps the output kept from one backend process; see `Settings::max_output_bytes`.
+#[tauri::command]
+async fn set_max_output_bytes(state: State<'_, AppState>, bytes: usize) -> Result<(), AppError> {
+    if bytes == 0 {
+        return Err(AppError::Validation(
+            "Max output bytes must be at least 1".to_string(),
+        ));
+    }
+    state.settings.update(|s| s.max_output_bytes = bytes)
+}
+
+#[tauri::command]
+async fn set_max_selected_bots(state: State<'_, AppState>, n: usize) -> Result<(), AppError> {
+    if n == 0 {
+        return Err(AppError::Validation(
+            "Max selected bots must be at least 1".to_string(),
+        ));
+    }
+    state.settings.update(|s| s.max_selected_bots = n)
+}
+
+/// Prompts in flight, then the most recently finished ones.
+#[tauri::command]
+async fn list_requests(state: State<'_, AppState>) -> Result<Vec<RequestInfo>, AppError> {
+    Ok(state.activity.list())
+}
+
+/// Success and latency counts per bot, across restarts.
+#[tauri::command]
+async fn get_metrics(state: State<'_, AppState>) -> Result<Vec<BotMetrics>, AppError> {
+    state.metrics.snapshot(&state.history)
+}
+
+#[tauri::command]
+async fn reset_metrics(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.metrics.reset(&state.history)
+}
+
+/// Models `id` can be set to, listed by the backend once per session.
+#[tauri::command]
+async fn get_chatbot_models(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<Vec<String>, AppError> {
+    let config = state.chatbots.get(&id)?;
+    state
+        .models
+        .models(
+            &state.backend_config,
+            &state.settings.get().network,
+            &config,
+        )
+        .await
+}
+
+/// Pushes every `chatbot-response` event to WebSocket clients on
+/// `ws://127.0.0.1:<port>`; port 0 picks a free one. Returns the port used.
+#[tauri::command]
+async fn start_event_server(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    port: u16,
+) -> Result<u16, AppError> {
+    state.event_server.start(&app, port).await
+}
+
+#[tauri::command]
+async fn stop_event_server(app: AppHandle, state: State<'_, AppState>) -> Result<(), AppError> {
+    if state.event_server.stop(&app) {
+        Ok(())
     } else {
-        let error_str = String::from_utf8_lossy(&output.stderr);
-        Err(format!("Session setup error: {}", error_str))
+        Err(AppError::NotFound("No event server is running".to_string()))
+    }
+}
+
+#[tauri::command]
+async fn check_chatbot_health(state: State<'_, AppState>) -> Result<Vec<ChatBotHealth>, AppError> {
+    health::check_all(
+        &state.backend_config,
+        &state.chatbots.list(),
+        &state.settings.get().network,
+        &state.breaker,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn diagnose_backend(state: State<'_, AppState>) -> Result<BackendDiagnostics, AppError> {
+    Ok(health::diagnose(&state.backend_config).await)
+}
+
+#[tauri::command]
+async fn get_prompt_history(
+    state: State<'_, AppState>,
+    limit: usize,
+) -> Result<Vec<PromptResponse>, AppError> {
+    state.history.recent(limit)
+}
+
+#[tauri::command]
+async fn tag_history_entry(
+    state: State<'_, AppState>,
+    entry_id: i64,
+    tags: Vec<String>,
+) -> Result<(), AppError> {
+    state.history.tag(entry_id, &tags)
+}
+
+#[tauri::command]
+async fn favorite_response(
+    state: State<'_, AppState>,
+    entry_id: i64,
+    bot_id: String,
+    favorite: bool,
+) -> Result<(), AppError> {
+    state.history.favorite(entry_id, &bot_id, favorite)
+}
+
+#[tauri::command]
+async fn list_favorites(state: State<'_, AppState>) -> Result<Vec<ChatBotResponse>, AppError> {
+    state.history.favorites()
+}
+
+/// Prompt and per-bot totals from `from_ts` (inclusive) to `to_ts`
+/// (exclusive), in milliseconds since the Unix epoch.
+#[tauri::command]
+async fn history_stats(
+    state: State<'_, AppState>,
+    from_ts: u64,
+    to_ts: u64,
+) -> Result<HistoryStats, AppError> {
+    state.history.stats(from_ts, to_ts)
+}
+
+#[tauri::command]
+async fn search_history(
+    state: State<'_, AppState>,
+    query: String,
+    tags: Vec<String>,
+) -> Result<Vec<PromptResponse>, AppError> {
+    state.history.search(&query, &tags)
+}
+
+#[tauri::command]
+async fn clear_history(state: State<'_, AppState>) -> Result<(), AppError> {
+    state.history.clear()
+}
+
+/// Deletes history entries beyond the newest `max_entries` and those older
+/// than `max_age_days`, keeping favorited ones; returns how many went.
+#[tauri::command]
+async fn prune_history(
+    state: State<'_, AppState>,
+    max_entries: Option<usize>,
+    max_age_days: Option<u64>,
+    vacuum: Option<bool>,
+) -> Result<usize, AppError> {
+    prune(
+        &state.history,
+        max_entries,
+        max_age_days,
+        vacuum.unwrap_or(false),
+    )
+}
+
+/// Limits for `prune_history` to apply at every startup; `None` for both
+/// turns that off.
+#[tauri::command]
+async fn set_history_retention(
+    state: State<'_, AppState>,
+    max_entries: Option<usize>,
+    max_age_days: Option<u64>,
+) -> Result<(), AppError> {
+    state.settings.update(|s| {
+        s.history_retention = HistoryRetention {
+            max_entries,
+            max_age_days,
+        };
+    })
+}
+
+fn prune(
+    history: &History,
+    max_entries: Option<usize>,
+    max_age_days: Option<u64>,
+    vacuum: bool,
+) -> Result<usize, AppError> {
+    let before_ts =
+        max_age_days.map(|days| now_millis().saturating_sub(days.saturating_mul(86_400_000)));
+    let removed = history.prune(max_entries, before_ts, vacuum)?;
+    tracing::info!(removed, "pruned history");
+    Ok(removed)
+}
+
+/// The request's own system prompt, else the configured default; blank ones don't count.
+fn system_prompt(requested: Option<String>, settings: &Settings) -> Option<String> {
+    let present = |p: &String| !p.trim().is_empty();
+    requested
+        .filter(present)
+        .or_else(|| settings.system_prompt.clone().filter(present))
+}
+
+/// Milliseconds since the Unix epoch, the unit of every `timestamp` field here
+/// and of `Date.now()` in the Node backend. A clock set before the epoch
+/// reads as 0 rather than panicking.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[tauri::command]
+async fn export_response_markdown(response: PromptResponse) -> Result<String, AppError> {
+    Ok(export::response_to_markdown(&response))
+}
+
+#[tauri::command]
+async fn save_response_markdown(response: PromptResponse, path: String) -> Result<(), AppError> {
+    std::fs::write(&path, export::response_to_markdown(&response))
+        .map_err(|e| AppError::Storage(format!("Failed to write {}: {}", path, e)))
+}
+
+#[tauri::command]
+async fn save_template(
+    state: State<'_, AppState>,
+    name: String,
+    body: String,
+) -> Result<(), AppError> {
+    state.templates.save(Template { name, body })
+}
+
+#[tauri::command]
+async fn list_templates(state: State<'_, AppState>) -> Result<Vec<Template>, AppError> {
+    Ok(state.templates.list())
+}
+
+#[tauri::command]
+async fn render_template(
+    state: State<'_, AppState>,
+    name: String,
+    vars: HashMap<String, String>,
+) -> Result<String, AppError> {
+    state.templates.render(&name, &vars)
+}
+
+#[tauri::command]
+async fn export_history_json(state: State<'_, AppState>, path: String) -> Result<(), AppError> {
+    let json = export::history_to_json(&state.history.all()?)
+        .map_err(|e| AppError::Storage(format!("Failed to serialize history: {}", e)))?;
+    std::fs::write(&path, json)
+        .map_err(|e| AppError::Storage(format!("Failed to write {}: {}", path, e)))
+}
+
+/// Writes history as CSV; by default without a byte order mark and with LF
+/// line endings.
+#[tauri::command]
+async fn export_history_csv(
+    state: State<'_, AppState>,
+    path: String,
+    with_bom: Option<bool>,
+    crlf: Option<bool>,
+) -> Result<(), AppError> {
+    let options = export::CsvOptions {
+        bom: with_bom.unwrap_or(false),
+        crlf: crlf.unwrap_or(false),
+    };
+    std::fs::write(
+        &path,
+        export::history_to_csv(&state.history.all()?, options),
+    )
+    .map_err(|e| AppError::Storage(format!("Failed to write {}: {}", path, e)))
+}
+
+#[tauri::command]
+async fn get_chatbots_list(state: State<'_, AppState>) -> Result<Vec<ChatBotListEntry>, AppError> {
+    let chatbots = state.chatbots.list();
+    let selected = state.selection.selected(&chatbots);
+    Ok(chatbots
+        .into_iter()
+        .map(|config| ChatBotListEntry {
+            selected: selected.contains(&config.id),
+            config,
+        })
+        .collect())
+}
+
+#[tauri::command]
+async fn save_selection(state: State<'_, AppState>, ids: Vec<String>) -> Result<(), AppError> {
+    state.selection.save(ids)
+}
+
+#[tauri::command]
+async fn load_selection(state: State<'_, AppState>) -> Result<Vec<String>, AppError> {
+    Ok(state.selection.selected(&state.chatbots.list()))
+}
+
+#[tauri::command]
+async fn get_chatbot(state: State<'_, AppState>, id: String) -> Result<ChatBotConfig, AppError> {
+    state.chatbots.get(&id)
+}
+
+#[tauri::command]
+async fn add_chatbot(state: State<'_, AppState>, config: ChatBotConfig) -> Result<(), AppError> {
+    state.chatbots.add(config)
+}
+
+#[tauri::command]
+async fn duplicate_chatbot(
+    state: State<'_, AppState>,
+    id: String,
+    new_id: String,
+    new_name: String,
+) -> Result<ChatBotConfig, AppError> {
+    state.chatbots.duplicate(&id, &new_id, &new_name)
+}
+
+#[tauri::command]
+async fn update_chatbot(state: State<'_, AppState>, config: ChatBotConfig) -> Result<(), AppError> {
+    state.chatbots.update(config)
+}
+
+/// Restores the four built-in bots, backing up the current list first.
+#[tauri::command]
+async fn reset_chatbots_to_defaults(
+    state: State<'_, AppState>,
+) -> Result<Vec<ChatBotConfig>, AppError> {
+    state.chatbots.reset_to_defaults()
+}
+
+#[tauri::command]
+async fn reorder_chatbots(
+    state: State<'_, AppState>,
+    ordered_ids: Vec<String>,
+) -> Result<(), AppError> {
+    state.chatbots.reorder(&ordered_ids)
+}
+
+#[tauri::command]
+async fn set_chatbot_enabled(
+    state: State<'_, AppState>,
+    id: String,
+    enabled: bool,
+) -> Result<(), AppError> {
+    state.chatbots.set_enabled(&id, enabled)
+}
+
+/// Deletes the files saved from bots' embedded attachments. Returns how
+/// many were removed; answers still on screen will point at missing files.
+#[tauri::command]
+async fn clear_attachments(state: State<'_, AppState>) -> Result<usize, AppError> {
+    state.response_files.clear()
+}
+
+/// Reports every problem in the stored chatbot list without changing it.
+/// Models are only checked against lists `get_chatbot_models` already
+/// fetched this session, so nothing is spawned.
+#[tauri::command]
+async fn validate_all_chatbots(
+    state: State<'_, AppState>,
+) -> Result<Vec<ConfigValidationIssue>, AppError> {
+    let chatbots = state.chatbots.list();
+    let mut issues = validation::chatbot_issues(&chatbots);
+    for (index, config) in chatbots.iter().enumerate() {
+        let (Some(model), Some(known)) = (&config.model, state.models.cached(&config.id)) else {
+            continue;
+        };
+        if !known.is_empty() && !known.contains(model) {
+            issues.push(ConfigValidationIssue {
+                index,
+                chatbot_id: config.id.clone(),
+                field: "model",
+                message: format!("unknown model '{}'", model),
+            });
+        }
+    }
+    issues.sort_by_key(|issue| issue.index);
+    Ok(issues)
+}
+
+/// Loads bots from a JSON array of configs, e.g. one copied from another
+/// machine's `chatbots.json`. Nothing is changed if any entry is invalid.
+#[tauri::command]
+async fn import_chatbots(
+    state: State<'_, AppState>,
+    path: String,
+    merge: bool,
+) -> Result<ImportSummary, AppError> {
+    let imported: Vec<ChatBotConfig> = persist::read_json(Path::new(&path))
+        .map_err(AppError::Validation)?
+        .ok_or_else(|| AppError::NotFound(format!("No file at {}", path)))?;
+    validation::validate_import(&imported)?;
+    state.chatbots.import(imported, merge)
+}
+
+/// Writes the chatbots, selection, templates and settings to `path` as one
+/// versioned file. API keys, and credential-looking backend variables and
+/// headers, are left out.
+#[tauri::command]
+async fn export_config_bundle(state: State<'_, AppState>, path: String) -> Result<(), AppError> {
+    let chatbots = state.chatbots.list();
+    let selection = state.selection.selected(&chatbots);
+    ConfigBundle::new(
+        chatbots,
+        selection,
+        state.templates.list(),
+        state.settings.get(),
+    )
+    .write(Path::new(&path))
+}
+
+/// Replaces the whole configuration with a file from `export_config_bundle`,
+/// keeping the secrets it left out. Nothing changes unless the whole bundle
+/// validates.
+#[tauri::command]
+async fn import_config_bundle(state: State<'_, AppState>, path: String) -> Result<(), AppError> {
+    let mut bundle = ConfigBundle::read(Path::new(&path))?;
+    bundle.keep_secrets(&state.settings.get(), &state.chatbots.list());
+    let settings = bundle.settings;
+
+    validation::validate_import(&bundle.chatbots)?;
+    for (name, value) in &settings.backend_env {
+        validation::validate_env_var(name, value)?;
+    }
+    for url in [&settings.network.http_proxy, &settings.network.https_proxy]
+        .into_iter()
+        .flatten()
+    {
+        validation::validate_proxy_url(url)?;
+    }
+    PostProcessors::default().set(&settings.post_processors)?;
+    if let Some(lang) = &settings.locale {
+        i18n::supported(lang)
+            .ok_or_else(|| AppError::Validation(format!("Unsupported locale '{}'", lang)))?;
+    }
+    let backend = backend::create(
+        &settings.backend,
+        state.backend_config.clone(),
+        state.secrets.clone(),
+        &settings.network,
+    )?;
+
+    state.templates.replace(bundle.templates)?;
+    state.chatbots.import(bundle.chatbots, false)?;
+    state.selection.save(bundle.selection)?;
+    state.settings.update(|s| *s = settings.clone())?;
+
+    state.backend_config.set_env(settings.backend_env);
+    state.backend.set(&settings.backend, backend);
+    state.post_processors.set(&settings.post_processors)?;
+    i18n::set_locale(settings.locale.as_deref())?;
+    Ok(())
+}
+
+/// Skips `id` for the next `minutes`, e.g. while its provider is down; 0 wakes it now.
+#[tauri::command]
+async fn snooze_chatbot(
+    state: State<'_, AppState>,
+    id: String,
+    minutes: u64,
+) -> Result<(), AppError> {
+    let until = (minutes > 0).then(|| now_millis().saturating_add(minutes.saturating_mul(60_000)));
+    state.chatbots.snooze(&id, until)
+}
+
+#[tauri::command]
+async fn remove_chatbot(state: State<'_, AppState>, id: String) -> Result<(), AppError> {
+    state.chatbots.remove(&id)
+}
+
+/// Pass `setup_id` to be able to stop the run with `cancel_prompt`; one is
+/// generated otherwise and reported in every `setup-progress` event.
+#[tauri::command]
+async fn setup_chatbot_sessions(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    setup_id: Option<String>,
+) -> Result<SetupSummary, AppError> {
+    let setup_id = setup_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    setup::run(
+        &app,
+        &state.backend_config,
+        &state.settings.get(),
+        &state.children,
+        &state.sessions,
+        setup_id,
+        &[],
+    )
+    .await
+}
+
+/// Sets up `id`'s session on its own, replacing any it has. Like
+/// `setup_chatbot_sessions`, `setup_id` lets `cancel_prompt` stop it.
+#[tauri::command]
+async fn setup_chatbot_session(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+    setup_id: Option<String>,
+) -> Result<SessionStatus, AppError> {
+    state.chatbots.get(&id)?;
+    let setup_id = setup_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    setup::run_one(&app, &state, setup_id, &id).await
+}
+
+/// How `id`'s last setup went, without running one.
+#[tauri::command]
+async fn get_session_status(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<SessionStatus, AppError> {
+    state.chatbots.get(&id)?;
+    Ok(state.sessions.get(&id))
+}
+
+fn active_backend(
+    settings: &SettingsStore,
+    config: &Arc<BackendConfig>,
+    secrets: &Arc<Secrets>,
+) -> ActiveBackend {
+    let settings = settings.get();
+    let kind = settings.backend;
+    match backend::create(&kind, config.clone(), secrets.clone(), &settings.network) {
+        Ok(backend) => ActiveBackend::new(&kind, backend),
+        Err(e) => {
+            tracing::warn!("{}; falling back to the Node backend", e);
+            let node = backend::create(
+                "node",
+                config.clone(),
+                secrets.clone(),
+                &NetworkSettings::default(),
+            )
+            .expect("node backend always exists");
+            ActiveBackend::new("node", node)
+        }
     }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Wrapped below so every command invocation is logged in one place.
+    let handler: Box<dyn Fn(Invoke) -> bool + Send + Sync> = Box::new(tauri::generate_handler![
+        greet,
+        send_prompt_to_chatbots,
+        send_prompt_raw,
+        send_structured_prompt,
+        enqueue_prompt,
+        get_queue_status,
+        run_prompt_batch,
+        regenerate_chatbot,
+        stream_to_file,
+        retry_failed,
+        replay_history_entry,
+        test_chatbot_config,
+        benchmark_backend,
+        summarize_responses,
+        rank_responses,
+        diff_responses,
+        count_prompt_tokens,
+        preflight,
+        score_responses,
+        cancel_prompt,
+        cancel_all,
+        kill_all_backend_processes,
+        restart_backend,
+        new_conversation,
+        clear_conversation,
+        set_max_concurrency,
+        set_rate_limit,
+        set_circuit_breaker,
+        reset_circuit,
+        set_max_prompt_length,
+        set_max_selected_bots,
+        set_max_output_bytes,
+        set_default_timeout,
+        get_default_timeout,
+        set_detect_language,
+        set_normalize_responses,
+        set_locale,
+        set_system_prompt,
+        get_system_prompt,
+        set_post_processors,
+        get_log_path,
+        set_log_level,
+        clear_cache,
+        set_cache_enabled,
+        set_cache_ttl,
+        set_backend,
+        set_proxy,
+        set_user_agent,
+        set_client_id,
+        get_proxy,
+        set_backend_env,
+        store_api_key,
+        has_api_key,
+        get_secret_store,
+        check_chatbot_health,
+        list_requests,
+        get_metrics,
+        reset_metrics,
+        get_chatbot_models,
+        start_event_server,
+        stop_event_server,
+        diagnose_backend,
+        get_backend_status,
+        get_capability_matrix,
+        get_chatbots_list,
+        save_selection,
+        load_selection,
+        get_chatbot,
+        add_chatbot,
+        update_chatbot,
+        duplicate_chatbot,
+        remove_chatbot,
+        set_chatbot_enabled,
+        snooze_chatbot,
+        import_chatbots,
+        export_config_bundle,
+        import_config_bundle,
+        validate_all_chatbots,
+        clear_attachments,
+        reorder_chatbots,
+        reset_chatbots_to_defaults,
+        setup_chatbot_sessions,
+        setup_chatbot_session,
+        get_session_status,
+        get_prompt_history,
+        tag_history_entry,
+        search_history,
+        history_stats,
+        favorite_response,
+        list_favorites,
+        clear_history,
+        prune_history,
+        set_history_retention,
+        export_response_markdown,
+        save_template,
+        list_templates,
+        render_template,
+        save_response_markdown,
+        export_history_json,
+        export_history_csv
+    ]);
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![
-            greet,
-            send_prompt_to_chatbots,
-            get_chatbots_list,
-            setup_chatbot_sessions
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(|app| {
+            // State is built here rather than before `.setup()` because the
+            // data and config paths need an app handle. Logging goes first so
+            // everything after it can already log.
+            let logging = Logging::init(app.path().app_log_dir().ok());
+
+            let data_dir = app.path().app_data_dir()?;
+            let config_dir = app.path().app_config_dir()?;
+            let settings = SettingsStore::load(config_dir.join("settings.json"));
+            if let Err(e) = i18n::set_locale(settings.get().locale.as_deref()) {
+                tracing::warn!("Ignoring configured locale: {}", e);
+                let _ = i18n::set_locale(None);
+            }
+            let backend_config = Arc::new(BackendConfig::resolve(
+                app.handle(),
+                settings.get().backend_env,
+            ));
+            let secrets = Arc::new(Secrets::new(data_dir.clone()));
+
+            let post_processors = PostProcessors::default();
+            if let Err(e) = post_processors.set(&settings.get().post_processors) {
+                tracing::warn!("Ignoring configured post-processors: {}", e);
+            }
+            let breaker = CircuitBreaker::new(
+                settings.get().circuit_threshold,
+                Duration::from_secs(settings.get().circuit_cooldown_secs),
+            );
+            let (queue, jobs) = PromptQueue::new();
+            app.manage(AppState {
+                logging,
+                backend: active_backend(&settings, &backend_config, &secrets),
+                backend_config,
+                secrets,
+                settings,
+                chatbots: ChatbotStore::load(config_dir.join("chatbots.json")),
+                selection: SelectionStore::load(config_dir.join("selection.json")),
+                templates: TemplateStore::load(config_dir.join("templates.json")),
+                history: History::new(data_dir.join("history.db")),
+                response_files: ResponseFiles::new(data_dir.join("response-attachments")),
+                children: ChildRegistry::default(),
+                sessions: Sessions::default(),
+                limit: ConcurrencyLimit::default(),
+                rate_limiter: RateLimiter::default(),
+                breaker,
+                conversations: ConversationStore::default(),
+                cache: ResponseCache::default(),
+                metrics: Metrics::default(),
+                activity: Activity::default(),
+                models: ModelCatalog::default(),
+                capabilities: CapabilityCache::default(),
+                event_server: EventServer::default(),
+                queue,
+                post_processors,
+            });
+            queue::spawn_worker(app.handle().clone(), jobs);
+
+            let retention = app.state::<AppState>().settings.get().history_retention;
+            if retention.max_entries.is_some() || retention.max_age_days.is_some() {
+                let handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    let state = handle.state::<AppState>();
+                    if let Err(e) = prune(
+                        &state.history,
+                        retention.max_entries,
+                        retention.max_age_days,
+                        false,
+                    ) {
+                        tracing::warn!("Failed to prune history: {}", e);
+                    }
+                });
+            }
+
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(metrics::FLUSH_INTERVAL).await;
+                    let state = handle.state::<AppState>();
+                    if let Err(e) = state.metrics.flush(&state.history) {
+                        tracing::warn!("Failed to save metrics: {}", e);
+                    }
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(move |invoke| {
+            tracing::info!(command = invoke.message.command(), "command invoked");
+            handler(invoke)
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Closing a window aborts the prompts sent for it, and closing the
+            // last one aborts everything, so nothing keeps running or emitting
+            // for a window that's gone.
+            if let RunEvent::WindowEvent {
+                label,
+                event: WindowEvent::CloseRequested { .. },
+                ..
+            } = &event
+            {
+                let last_window = app.webview_windows().len() <= 1;
+                let (app, label) = (app.clone(), label.clone());
+                tauri::async_runtime::spawn(async move {
+                    let Some(state) = app.try_state::<AppState>() else {
+                        return;
+                    };
+                    let cancelled = state.children.window_closed(&label, last_window).await;
+                    if cancelled > 0 {
+                        tracing::info!(
+                            cancelled,
+                            window = %label,
+                            "window closed; cancelled prompts in flight"
+                        );
+                    }
+                });
+            }
+            // Exit also fires for `app.exit()`, so quitting from a tray menu is covered.
+            if let RunEvent::ExitRequested { .. } | RunEvent::Exit = event {
+                if let Some(state) = app.try_state::<AppState>() {
+                    state.children.kill_all();
+                    state.event_server.stop(app);
+                    if let Err(e) = state.metrics.flush(&state.history) {
+                        tracing::warn!("Failed to save metrics: {}", e);
+                    }
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn now_millis_is_milliseconds_since_the_epoch() {
+        let before = chrono::Utc::now().timestamp_millis() as u64;
+        let now = now_millis();
+        let after = chrono::Utc::now().timestamp_millis() as u64;
+        assert!((before..=after).contains(&now));
+    }
+
+    #[test]
+    fn now_millis_reads_a_clock_set_after_the_epoch() {
+        assert_ne!(now_millis(), 0);
+    }
 }
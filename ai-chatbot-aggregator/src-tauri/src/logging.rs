@@ -0,0 +1,77 @@
+use crate::error::AppError;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+const DEFAULT_LEVEL: &str = "info";
+const MAX_LOG_FILES: usize = 7;
+
+/// The installed tracing subscriber: a daily-rotated file under the app log
+/// dir, or stderr if that dir can't be used.
+pub struct Logging {
+    dir: Option<PathBuf>,
+    filter: reload::Handle<EnvFilter, Registry>,
+    // Flushes buffered lines when dropped, so it lives as long as the app.
+    _guard: WorkerGuard,
+}
+
+impl Logging {
+    /// Installs the global subscriber. Never panics: an unusable `log_dir`
+    /// falls back to stderr, and a subscriber installed earlier is left alone.
+    pub fn init(log_dir: Option<PathBuf>) -> Self {
+        let initial = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LEVEL.to_string());
+        let filter = EnvFilter::try_new(&initial).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL));
+        let (filter, handle) = reload::Layer::new(filter);
+
+        let appender = log_dir.as_ref().and_then(|dir| {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| e.to_string())
+                .and_then(|_| {
+                    Builder::new()
+                        .rotation(Rotation::DAILY)
+                        .filename_prefix("ai-multichat")
+                        .filename_suffix("log")
+                        .max_log_files(MAX_LOG_FILES)
+                        .build(dir)
+                        .map_err(|e| e.to_string())
+                })
+                .map_err(|e| eprintln!("Logging to stderr; cannot use {}: {}", dir.display(), e))
+                .ok()
+        });
+        let dir = appender.is_some().then_some(log_dir).flatten();
+
+        let (writer, guard) = match appender {
+            Some(appender) => tracing_appender::non_blocking(appender),
+            None => tracing_appender::non_blocking(std::io::stderr()),
+        };
+        let _ = tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt::layer().with_writer(writer).with_ansi(false))
+            .try_init();
+
+        Self {
+            dir,
+            filter: handle,
+            _guard: guard,
+        }
+    }
+
+    /// Directory holding the log files; the current one is `ai-multichat.<date>.log`.
+    pub fn path(&self) -> Result<PathBuf, AppError> {
+        self.dir
+            .clone()
+            .ok_or_else(|| AppError::NotFound("File logging is unavailable".to_string()))
+    }
+
+    /// Accepts a level ("debug") or a full filter directive ("info,ai_chatbot_aggregator_lib=trace").
+    pub fn set_level(&self, level: &str) -> Result<(), AppError> {
+        let filter = EnvFilter::try_new(level)
+            .map_err(|e| AppError::Validation(format!("Invalid log level '{}': {}", level, e)))?;
+        self.filter
+            .reload(filter)
+            .map_err(|e| AppError::NotFound(format!("Failed to change log level: {}", e)))
+    }
+}
@@ -0,0 +1,151 @@
+use crate::error::AppError;
+use crate::history::History;
+use crate::ChatBotResponse;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How often counters are written to the history database.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Running totals for one bot, as stored in the `bot_metrics` table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counters {
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub total_latency_ms: u64,
+}
+
+impl Counters {
+    fn add(&mut self, other: Counters) {
+        self.requests += other.requests;
+        self.successes += other.successes;
+        self.failures += other.failures;
+        self.total_latency_ms += other.total_latency_ms;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BotMetrics {
+    chatbot_id: String,
+    requests: u64,
+    successes: u64,
+    /// Cancelled calls count as requests but neither succeed nor fail.
+    failures: u64,
+    average_latency_ms: f64,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    /// Whether the persisted totals have been merged in yet.
+    loaded: bool,
+    dirty: bool,
+    bots: HashMap<String, Counters>,
+}
+
+/// Per-bot success and latency counters. Recording only takes a short
+/// in-memory lock; the database is read on first use, like the history
+/// itself, and written by a periodic flush.
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+impl Metrics {
+    /// Counts one backend call. Cached and rate-limited answers never reached
+    /// a backend and are left out.
+    pub fn record(&self, response: &ChatBotResponse) {
+        if response.from_cache
+            || matches!(
+                response.status.as_str(),
+                "rate_limited" | "queued" | "snoozed"
+            )
+        {
+            return;
+        }
+        let mut state = self.lock();
+        state.dirty = true;
+        let counters = state.bots.entry(response.id.clone()).or_default();
+        counters.requests += 1;
+        counters.total_latency_ms += response.latency_ms;
+        match response.status.as_str() {
+            "success" => counters.successes += 1,
+            "cancelled" => {}
+            _ => counters.failures += 1,
+        }
+    }
+
+    /// Totals since the last reset, by bot id.
+    pub fn snapshot(&self, history: &History) -> Result<Vec<BotMetrics>, AppError> {
+        self.load(history)?;
+        let mut metrics: Vec<BotMetrics> = self
+            .lock()
+            .bots
+            .iter()
+            .map(|(id, c)| BotMetrics {
+                chatbot_id: id.clone(),
+                requests: c.requests,
+                successes: c.successes,
+                failures: c.failures,
+                average_latency_ms: if c.requests == 0 {
+                    0.0
+                } else {
+                    c.total_latency_ms as f64 / c.requests as f64
+                },
+            })
+            .collect();
+        metrics.sort_by(|a, b| a.chatbot_id.cmp(&b.chatbot_id));
+        Ok(metrics)
+    }
+
+    /// Writes the totals to the database if anything changed since the last flush.
+    pub fn flush(&self, history: &History) -> Result<(), AppError> {
+        self.load(history)?;
+        let totals: Vec<(String, Counters)> = {
+            let mut state = self.lock();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.dirty = false;
+            state.bots.iter().map(|(id, c)| (id.clone(), *c)).collect()
+        };
+        if let Err(e) = history.save_metrics(&totals) {
+            self.lock().dirty = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn reset(&self, history: &History) -> Result<(), AppError> {
+        history.clear_metrics()?;
+        let mut state = self.lock();
+        state.bots.clear();
+        state.loaded = true;
+        state.dirty = false;
+        Ok(())
+    }
+
+    /// Adds the persisted totals to whatever was recorded since startup. The
+    /// query runs outside the lock so dispatches never wait on the database.
+    fn load(&self, history: &History) -> Result<(), AppError> {
+        if self.lock().loaded {
+            return Ok(());
+        }
+        let persisted = history.load_metrics()?;
+        let mut state = self.lock();
+        if state.loaded {
+            return Ok(());
+        }
+        for (id, counters) in persisted {
+            state.bots.entry(id).or_default().add(counters);
+        }
+        state.loaded = true;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MetricsState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
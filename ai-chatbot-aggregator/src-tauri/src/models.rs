@@ -0,0 +1,101 @@
+use crate::backend::{spawn_env, BackendConfig};
+use crate::error::AppError;
+use crate::settings::NetworkSettings;
+use crate::ChatBotConfig;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const LIST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Models each bot reports through the backend's `--list-models` flag,
+/// asked once per bot and kept for the rest of the session.
+#[derive(Default)]
+pub struct ModelCatalog {
+    listed: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl ModelCatalog {
+    /// The models `config` can use. Bots that can't enumerate them get their
+    /// configured model, if any, so an empty list just means "no choice".
+    pub async fn models(
+        &self,
+        backend: &BackendConfig,
+        network: &NetworkSettings,
+        config: &ChatBotConfig,
+    ) -> Result<Vec<String>, AppError> {
+        let cached = self.lock().get(&config.id).cloned();
+        let listed = match cached {
+            Some(listed) => listed,
+            None => {
+                let listed = list(backend, network, &config.id).await?;
+                self.lock().insert(config.id.clone(), listed.clone());
+                listed
+            }
+        };
+        if listed.is_empty() {
+            return Ok(config.model.iter().cloned().collect());
+        }
+        Ok(listed)
+    }
+
+    /// What `models` found for `chatbot_id` earlier in the session, without asking again.
+    pub fn cached(&self, chatbot_id: &str) -> Option<Vec<String>> {
+        self.lock().get(chatbot_id).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<String>>> {
+        self.listed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Runs `--list-models <id>`; the script prints a JSON array of model names
+/// on its last stdout line, empty for bots it can't enumerate.
+async fn list(
+    backend: &BackendConfig,
+    network: &NetworkSettings,
+    chatbot_id: &str,
+) -> Result<Vec<String>, AppError> {
+    let output = backend
+        .node_command()
+        .map_err(AppError::BackendSpawn)?
+        .envs(spawn_env(backend, network))
+        .arg(&backend.script_path)
+        .arg("--list-models")
+        .arg(chatbot_id)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(LIST_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            return Err(AppError::BackendSpawn(format!(
+                "Failed to execute AI backend: {}",
+                e
+            )))
+        }
+        Err(_) => {
+            return Err(AppError::BackendExit(format!(
+                "No model list within {} ms",
+                LIST_TIMEOUT.as_millis()
+            )))
+        }
+    };
+    if !output.status.success() {
+        return Err(AppError::BackendExit(format!(
+            "Listing models failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout
+        .lines()
+        .map(str::trim)
+        .rfind(|line| line.starts_with('['))
+        .unwrap_or("[]");
+    serde_json::from_str(line)
+        .map_err(|e| AppError::BackendExit(format!("Failed to parse model list: {}", e)))
+}
@@ -0,0 +1,373 @@
+use crate::error::AppError;
+use crate::{stats, ChatBotResponse};
+use std::sync::RwLock;
+
+/// A transformation applied to every answer before it reaches the UI,
+/// history or a conversation.
+pub trait ResponsePostProcessor: Send + Sync {
+    fn process(&self, response: &mut ChatBotResponse);
+}
+
+/// Removes Markdown fence lines (```` ``` ```` or ```` ```rust ````), keeping the code.
+pub struct StripCodeFences;
+
+impl ResponsePostProcessor for StripCodeFences {
+    fn process(&self, response: &mut ChatBotResponse) {
+        response.response = response
+            .response
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("```"))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+/// Drops trailing spaces, squeezes runs of blank lines to one and trims both ends.
+pub struct TrimWhitespace;
+
+impl ResponsePostProcessor for TrimWhitespace {
+    fn process(&self, response: &mut ChatBotResponse) {
+        let mut out = Vec::new();
+        for line in response.response.lines().map(str::trim_end) {
+            if line.is_empty() && out.last().is_none_or(|l: &&str| l.is_empty()) {
+                continue;
+            }
+            out.push(line);
+        }
+        while out.last().is_some_and(|l| l.is_empty()) {
+            out.pop();
+        }
+        response.response = out.join("\n");
+    }
+}
+
+/// Replaces email addresses with `[email]` and phone numbers (10 to 15
+/// digits, optionally grouped with spaces, dashes, dots or parentheses; see
+/// `looks_like_phone`) with `[phone]`, in the answer and in `error`.
+pub struct RedactPii;
+
+impl ResponsePostProcessor for RedactPii {
+    fn process(&self, response: &mut ChatBotResponse) {
+        response.response = redact_phones(&redact_emails(&response.response));
+        if let Some(error) = &response.error {
+            response.error = Some(redact_phones(&redact_emails(error)));
+        }
+    }
+}
+
+/// `text` with CRLF and lone CR line endings turned into LF and trailing
+/// spaces and tabs dropped from every line. Indentation, blank lines and
+/// everything else, inside fenced code blocks too, are left alone.
+pub fn normalize_whitespace(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .split('\n')
+        .map(|line| line.trim_end_matches([' ', '\t']))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Applies `normalize_whitespace` to the answer, flagging it if anything changed.
+pub fn normalize(response: &mut ChatBotResponse) {
+    let normalized = normalize_whitespace(&response.response);
+    if normalized != response.response {
+        response.response = normalized;
+        response.normalized = true;
+        response.char_count = stats::char_count(&response.response);
+        response.word_count = stats::word_count(&response.response);
+    }
+}
+
+/// Processor names accepted by `set_post_processors`, in no particular order.
+const NAMES: &[&str] = &["strip_code_fences", "trim_whitespace", "redact_pii"];
+
+fn by_name(name: &str) -> Option<Box<dyn ResponsePostProcessor>> {
+    match name {
+        "strip_code_fences" => Some(Box::new(StripCodeFences)),
+        "trim_whitespace" => Some(Box::new(TrimWhitespace)),
+        "redact_pii" => Some(Box::new(RedactPii)),
+        _ => None,
+    }
+}
+
+/// The configured processors, run in order. Empty by default.
+#[derive(Default)]
+pub struct PostProcessors {
+    pipeline: RwLock<Vec<Box<dyn ResponsePostProcessor>>>,
+}
+
+impl PostProcessors {
+    /// Replaces the pipeline. Nothing changes if any name is unknown.
+    pub fn set(&self, names: &[String]) -> Result<(), AppError> {
+        let pipeline = names
+            .iter()
+            .map(|name| {
+                by_name(name.trim()).ok_or_else(|| {
+                    AppError::Validation(format!(
+                        "Unknown post-processor '{}'; expected one of {}",
+                        name,
+                        NAMES.join(", ")
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        *self.pipeline.write().unwrap_or_else(|e| e.into_inner()) = pipeline;
+        Ok(())
+    }
+
+    /// Runs the pipeline over `response` and refreshes its counts.
+    pub fn apply(&self, response: &mut ChatBotResponse) {
+        let pipeline = self.pipeline.read().unwrap_or_else(|e| e.into_inner());
+        if pipeline.is_empty() {
+            return;
+        }
+        for processor in pipeline.iter() {
+            processor.process(response);
+        }
+        response.char_count = stats::char_count(&response.response);
+        response.word_count = stats::word_count(&response.response);
+    }
+}
+
+fn redact_emails(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '@' {
+            let local = chars[..i]
+                .iter()
+                .rev()
+                .take_while(|c| c.is_alphanumeric() || "._%+-".contains(**c))
+                .count();
+            let domain = chars[i + 1..]
+                .iter()
+                .take_while(|c| c.is_alphanumeric() || ".-".contains(**c))
+                .count();
+            // A trailing full stop ends the sentence, not the domain.
+            let domain = chars[i + 1..i + 1 + domain]
+                .iter()
+                .rposition(|c| *c != '.')
+                .map_or(0, |last| last + 1);
+            let host = &chars[i + 1..i + 1 + domain];
+            if local > 0 && host.contains(&'.') && host[0] != '.' {
+                let local_bytes: usize = chars[i - local..i].iter().map(|c| c.len_utf8()).sum();
+                out.truncate(out.len() - local_bytes);
+                out.push_str("[email]");
+                i += 1 + domain;
+                continue;
+            }
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+fn redact_phones(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let starts_number = chars[i].is_ascii_digit() || chars[i] == '+' || chars[i] == '(';
+        // Not inside a word, nor inside a longer number like 10.0.19041.1234.
+        let inside = i > 0
+            && (chars[i - 1].is_alphanumeric()
+                || (".-".contains(chars[i - 1]) && i > 1 && chars[i - 2].is_ascii_digit()));
+        if starts_number && !inside {
+            let run = chars[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit() || " -.()+".contains(**c))
+                .count();
+            // Don't swallow the separators after the last digit.
+            let run = chars[i..i + run]
+                .iter()
+                .rposition(|c| c.is_ascii_digit())
+                .map_or(0, |last| last + 1);
+            let candidate: String = chars[i..i + run].iter().collect();
+            // A colon right after is a time, as in "2024-01-15 10:30".
+            let ends_word = chars
+                .get(i + run)
+                .is_some_and(|c| c.is_alphanumeric() || *c == ':');
+            if !ends_word && looks_like_phone(&candidate) {
+                out.push_str("[phone]");
+                i += run;
+                continue;
+            }
+        }
+        out.push(chars[i]);
+        i += 1;
+    }
+    out
+}
+
+/// 10 to 15 digits in at most four groups (five after a leading `+`),
+/// separated by no more than two characters. Groups after the first have at
+/// least two digits, which rules out version numbers like 10.0.19041.1234,
+/// and a lone group without `+` must be exactly ten digits so long order
+/// numbers don't qualify. Without a `+`, date-shaped groups are a date.
+fn looks_like_phone(run: &str) -> bool {
+    let international = run.starts_with('+');
+    let number = run.strip_prefix('+').unwrap_or(run);
+    if number.contains('+') {
+        return false;
+    }
+    let groups: Vec<usize> = number
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|g| !g.is_empty())
+        .map(str::len)
+        .collect();
+    let digits: usize = groups.iter().sum();
+    let max_groups = if international { 5 } else { 4 };
+    if !(10..=15).contains(&digits) || groups.len() > max_groups {
+        return false;
+    }
+    if groups.iter().skip(1).any(|g| *g < 2) {
+        return false;
+    }
+    if groups.len() == 1 && !international && digits != 10 {
+        return false;
+    }
+    let widest_gap = number
+        .split(|c: char| c.is_ascii_digit())
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let date =
+        !international && matches!(groups[..], [4, 1..=2, 1..=2, ..] | [1..=2, 1..=2, 4, ..]);
+    widest_gap <= 2 && !date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(text: &str) -> ChatBotResponse {
+        ChatBotResponse {
+            response: text.to_string(),
+            status: "success".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn run(processor: &dyn ResponsePostProcessor, text: &str) -> String {
+        let mut response = answer(text);
+        processor.process(&mut response);
+        response.response
+    }
+
+    fn redact(text: &str) -> String {
+        run(&RedactPii, text)
+    }
+
+    #[test]
+    fn strip_code_fences_keeps_the_code() {
+        assert_eq!(
+            run(&StripCodeFences, "Try:\n```rust\nfn main() {}\n  ```\nDone"),
+            "Try:\nfn main() {}\nDone"
+        );
+    }
+
+    #[test]
+    fn trim_whitespace_squeezes_blank_lines() {
+        assert_eq!(
+            run(&TrimWhitespace, "\n\nfirst  \n\n\n\nsecond\t\n\n"),
+            "first\n\nsecond"
+        );
+    }
+
+    #[test]
+    fn redacts_email_addresses() {
+        assert_eq!(redact("Mail jane.doe+ai@example.co.uk."), "Mail [email].");
+        assert_eq!(redact("Follow @rustlang or a@b"), "Follow @rustlang or a@b");
+    }
+
+    #[test]
+    fn redacts_phone_numbers() {
+        assert_eq!(redact("Call 555-123-4567 now"), "Call [phone] now");
+        assert_eq!(redact("Call (555) 123-4567"), "Call [phone]");
+        assert_eq!(redact("Call +44 20 7946 0958"), "Call [phone]");
+        assert_eq!(redact("Call 5551234567."), "Call [phone].");
+    }
+
+    #[test]
+    fn leaves_dates_times_and_versions_alone() {
+        for text in [
+            "On 2024-01-15 10:30 it broke",
+            "Due 15.01.2024, 10:30",
+            "Since 01/15/2024 12:00",
+            "Windows 10.0.19041.1234",
+            "Order 123456789012",
+            "It costs 1,234,567.89",
+        ] {
+            assert_eq!(redact(text), text);
+        }
+    }
+
+    #[test]
+    fn redacts_errors_too() {
+        let mut response = answer("");
+        response.error = Some("Account jane@example.com locked".to_string());
+        RedactPii.process(&mut response);
+        assert_eq!(response.error.as_deref(), Some("Account [email] locked"));
+    }
+
+    #[test]
+    fn pipeline_runs_in_order_and_recounts() {
+        let processors = PostProcessors::default();
+        processors
+            .set(&[
+                "strip_code_fences".to_string(),
+                "trim_whitespace".to_string(),
+            ])
+            .unwrap();
+        let mut response = answer("```\ncode  \n```\n\n");
+        processors.apply(&mut response);
+        assert_eq!(response.response, "code");
+        assert_eq!(response.char_count, 4);
+        assert_eq!(response.word_count, 1);
+    }
+
+    #[test]
+    fn normalize_whitespace_fixes_line_endings_and_trailing_space() {
+        assert_eq!(
+            normalize_whitespace("one  \r\ntwo\t\rthree\r\n\r\n  four \n"),
+            "one\ntwo\nthree\n\n  four\n"
+        );
+    }
+
+    #[test]
+    fn normalize_whitespace_keeps_code_blocks_indented() {
+        let text =
+            "Example:\r\n```python\r\ndef f():   \r\n    if x:\r\n\r\n\treturn 1\t\r\n```\r\n";
+        assert_eq!(
+            normalize_whitespace(text),
+            "Example:\n```python\ndef f():\n    if x:\n\n\treturn 1\n```\n"
+        );
+    }
+
+    #[test]
+    fn normalize_only_flags_answers_it_changed() {
+        let mut clean = answer("Already clean\n\n  indented");
+        normalize(&mut clean);
+        assert!(!clean.normalized);
+
+        let mut messy = answer("two words  \r\n");
+        normalize(&mut messy);
+        assert!(messy.normalized);
+        assert_eq!(messy.response, "two words\n");
+        assert_eq!(messy.word_count, 2);
+    }
+
+    #[test]
+    fn unknown_processor_names_change_nothing() {
+        let processors = PostProcessors::default();
+        processors.set(&["trim_whitespace".to_string()]).unwrap();
+        assert!(processors
+            .set(&["trim_whitespace".to_string(), "shout".to_string()])
+            .is_err());
+        let mut response = answer("kept  ");
+        processors.apply(&mut response);
+        assert_eq!(response.response, "kept");
+    }
+}
@@ -0,0 +1,71 @@
+use crate::conversations::ConversationStore;
+use crate::{pricing, tokenize, ChatBotConfig, PromptRequest};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct BotEstimate {
+    chatbot_id: String,
+    model: Option<String>,
+    input_tokens: usize,
+    /// Input only: answers aren't written yet. 0.0 for models without a price.
+    estimated_cost_usd: f64,
+    /// `input_tokens` is an estimate; see `TokenCount`.
+    fallback: bool,
+}
+
+/// What a prompt would cost before it is sent, per bot and in total.
+#[derive(Debug, Serialize)]
+pub struct Preflight {
+    bots: Vec<BotEstimate>,
+    total_input_tokens: usize,
+    total_estimated_cost_usd: f64,
+}
+
+/// Estimates `request`, which must already have its bots and system prompt
+/// resolved. Each bot is counted with what it would be sent: the system
+/// prompt, its view of the conversation and the prompt itself.
+pub fn estimate(
+    request: &PromptRequest,
+    chatbots: &[ChatBotConfig],
+    conversations: &ConversationStore,
+) -> Preflight {
+    let bots: Vec<BotEstimate> = request
+        .chatbots
+        .iter()
+        .map(|id| {
+            let model = chatbots
+                .iter()
+                .find(|c| &c.id == id)
+                .and_then(|c| c.model.clone());
+            let mut text = request.system_prompt.clone().unwrap_or_default();
+            if let Some(conversation) = &request.conversation_id {
+                for message in conversations.context(conversation, id) {
+                    text.push('\n');
+                    text.push_str(&message.content);
+                }
+            }
+            text.push('\n');
+            text.push_str(&request.prompt);
+
+            // Without a model override the bot id ("claude", "gemini") still
+            // says which tokenizer is closest.
+            let count = tokenize::count(&text, model.as_deref().unwrap_or(id));
+            let estimated_cost_usd = model
+                .as_deref()
+                .map_or(0.0, |m| pricing::estimate_cost(m, count.tokens() as u64, 0));
+            BotEstimate {
+                chatbot_id: id.clone(),
+                model,
+                input_tokens: count.tokens(),
+                estimated_cost_usd,
+                fallback: count.fallback(),
+            }
+        })
+        .collect();
+
+    Preflight {
+        total_input_tokens: bots.iter().map(|b| b.input_tokens).sum(),
+        total_estimated_cost_usd: bots.iter().map(|b| b.estimated_cost_usd).sum(),
+        bots,
+    }
+}
@@ -0,0 +1,66 @@
+/// USD per million tokens as (model prefix, prompt, completion). Dated
+/// variants such as `gpt-4o-2024-08-06` match their base entry; the longest
+/// matching prefix wins so `gpt-4o-mini` isn't priced as `gpt-4o`.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-opus", 15.00, 75.00),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("sonar", 1.00, 1.00),
+];
+
+/// Estimated cost of one call in USD, or 0.0 for models not in the table.
+pub fn estimate_cost(model: &str, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+    let model = model.trim().to_ascii_lowercase();
+    PRICES
+        .iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len())
+        .map(|(_, prompt, completion)| {
+            (prompt_tokens as f64 * prompt + completion_tokens as f64 * completion) / 1_000_000.0
+        })
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cost of a million prompt tokens and a million completion tokens.
+    fn per_million(model: &str) -> f64 {
+        estimate_cost(model, 1_000_000, 1_000_000)
+    }
+
+    #[test]
+    fn the_longest_prefix_sets_the_price() {
+        assert_eq!(per_million("gpt-4o"), 12.50);
+        assert_eq!(per_million("gpt-4o-mini"), 0.75);
+        assert_eq!(per_million("gpt-4.1-nano"), 0.50);
+    }
+
+    #[test]
+    fn dated_variants_are_priced_as_their_base_model() {
+        assert_eq!(per_million("gpt-4o-2024-08-06"), per_million("gpt-4o"));
+        assert_eq!(
+            per_million("gpt-4o-mini-2024-07-18"),
+            per_million("gpt-4o-mini")
+        );
+        assert_eq!(per_million(" Claude-3-5-Sonnet-20241022 "), 18.00);
+        assert_eq!(estimate_cost("gpt-4o", 2_000, 500), 0.01);
+    }
+
+    #[test]
+    fn unknown_models_cost_nothing() {
+        assert_eq!(per_million("llama-3-70b"), 0.0);
+        assert_eq!(per_million(""), 0.0);
+        // Being the start of a known name isn't enough.
+        assert_eq!(per_million("gpt-4"), 0.0);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::error::AppError;
+use crate::events::{Audience, EventSink};
+use crate::state::AppState;
+use crate::{run_prompt, PromptRequest, PromptResponse};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+/// A prompt waiting its turn. Prompts from `enqueue` have no `reply` and
+/// report their outcome as a `prompt-result` event instead.
+pub struct Job {
+    request: PromptRequest,
+    reply: Option<Reply>,
+    /// Set for submitted prompts that identical ones may join; see `coalesce_key`.
+    key: Option<u64>,
+}
+
+type Reply = oneshot::Sender<Result<PromptResponse, AppError>>;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct QueueStatus {
+    pending: usize,
+    running: usize,
+}
+
+/// Emitted as `prompt-result` when a prompt submitted with `enqueue_prompt` finishes.
+#[derive(Debug, Serialize)]
+struct PromptResult<'a> {
+    request_id: &'a str,
+    response: Option<&'a PromptResponse>,
+    error: Option<&'a AppError>,
+}
+
+/// Runs prompts one at a time in submission order, so a second prompt sent
+/// before the first finishes waits instead of competing for the machine.
+pub struct PromptQueue {
+    jobs: mpsc::UnboundedSender<Job>,
+    pending: AtomicUsize,
+    running: AtomicUsize,
+    /// Submitted prompts not yet answered, with the identical submissions
+    /// waiting to share their result.
+    in_flight: Mutex<HashMap<u64, Group>>,
+}
+
+/// A submitted prompt and the identical ones that joined it. They all share
+/// its request id, which is the one their response carries.
+struct Group {
+    request_id: String,
+    /// Ids the joining submissions brought along, so `cancel_prompt` still
+    /// finds the prompt by them.
+    aliases: Vec<String>,
+    waiters: Vec<Reply>,
+}
+
+impl PromptQueue {
+    /// The receiving end goes to [`spawn_worker`] once the queue is in managed state.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<Job>) {
+        let (jobs, receiver) = mpsc::unbounded_channel();
+        let queue = Self {
+            jobs,
+            pending: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            in_flight: Mutex::new(HashMap::new()),
+        };
+        (queue, receiver)
+    }
+
+    /// Queues `request` and waits for its turn and its result. A request
+    /// identical to one still queued or running, e.g. from a double click,
+    /// isn't run again but gets the same response, request id included.
+    pub async fn submit(&self, mut request: PromptRequest) -> Result<PromptResponse, AppError> {
+        let key = coalesce_key(&request);
+        let (reply, result) = oneshot::channel();
+        let mut reply = Some(reply);
+        if let Some(key) = key {
+            let mut in_flight = self.in_flight();
+            match in_flight.get_mut(&key) {
+                Some(group) => {
+                    tracing::info!("identical prompt already in flight; sharing its result");
+                    group.aliases.extend(request.request_id.take());
+                    group.waiters.extend(reply.take());
+                }
+                None => {
+                    let request_id = request
+                        .request_id
+                        .get_or_insert_with(|| Uuid::new_v4().to_string())
+                        .clone();
+                    let group = Group {
+                        request_id,
+                        aliases: Vec::new(),
+                        waiters: Vec::new(),
+                    };
+                    in_flight.insert(key, group);
+                }
+            }
+        }
+        if let Some(reply) = reply {
+            if let Err(e) = self.push(request, Some(reply), key) {
+                if let Some(key) = key {
+                    self.in_flight().remove(&key);
+                }
+                return Err(e);
+            }
+        }
+        result
+            .await
+            .map_err(|_| AppError::BackendExit("Prompt queue stopped".to_string()))?
+    }
+
+    /// Queues `request` and returns its request id straight away.
+    pub fn enqueue(&self, request: PromptRequest) -> Result<String, AppError> {
+        self.push(request, None, None)
+    }
+
+    /// The id to cancel `request_id` by: that of the prompt it joined, if it
+    /// was submitted while an identical one was in flight, or else itself.
+    pub fn coalesced_id(&self, request_id: &str) -> String {
+        self.in_flight()
+            .values()
+            .find(|group| group.aliases.iter().any(|alias| alias == request_id))
+            .map_or_else(|| request_id.to_string(), |group| group.request_id.clone())
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        QueueStatus {
+            pending: self.pending.load(Ordering::SeqCst),
+            running: self.running.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Fixes the request id now, so callers can match events to the prompt
+    /// before it has started.
+    fn push(
+        &self,
+        mut request: PromptRequest,
+        reply: Option<Reply>,
+        key: Option<u64>,
+    ) -> Result<String, AppError> {
+        let request_id = request
+            .request_id
+            .get_or_insert_with(|| Uuid::new_v4().to_string())
+            .clone();
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if self
+            .jobs
+            .send(Job {
+                request,
+                reply,
+                key,
+            })
+            .is_err()
+        {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(AppError::BackendExit("Prompt queue stopped".to_string()));
+        }
+        Ok(request_id)
+    }
+
+    /// Runs `job` with `run` and hands the result to everyone waiting on it.
+    /// Returns the result when nobody is, i.e. the job came from `enqueue`.
+    async fn run_job<F, Fut>(&self, job: Job, run: F) -> Option<Result<PromptResponse, AppError>>
+    where
+        F: FnOnce(PromptRequest) -> Fut,
+        Fut: Future<Output = Result<PromptResponse, AppError>>,
+    {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        self.running.fetch_add(1, Ordering::SeqCst);
+        let running = Running {
+            queue: self,
+            key: job.key,
+        };
+        let result = run(job.request).await;
+        for waiter in running.finish() {
+            let _ = waiter.send(result.clone());
+        }
+
+        match job.reply {
+            // The caller may have gone away; the result is in history either way.
+            Some(reply) => {
+                let _ = reply.send(result);
+                None
+            }
+            None => Some(result),
+        }
+    }
+
+    fn in_flight(&self) -> MutexGuard<'_, HashMap<u64, Group>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A job being run. Dropping it, even while unwinding from a panic in the
+/// prompt, takes the job's group out of `in_flight` so the next identical
+/// prompt runs afresh instead of waiting on a result that never comes.
+struct Running<'a> {
+    queue: &'a PromptQueue,
+    key: Option<u64>,
+}
+
+impl Running<'_> {
+    /// The submissions that joined the job and are waiting for its result.
+    fn finish(mut self) -> Vec<Reply> {
+        let group = self
+            .key
+            .take()
+            .and_then(|key| self.queue.in_flight().remove(&key));
+        group.map(|group| group.waiters).unwrap_or_default()
+    }
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.queue.running.fetch_sub(1, Ordering::SeqCst);
+        if let Some(key) = self.key.take() {
+            self.queue.in_flight().remove(&key);
+        }
+    }
+}
+
+/// Everything about `request` that shapes its answer, hashed; only the
+/// request id is left out. Bot configs aren't part of it, so editing a bot's
+/// model while its prompt is in flight doesn't stop an identical one joining.
+fn coalesce_key(request: &PromptRequest) -> Option<u64> {
+    let mut value = serde_json::to_value(request).ok()?;
+    value.as_object_mut()?.remove("request_id");
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Works through the queue for the lifetime of the app.
+pub fn spawn_worker(app: AppHandle, mut jobs: mpsc::UnboundedReceiver<Job>) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(job) = jobs.recv().await {
+            let queue = &app.state::<AppState>().queue;
+            let request_id = job.request.request_id.clone().unwrap_or_default();
+            let events = EventSink::new(
+                &app,
+                Audience::from_window(job.request.event_window.as_deref()),
+            );
+            let run = |request| run_prompt(&app, request);
+            if let Some(result) = queue.run_job(job, run).await {
+                let event = PromptResult {
+                    request_id: &request_id,
+                    response: result.as_ref().ok(),
+                    error: result.as_ref().err(),
+                };
+                events.emit("prompt-result", &event);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::panic::AssertUnwindSafe;
+
+    fn request(prompt: &str, request_id: Option<&str>) -> PromptRequest {
+        PromptRequest {
+            prompt: prompt.to_string(),
+            chatbots: vec!["a".to_string()],
+            request_id: request_id.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    /// Answers with how many prompts it has run so far.
+    async fn answer(
+        runs: &AtomicUsize,
+        request: PromptRequest,
+    ) -> Result<PromptResponse, AppError> {
+        let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(PromptResponse {
+            request_id: request.request_id.unwrap_or_default(),
+            prompt: format!("{} #{}", request.prompt, run),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn identical_submissions_share_one_run_until_it_finishes() {
+        tauri::async_runtime::block_on(async {
+            let (queue, mut jobs) = PromptQueue::new();
+            let runs = AtomicUsize::new(0);
+            let worker = async {
+                let job = jobs.recv().await.unwrap();
+                queue
+                    .run_job(job, |request| async {
+                        // The joining submission's own id finds the shared run.
+                        assert_eq!(queue.coalesced_id("second"), "first");
+                        answer(&runs, request).await
+                    })
+                    .await
+            };
+            let (first, second, unanswered) = futures::future::join3(
+                queue.submit(request("hi", Some("first"))),
+                queue.submit(request("hi", Some("second"))),
+                worker,
+            )
+            .await;
+            let (first, second) = (first.unwrap(), second.unwrap());
+            assert!(unanswered.is_none());
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            assert_eq!(
+                (first.request_id.as_str(), first.prompt.as_str()),
+                ("first", "hi #1")
+            );
+            assert_eq!(
+                (second.request_id.as_str(), second.prompt.as_str()),
+                ("first", "hi #1")
+            );
+            assert_eq!(queue.coalesced_id("second"), "second");
+
+            let worker = async {
+                let job = jobs.recv().await.unwrap();
+                queue.run_job(job, |request| answer(&runs, request)).await
+            };
+            let (third, _) =
+                futures::future::join(queue.submit(request("hi", Some("third"))), worker).await;
+            let third = third.unwrap();
+            assert_eq!(
+                (third.request_id.as_str(), third.prompt.as_str()),
+                ("third", "hi #2")
+            );
+            assert_eq!(queue.status().running, 0);
+        });
+    }
+
+    #[test]
+    fn a_panicking_run_lets_identical_prompts_run_again() {
+        tauri::async_runtime::block_on(async {
+            let (queue, mut jobs) = PromptQueue::new();
+            let worker = async {
+                let job = jobs.recv().await.unwrap();
+                let run = queue.run_job(job, |_| async { panic!("backend blew up") });
+                assert!(AssertUnwindSafe(run).catch_unwind().await.is_err());
+            };
+            let (first, second, ()) = futures::future::join3(
+                queue.submit(request("hi", None)),
+                queue.submit(request("hi", None)),
+                worker,
+            )
+            .await;
+            // Both replies were dropped with the job instead of hanging.
+            assert!(first.is_err() && second.is_err());
+            assert!(queue.in_flight().is_empty());
+            assert_eq!(queue.status().running, 0);
+        });
+    }
+}
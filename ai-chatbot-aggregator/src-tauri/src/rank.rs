@@ -0,0 +1,157 @@
+use crate::dispatch::{dispatch_one, DispatchContext};
+use crate::error::AppError;
+use crate::{ChatBotResponse, PromptRequest, PromptResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// A successful answer with its place as judged by the ranking bot.
+#[derive(Debug, Serialize)]
+pub struct RankedResponse {
+    #[serde(flatten)]
+    response: ChatBotResponse,
+    /// 1 for the best answer; 0 for every answer when ranking failed.
+    rank: u32,
+    /// The ranking bot's reason, or why there is no ranking.
+    rationale: String,
+}
+
+#[derive(Deserialize)]
+struct Verdicts {
+    rankings: Vec<Verdict>,
+}
+
+#[derive(Deserialize)]
+struct Verdict {
+    /// 1-based position in the prompt's list of answers.
+    answer: usize,
+    score: f64,
+    rationale: String,
+}
+
+/// Asks `ranker` to score the successful answers in `response` against
+/// `criteria`, best first; ties and answers it leaves out keep their order.
+/// If ranking fails the answers come back in their original order with rank
+/// 0 and the failure as their rationale, since they are still worth showing.
+pub async fn rank(
+    ctx: &DispatchContext<'_>,
+    response: &PromptResponse,
+    criteria: &str,
+    ranker: &str,
+) -> Result<Vec<RankedResponse>, AppError> {
+    let answers: Vec<ChatBotResponse> = response
+        .results
+        .iter()
+        .filter(|r| r.status == "success")
+        .cloned()
+        .collect();
+    if answers.is_empty() {
+        return Err(AppError::Validation(
+            "No successful responses to rank".to_string(),
+        ));
+    }
+
+    let verdicts = match ask(ctx, &response.prompt, &answers, criteria, ranker).await {
+        Ok(verdicts) => verdicts,
+        Err(e) => {
+            let rationale = format!("Ranking failed: {}", e);
+            return Ok(answers
+                .into_iter()
+                .map(|response| RankedResponse {
+                    response,
+                    rank: 0,
+                    rationale: rationale.clone(),
+                })
+                .collect());
+        }
+    };
+
+    let mut scored: Vec<(Option<f64>, ChatBotResponse, String)> = answers
+        .into_iter()
+        .enumerate()
+        .map(
+            |(i, answer)| match verdicts.iter().find(|v| v.answer == i + 1) {
+                Some(verdict) => (Some(verdict.score), answer, verdict.rationale.clone()),
+                None => (None, answer, "Left out by the ranking bot".to_string()),
+            },
+        )
+        .collect();
+    scored.sort_by(|(a, ..), (b, ..)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(scored
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, response, rationale))| RankedResponse {
+            response,
+            rank: i as u32 + 1,
+            rationale,
+        })
+        .collect())
+}
+
+async fn ask(
+    ctx: &DispatchContext<'_>,
+    prompt: &str,
+    answers: &[ChatBotResponse],
+    criteria: &str,
+    ranker: &str,
+) -> Result<Vec<Verdict>, String> {
+    let schema = json!({
+        "type": "object",
+        "required": ["rankings"],
+        "properties": {
+            "rankings": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["answer", "score", "rationale"],
+                    "properties": {
+                        "answer": {"type": "integer", "minimum": 1, "maximum": answers.len()},
+                        "score": {"type": "number"},
+                        "rationale": {"type": "string"}
+                    }
+                }
+            }
+        }
+    });
+    let request = PromptRequest {
+        prompt: ranking_prompt(prompt, answers, criteria),
+        chatbots: vec![ranker.to_string()],
+        response_format: Some("json".to_string()),
+        response_schema: Some(schema),
+        ..Default::default()
+    };
+    let request_id = Uuid::new_v4().to_string();
+    let ranking = dispatch_one(ctx, &request, &request_id, ranker)
+        .await
+        .map_err(|e| e.to_string())?;
+    if ranking.status != "success" {
+        return Err(format!(
+            "{} answered with {}: {}",
+            ranking.name,
+            ranking.status,
+            ranking.error.unwrap_or_default()
+        ));
+    }
+    serde_json::from_str::<Verdicts>(&ranking.response)
+        .map(|v| v.rankings)
+        .map_err(|e| format!("Unreadable ranking from {}: {}", ranking.name, e))
+}
+
+fn ranking_prompt(prompt: &str, answers: &[ChatBotResponse], criteria: &str) -> String {
+    let mut out = format!(
+        "Several assistants answered the question below. Score each answer from 0 \
+         to 10 by these criteria: {}\n\nReply with JSON only, shaped like \
+         {{\"rankings\": [{{\"answer\": 1, \"score\": 7.5, \"rationale\": \"...\"}}]}}, \
+         with one entry per answer and a one-sentence rationale.\n\n",
+        criteria.trim()
+    );
+    out.push_str(&format!("Question:\n{}\n", prompt.trim()));
+    for (i, answer) in answers.iter().enumerate() {
+        out.push_str(&format!(
+            "\nAnswer {}:\n{}\n",
+            i + 1,
+            answer.response.trim()
+        ));
+    }
+    out
+}
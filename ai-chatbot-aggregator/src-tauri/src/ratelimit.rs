@@ -0,0 +1,93 @@
+use crate::error::AppError;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long a bot may wait for its bucket before it is reported as rate limited.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Token bucket holding up to `per_minute` requests, refilled continuously.
+struct Bucket {
+    per_minute: u32,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            tokens: per_minute as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes a token if one frees up within `max_wait` and returns how long
+    /// to wait for it. The token is taken immediately, so later callers queue
+    /// behind this one instead of racing it.
+    fn reserve(&mut self, now: Instant, max_wait: Duration) -> Option<Duration> {
+        let rate = self.per_minute as f64 / 60.0;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(self.per_minute as f64);
+        self.updated = now;
+
+        let wait = if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / rate)
+        };
+        if wait > max_wait {
+            return None;
+        }
+        self.tokens -= 1.0;
+        Some(wait)
+    }
+}
+
+/// Per-chatbot request rate limits. Bots without a limit are never delayed.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Limits `chatbot_id` to `per_minute` requests; 0 removes the limit.
+    pub fn set(&self, chatbot_id: &str, per_minute: u32) -> Result<(), AppError> {
+        if chatbot_id.trim().is_empty() {
+            return Err(AppError::Validation(
+                "Chatbot id must not be empty".to_string(),
+            ));
+        }
+
+        let mut buckets = self.lock();
+        if per_minute == 0 {
+            buckets.remove(chatbot_id);
+        } else {
+            buckets.insert(chatbot_id.to_string(), Bucket::new(per_minute));
+        }
+        Ok(())
+    }
+
+    /// Waits until `chatbot_id` may send another request. Returns `false`
+    /// without waiting if that would take longer than the maximum wait.
+    pub async fn acquire(&self, chatbot_id: &str) -> bool {
+        let wait = match self.lock().get_mut(chatbot_id) {
+            Some(bucket) => bucket.reserve(Instant::now(), MAX_WAIT),
+            None => return true,
+        };
+        match wait {
+            Some(wait) => {
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Bucket>> {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
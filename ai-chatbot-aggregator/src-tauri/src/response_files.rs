@@ -0,0 +1,113 @@
+use crate::error::AppError;
+use crate::ChatBotResponse;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// A generated image or file returned with an answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseAttachment {
+    pub filename: String,
+    pub mime: String,
+    /// Where the file is on disk. Backends either give one or send `data`,
+    /// which is saved by [`ResponseFiles`] and then filled in here.
+    #[serde(default)]
+    pub path: String,
+    /// Base64 content, optionally as a `data:` URL. Never sent on to the frontend.
+    #[serde(default, skip_serializing)]
+    pub data: Option<String>,
+}
+
+/// The directory under the app data path that embedded attachments are
+/// written to, until `clear_attachments` empties it.
+pub struct ResponseFiles {
+    dir: PathBuf,
+}
+
+impl ResponseFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Writes out every embedded attachment of `response` and points its
+    /// `path` at the file. Attachments that can't be saved are dropped, with
+    /// the reason returned so it can be noted on the response.
+    pub fn save(&self, response: &mut ChatBotResponse) -> Vec<String> {
+        let mut failures = Vec::new();
+        response.attachments.retain_mut(|attachment| {
+            let Some(data) = attachment.data.take() else {
+                return !attachment.path.is_empty();
+            };
+            match self.write(&attachment.filename, &data) {
+                Ok(path) => {
+                    attachment.path = path;
+                    true
+                }
+                Err(e) => {
+                    failures.push(format!(
+                        "Attachment '{}' not saved: {}",
+                        attachment.filename, e
+                    ));
+                    false
+                }
+            }
+        });
+        failures
+    }
+
+    /// Deletes every saved attachment. Returns how many files were removed.
+    pub fn clear(&self) -> Result<usize, AppError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(AppError::Storage(format!(
+                    "Failed to read {}: {}",
+                    self.dir.display(),
+                    e
+                )))
+            }
+        };
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            fs::remove_file(&path).map_err(|e| {
+                AppError::Storage(format!("Failed to remove {}: {}", path.display(), e))
+            })?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    fn write(&self, filename: &str, data: &str) -> Result<String, String> {
+        // `data:image/png;base64,...` carries its own prefix; plain base64 doesn't.
+        let encoded = match data.split_once(";base64,") {
+            Some((prefix, encoded)) if prefix.starts_with("data:") => encoded,
+            _ => data,
+        };
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| format!("invalid base64: {}", e))?;
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        // A bot picks the name, so only its last component is used, behind a
+        // unique prefix so two answers can't overwrite each other.
+        let name: String = std::path::Path::new(filename)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || "._-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path = self.dir.join(format!("{}-{}", Uuid::new_v4(), name));
+        fs::write(&path, bytes).map_err(|e| e.to_string())?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+}
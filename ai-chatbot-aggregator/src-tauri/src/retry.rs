@@ -0,0 +1,109 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Runs `op` until it succeeds, fails with an error `is_retryable` rejects, or
+/// `max_retries` retries have been spent. Returns the last result together
+/// with the number of attempts made.
+pub async fn with_backoff<T, E, F, Fut>(
+    max_retries: u32,
+    is_retryable: impl Fn(&E) -> bool,
+    mut op: F,
+) -> (Result<T, E>, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match op().await {
+            Err(e) if attempt <= max_retries && is_retryable(&e) => {
+                tokio::time::sleep(backoff_delay(attempt)).await;
+            }
+            result => return (result, attempt),
+        }
+    }
+}
+
+/// Exponential backoff with jitter: the `attempt`-th retry waits a random
+/// duration between half and all of `BASE_DELAY * 2^(attempt - 1)`, capped at `MAX_DELAY`.
+fn backoff_delay(attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let ceiling = BASE_DELAY.saturating_mul(1 << exponent).min(MAX_DELAY);
+    let half = ceiling / 2;
+    let jitter = random_u64() % (half.as_millis() as u64 + 1);
+    half + Duration::from_millis(jitter)
+}
+
+fn random_u64() -> u64 {
+    // RandomState is seeded per instance, which is plenty for jitter.
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        for (attempt, ceiling) in [
+            (1, 500),
+            (2, 1000),
+            (3, 2000),
+            (4, 4000),
+            (5, 8000),
+            (40, 8000),
+        ] {
+            for _ in 0..20 {
+                let delay = backoff_delay(attempt).as_millis();
+                assert!(
+                    (ceiling / 2..=ceiling).contains(&delay),
+                    "attempt {}: {} ms",
+                    attempt,
+                    delay
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_success_or_a_fatal_error() {
+        let calls = Cell::new(0);
+        let (result, attempts) = tauri::async_runtime::block_on(with_backoff(
+            3,
+            |_: &&str| true,
+            || {
+                calls.set(calls.get() + 1);
+                async { Ok::<_, &str>("done") }
+            },
+        ));
+        assert_eq!((result, attempts, calls.get()), (Ok("done"), 1, 1));
+
+        let (result, attempts) = tauri::async_runtime::block_on(with_backoff(
+            3,
+            |e: &&str| *e == "transient",
+            || async { Err::<(), _>("fatal") },
+        ));
+        assert_eq!((result, attempts), (Err("fatal"), 1));
+    }
+
+    #[test]
+    fn retries_transient_errors_until_the_budget_is_spent() {
+        let calls = Cell::new(0);
+        let (result, attempts) = tauri::async_runtime::block_on(with_backoff(
+            1,
+            |_: &&str| true,
+            || {
+                calls.set(calls.get() + 1);
+                async { Err::<(), _>("transient") }
+            },
+        ));
+        assert_eq!((result, attempts, calls.get()), (Err("transient"), 2, 2));
+    }
+}
@@ -0,0 +1,80 @@
+use jsonschema::Validator;
+use serde_json::Value;
+
+/// Compiles `schema` for `validate`, checking it against its draft's
+/// meta-schema. `format` is enforced, not just an annotation. References to
+/// other documents are not fetched, so a schema that needs one fails here
+/// instead of making the app go online.
+pub fn compile(schema: &Value) -> Result<Validator, String> {
+    jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+        .map_err(|e| e.to_string())
+}
+
+/// Validates `value` against a compiled schema. Returns the first
+/// violation, located by JSON pointer.
+pub fn validate(schema: &Validator, value: &Value) -> Result<(), String> {
+    schema.validate(value).map_err(|e| {
+        let at = e.instance_path().as_str();
+        format!("{}: {}", if at.is_empty() { "(root)" } else { at }, e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn person() -> Validator {
+        compile(&json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {
+                "name": { "type": "string", "minLength": 1, "pattern": "^[A-Z]" },
+                "email": { "type": "string", "format": "email" },
+                "tags": { "type": "array", "items": { "$ref": "#/$defs/tag" } },
+                "a/b": { "const": 1 }
+            },
+            "dependentRequired": { "email": ["name"] },
+            "additionalProperties": false,
+            "$defs": { "tag": { "enum": ["a", "b"] } }
+        }))
+        .unwrap()
+    }
+
+    fn violation(value: Value) -> String {
+        validate(&person(), &value).unwrap_err()
+    }
+
+    #[test]
+    fn accepts_matching_values() {
+        validate(&person(), &json!({ "name": "Ann", "tags": ["a"] })).unwrap();
+        validate(&compile(&json!(true)).unwrap(), &json!(null)).unwrap();
+    }
+
+    #[test]
+    fn reports_the_first_violation_by_pointer() {
+        assert!(violation(json!([])).starts_with("(root): "));
+        assert!(violation(json!({ "name": "Ann" })).contains("\"tags\" is a required property"));
+        assert!(violation(json!({ "name": "ann", "tags": [] })).starts_with("/name: "));
+        assert!(violation(json!({ "name": "Ann", "tags": ["c"] })).starts_with("/tags/0: "));
+        assert!(violation(json!({ "name": "Ann", "tags": [], "a/b": 2 })).starts_with("/a~1b: "));
+        assert!(violation(json!({ "name": "Ann", "tags": [], "x": 1 })).starts_with("(root): "));
+    }
+
+    #[test]
+    fn formats_are_checked() {
+        let value = json!({ "name": "Ann", "tags": [], "email": "not an address" });
+        assert!(violation(value).starts_with("/email: "));
+    }
+
+    #[test]
+    fn compile_rejects_malformed_schemas() {
+        assert!(compile(&json!("string")).is_err());
+        assert!(compile(&json!({ "type": "text" })).is_err());
+        assert!(compile(&json!({ "minLength": -1 })).is_err());
+        assert!(compile(&json!({ "$ref": "#/$defs/missing" })).is_err());
+        assert!(compile(&json!({ "$ref": "https://example.com/schema.json" })).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use crate::ChatBotResponse;
+use std::collections::HashMap;
+
+/// Each answer's token F1 against `reference`, in result order. Answers
+/// that didn't succeed score 0.0.
+pub fn score_all(results: &[ChatBotResponse], reference: &str) -> Vec<(String, f64)> {
+    let reference = token_counts(reference);
+    results
+        .iter()
+        .map(|r| {
+            let score = if r.status == "success" {
+                f1(&token_counts(&r.response), &reference)
+            } else {
+                0.0
+            };
+            (r.id.clone(), score)
+        })
+        .collect()
+}
+
+/// Harmonic mean of token precision and recall in `0.0..=1.0`, ignoring
+/// case and punctuation. Unlike the Jaccard similarity used for
+/// deduplication, repeated words count as often as they appear, so padding
+/// an answer with the reference's words doesn't raise its score. Two empty
+/// texts score 1.0.
+fn f1(answer: &HashMap<String, usize>, reference: &HashMap<String, usize>) -> f64 {
+    let answer_len: usize = answer.values().sum();
+    let reference_len: usize = reference.values().sum();
+    if answer_len == 0 || reference_len == 0 {
+        return if answer_len == reference_len {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    let shared: usize = answer
+        .iter()
+        .map(|(token, n)| (*n).min(reference.get(token).copied().unwrap_or(0)))
+        .sum();
+    if shared == 0 {
+        return 0.0;
+    }
+    let precision = shared as f64 / answer_len as f64;
+    let recall = shared as f64 / reference_len as f64;
+    2.0 * precision * recall / (precision + recall)
+}
+
+fn token_counts(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for token in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+    {
+        *counts.entry(token.to_lowercase()).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(answer: &str, reference: &str) -> f64 {
+        f1(&token_counts(answer), &token_counts(reference))
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn exact_answers_score_one() {
+        assert_eq!(score("The cat sat.", "the CAT sat"), 1.0);
+        assert_eq!(score("", "..."), 1.0);
+        assert_eq!(score("anything", ""), 0.0);
+        assert_eq!(score("dog", "cat"), 0.0);
+    }
+
+    #[test]
+    fn partial_answers_score_f1() {
+        // Precision 1, recall 1/2.
+        assert!(close(
+            score("the cat sat", "the cat sat on the mat"),
+            2.0 / 3.0
+        ));
+    }
+
+    #[test]
+    fn repeating_reference_words_does_not_help() {
+        // Precision 1/4, recall 1/2.
+        assert!(close(score("the the the the", "the cat"), 1.0 / 3.0));
+        assert!(score("the the the the", "the cat") < score("the", "the cat"));
+    }
+
+    #[test]
+    fn failed_answers_score_zero() {
+        let answer = |id: &str, status: &str| ChatBotResponse {
+            id: id.to_string(),
+            status: status.to_string(),
+            response: "Paris".to_string(),
+            ..Default::default()
+        };
+        let scores = score_all(&[answer("a", "success"), answer("b", "timeout")], "Paris");
+        assert_eq!(scores, [("a".to_string(), 1.0), ("b".to_string(), 0.0)]);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::AppError;
+use crate::persist::{read_json, write_json};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+const SERVICE: &str = "ai-chatbot-aggregator";
+const NONCE_LEN: usize = 12;
+
+/// Where API keys end up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretStoreKind {
+    Keychain,
+    EncryptedFile,
+}
+
+/// API keys for direct-API backends. Keys go to the OS keychain when one is
+/// available; otherwise they are encrypted into `secrets.json` with a random
+/// key kept next to it in `secrets.key`. That fallback only keeps keys out of
+/// plain sight; it is not a substitute for a real keychain.
+pub struct Secrets {
+    dir: PathBuf,
+    kind: OnceLock<SecretStoreKind>,
+    file_lock: Mutex<()>,
+}
+
+impl Secrets {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            kind: OnceLock::new(),
+            file_lock: Mutex::new(()),
+        }
+    }
+
+    /// Which store is in use, probing the keychain on first call.
+    pub fn kind(&self) -> SecretStoreKind {
+        *self.kind.get_or_init(|| {
+            let probe = keyring::Entry::new(SERVICE, "__probe__").and_then(|e| e.get_password());
+            match probe {
+                Ok(_) | Err(keyring::Error::NoEntry) => SecretStoreKind::Keychain,
+                Err(e) => {
+                    tracing::warn!("OS keychain unavailable ({}); using encrypted file", e);
+                    SecretStoreKind::EncryptedFile
+                }
+            }
+        })
+    }
+
+    pub fn store(&self, provider: &str, key: &str) -> Result<SecretStoreKind, AppError> {
+        if provider.trim().is_empty() {
+            return Err(AppError::Validation(
+                "Provider must not be empty".to_string(),
+            ));
+        }
+        self.write(provider, key).map_err(AppError::Storage)?;
+        Ok(self.kind())
+    }
+
+    fn write(&self, provider: &str, key: &str) -> Result<(), String> {
+        match self.kind() {
+            SecretStoreKind::Keychain => keyring::Entry::new(SERVICE, provider)
+                .and_then(|e| e.set_password(key))
+                .map_err(|e| format!("Failed to store API key: {}", e))?,
+            SecretStoreKind::EncryptedFile => {
+                let _guard = self.lock_file();
+                let cipher = self.cipher()?;
+                let mut entries = self.read_entries()?;
+                let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let mut sealed = nonce.to_vec();
+                sealed.extend(
+                    cipher
+                        .encrypt(&nonce, key.as_bytes())
+                        .map_err(|_| "Failed to encrypt API key".to_string())?,
+                );
+                entries.insert(provider.to_string(), BASE64.encode(sealed));
+                write_json(&self.entries_path(), &entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the key for `provider`. Only ever used by backends; keys are
+    /// never handed back to the frontend.
+    pub fn get(&self, provider: &str) -> Result<Option<String>, String> {
+        match self.kind() {
+            SecretStoreKind::Keychain => {
+                match keyring::Entry::new(SERVICE, provider).and_then(|e| e.get_password()) {
+                    Ok(key) => Ok(Some(key)),
+                    Err(keyring::Error::NoEntry) => Ok(None),
+                    Err(e) => Err(format!("Failed to read API key: {}", e)),
+                }
+            }
+            SecretStoreKind::EncryptedFile => {
+                let _guard = self.lock_file();
+                let Some(sealed) = self.read_entries()?.remove(provider) else {
+                    return Ok(None);
+                };
+                let sealed = BASE64
+                    .decode(sealed)
+                    .map_err(|e| format!("Corrupt secrets file: {}", e))?;
+                if sealed.len() < NONCE_LEN {
+                    return Err("Corrupt secrets file".to_string());
+                }
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+                let plaintext = self
+                    .cipher()?
+                    .decrypt(Nonce::from_slice(nonce), ciphertext)
+                    .map_err(|_| "Failed to decrypt API key".to_string())?;
+                String::from_utf8(plaintext)
+                    .map(Some)
+                    .map_err(|_| "Corrupt secrets file".to_string())
+            }
+        }
+    }
+
+    pub fn has(&self, provider: &str) -> bool {
+        matches!(self.get(provider), Ok(Some(_)))
+    }
+
+    fn entries_path(&self) -> PathBuf {
+        self.dir.join("secrets.json")
+    }
+
+    fn read_entries(&self) -> Result<HashMap<String, String>, String> {
+        Ok(read_json(&self.entries_path())?.unwrap_or_default())
+    }
+
+    /// Loads the file encryption key, generating it on first use.
+    fn cipher(&self) -> Result<ChaCha20Poly1305, String> {
+        let path = self.dir.join("secrets.key");
+        let key = match fs::read(&path) {
+            Ok(bytes) if bytes.len() == 32 => *Key::from_slice(&bytes),
+            Ok(_) => return Err("Corrupt secrets key file".to_string()),
+            Err(_) => {
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                fs::create_dir_all(&self.dir)
+                    .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+                fs::write(&path, key.as_slice())
+                    .map_err(|e| format!("Failed to write secrets key: {}", e))?;
+                restrict_permissions(&path);
+                key
+            }
+        };
+        Ok(ChaCha20Poly1305::new(&key))
+    }
+
+    fn lock_file(&self) -> MutexGuard<'_, ()> {
+        self.file_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o600));
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path) {}
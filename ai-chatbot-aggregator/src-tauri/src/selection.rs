@@ -0,0 +1,54 @@
+use crate::error::AppError;
+use crate::persist::{read_json, write_json};
+use crate::ChatBotConfig;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+/// The bots the user last had checked, persisted as JSON in the app config dir.
+pub struct SelectionStore {
+    path: PathBuf,
+    /// `None` until something is saved, or if the file couldn't be read.
+    saved: Mutex<Option<Vec<String>>>,
+}
+
+impl SelectionStore {
+    pub fn load(path: PathBuf) -> Self {
+        let saved = read_json(&path).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring unreadable chatbot selection: {}", e);
+            None
+        });
+
+        Self {
+            path,
+            saved: Mutex::new(saved),
+        }
+    }
+
+    pub fn save(&self, ids: Vec<String>) -> Result<(), AppError> {
+        let mut saved = self.lock();
+        write_json(&self.path, &ids).map_err(AppError::Storage)?;
+        *saved = Some(ids);
+        Ok(())
+    }
+
+    /// The saved selection restricted to bots that still exist, or every
+    /// enabled bot when nothing usable has been saved.
+    pub fn selected(&self, chatbots: &[ChatBotConfig]) -> Vec<String> {
+        match &*self.lock() {
+            Some(ids) => ids
+                .iter()
+                .filter(|id| chatbots.iter().any(|c| &c.id == *id))
+                .cloned()
+                .collect(),
+            None => chatbots
+                .iter()
+                .filter(|c| c.is_enabled)
+                .map(|c| c.id.clone())
+                .collect(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Vec<String>>> {
+        self.saved.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
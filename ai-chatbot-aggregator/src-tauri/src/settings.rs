@@ -0,0 +1,167 @@
+use crate::error::AppError;
+use crate::persist::{read_json, write_json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+/// App-wide preferences, persisted as JSON in the app config dir. Missing
+/// fields take their defaults so older files keep loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub max_prompt_chars: usize,
+    /// Prompts going to more bots than this need `confirm_large_batch`.
+    pub max_selected_bots: usize,
+    /// Which `ChatBackend` prompts go through: "node", "node-persistent" or "openai".
+    pub backend: String,
+    /// Bot that `summarize_responses` asks to merge the other answers.
+    pub summarizer: String,
+    /// Bot that `rank_responses` asks to score the other answers.
+    pub ranker: String,
+    /// Sent with every prompt whose request doesn't carry its own.
+    pub system_prompt: Option<String>,
+    pub network: NetworkSettings,
+    /// Extra environment variables for the Node backend; see `set_backend_env`.
+    pub backend_env: HashMap<String, String>,
+    /// Names of the post-processors applied to every answer, in order.
+    pub post_processors: Vec<String>,
+    /// Most stdout, and separately stderr, kept from one backend process
+    /// before it is stopped.
+    pub max_output_bytes: usize,
+    /// Language of error messages; unset follows the OS.
+    pub locale: Option<String>,
+    /// Per-bot timeout for requests that don't set `timeout_ms`; 0 means none.
+    pub default_timeout_ms: u64,
+    /// Fills in `detected_language` on answers.
+    pub detect_language: bool,
+    /// Standardizes line endings and trailing whitespace of answers.
+    pub normalize_responses: bool,
+    /// Consecutive failures after which a bot is skipped for
+    /// `circuit_cooldown_secs`; 0 never skips.
+    pub circuit_threshold: u32,
+    pub circuit_cooldown_secs: u64,
+    /// Limits applied to history at startup; see `prune_history`.
+    pub history_retention: HistoryRetention,
+}
+
+/// Unset limits keep history forever.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryRetention {
+    pub max_entries: Option<usize>,
+    pub max_age_days: Option<u64>,
+}
+
+/// Outbound proxies for backend traffic, unset meaning a direct connection,
+/// and how that traffic identifies itself.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    pub http_proxy: Option<String>,
+    pub https_proxy: Option<String>,
+    pub client_identity: ClientIdentity,
+}
+
+/// Sent as `User-Agent` and `X-Client-Id` by HTTP backends, and given to the
+/// Node backend as `AIMULTICHAT_USER_AGENT` and `AIMULTICHAT_CLIENT_ID`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientIdentity {
+    pub user_agent: String,
+    pub client_id: Option<String>,
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("ai-multichat/{}", env!("CARGO_PKG_VERSION")),
+            client_id: None,
+        }
+    }
+}
+
+impl NetworkSettings {
+    /// The proxy variables to set on spawned processes, in both the upper- and
+    /// lower-case spellings since tools disagree on which they read.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        if let Some(url) = &self.http_proxy {
+            vars.push(("HTTP_PROXY", url.clone()));
+            vars.push(("http_proxy", url.clone()));
+        }
+        if let Some(url) = &self.https_proxy {
+            vars.push(("HTTPS_PROXY", url.clone()));
+            vars.push(("https_proxy", url.clone()));
+        }
+        let identity = &self.client_identity;
+        vars.push(("AIMULTICHAT_USER_AGENT", identity.user_agent.clone()));
+        if let Some(id) = &identity.client_id {
+            vars.push(("AIMULTICHAT_CLIENT_ID", id.clone()));
+        }
+        vars
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_prompt_chars: 100_000,
+            max_selected_bots: 10,
+            backend: "node".to_string(),
+            summarizer: "chatgpt".to_string(),
+            ranker: "chatgpt".to_string(),
+            system_prompt: None,
+            network: NetworkSettings::default(),
+            backend_env: HashMap::new(),
+            post_processors: Vec::new(),
+            max_output_bytes: 64 * 1024 * 1024,
+            locale: None,
+            default_timeout_ms: 120_000,
+            detect_language: true,
+            normalize_responses: false,
+            circuit_threshold: 5,
+            circuit_cooldown_secs: 60,
+            history_retention: HistoryRetention::default(),
+        }
+    }
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    /// Loads persisted settings, falling back to defaults if the file is missing or unreadable.
+    pub fn load(path: PathBuf) -> Self {
+        let settings = read_json(&path)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable settings: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.lock().clone()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut Settings)) -> Result<(), AppError> {
+        let mut settings = self.lock();
+        let mut updated = settings.clone();
+        f(&mut updated);
+        write_json(&self.path, &updated).map_err(AppError::Storage)?;
+        *settings = updated;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Settings> {
+        self.settings.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
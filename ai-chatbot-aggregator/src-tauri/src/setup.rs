@@ -0,0 +1,352 @@
+use crate::backend::{spawn_env, BackendConfig};
+use crate::capture::Capture;
+use crate::children::ChildRegistry;
+use crate::dispatch::{add_note, dispatch_one, DispatchContext};
+use crate::error::AppError;
+use crate::settings::Settings;
+use crate::state::AppState;
+use crate::{ChatBotResponse, PromptRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::io::BufReader;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Registry key for the setup process within its (pseudo-)request.
+const CHILD_KEY: &str = "setup";
+/// Stdout lines read ahead of the progress loop.
+const LINE_BUFFER: usize = 64;
+
+/// One stdout line of `--setup-sessions`, emitted as `setup-progress`. Lines
+/// the script reports per bot carry `chatbot_id` and `status`; anything else
+/// it prints (log output) only has `message`.
+#[derive(Debug, Clone, Serialize)]
+struct SetupProgress {
+    setup_id: String,
+    chatbot_id: Option<String>,
+    status: Option<String>,
+    message: String,
+}
+
+/// `{"session": "claude", "status": "succeeded", "error": null}`
+#[derive(Deserialize)]
+struct SessionLine {
+    session: String,
+    status: String,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SetupSummary {
+    setup_id: String,
+    succeeded: usize,
+    failed: usize,
+    /// Bots that already had a saved session.
+    skipped: usize,
+    /// Set when `cancel_prompt(setup_id)` or `cancel_all` stopped the run early.
+    cancelled: bool,
+}
+
+/// Where a bot's login stands, as of its last setup since the app started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    /// Not set up since the app started; a saved session may still exist.
+    Unknown,
+    /// Logged in during setup, or a saved session was found.
+    LoggedIn,
+    /// Setup ended, e.g. by being cancelled, before the login was finished
+    /// in the browser.
+    NeedsManualAuth,
+    Failed,
+}
+
+/// The last `SessionStatus` reported for each bot. Kept in memory only.
+#[derive(Default)]
+pub struct Sessions {
+    statuses: Mutex<HashMap<String, SessionStatus>>,
+}
+
+impl Sessions {
+    pub fn get(&self, chatbot_id: &str) -> SessionStatus {
+        self.lock()
+            .get(chatbot_id)
+            .copied()
+            .unwrap_or(SessionStatus::Unknown)
+    }
+
+    fn set(&self, chatbot_id: &str, status: SessionStatus) {
+        self.lock().insert(chatbot_id.to_string(), status);
+    }
+
+    fn forget(&self, chatbot_id: &str) {
+        self.lock().remove(chatbot_id);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, SessionStatus>> {
+        self.statuses.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Sets up `chatbot_id` alone, replacing any session it has, and returns how
+/// that went. Cancellable like `run`, which leaves it needing manual auth.
+pub async fn run_one(
+    app: &AppHandle,
+    state: &AppState,
+    setup_id: String,
+    chatbot_id: &str,
+) -> Result<SessionStatus, AppError> {
+    state.sessions.forget(chatbot_id);
+    let summary = run(
+        app,
+        &state.backend_config,
+        &state.settings.get(),
+        &state.children,
+        &state.sessions,
+        setup_id,
+        &[chatbot_id.to_string()],
+    )
+    .await;
+    if let Err(e) = summary {
+        state.sessions.set(chatbot_id, SessionStatus::Failed);
+        return Err(e);
+    }
+    if state.sessions.get(chatbot_id) == SessionStatus::Unknown {
+        state
+            .sessions
+            .set(chatbot_id, SessionStatus::NeedsManualAuth);
+    }
+    Ok(state.sessions.get(chatbot_id))
+}
+
+/// Runs the backend's interactive session setup, streaming its output as
+/// `setup-progress` events. Cancellable through `children` under `setup_id`.
+/// With `only`, just those bots are set up, replacing sessions they already have.
+pub async fn run(
+    app: &AppHandle,
+    backend: &BackendConfig,
+    settings: &Settings,
+    children: &ChildRegistry,
+    sessions: &Sessions,
+    setup_id: String,
+    only: &[String],
+) -> Result<SetupSummary, AppError> {
+    let mut command = backend.node_command().map_err(AppError::BackendSpawn)?;
+    command
+        .envs(spawn_env(backend, &settings.network))
+        .arg(&backend.script_path)
+        .arg("--setup-sessions");
+    if !only.is_empty() {
+        command.arg("--only").arg(only.join(","));
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| AppError::BackendSpawn(format!("Failed to setup sessions: {}", e)))?;
+
+    // Stdout lines, or why reading them stopped; the weak sender lets stderr
+    // stop them too without keeping the channel open.
+    let (tx, lines) = mpsc::channel(LINE_BUFFER);
+    let limit = settings.max_output_bytes;
+    let overflow = tx.downgrade();
+    let mut stderr = child.stderr.take();
+    let stderr_task = tauri::async_runtime::spawn(async move {
+        let Some(stderr) = stderr.as_mut() else {
+            return Ok(Vec::new());
+        };
+        let captured = Capture::new("Session setup stderr", limit)
+            .rest(stderr)
+            .await;
+        if let Err(message) = &captured {
+            if let Some(tx) = overflow.upgrade() {
+                let _ = tx.send(Err(message.clone())).await;
+            }
+            // Keep the pipe draining so a child still writing can't block
+            // before it is stopped or exits.
+            let _ = tokio::io::copy(stderr, &mut tokio::io::sink()).await;
+        }
+        captured
+    });
+    let stdout = child.stdout.take();
+    tauri::async_runtime::spawn(async move {
+        let Some(stdout) = stdout else {
+            return;
+        };
+        let mut reader = BufReader::new(stdout);
+        let mut capture = Capture::new("Session setup stdout", limit);
+        loop {
+            let line = match capture.next_line(&mut reader).await {
+                Ok(None) => break,
+                Ok(Some(line)) => Ok(String::from_utf8_lossy(&line).into_owned()),
+                Err(message) => Err(message),
+            };
+            let failed = line.is_err();
+            if tx.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    children.begin(&setup_id, None);
+    let result = track(app, children, sessions, &setup_id, child, lines).await;
+    children.finish(&setup_id);
+    let (mut summary, status) = result?;
+
+    let Some(status) = status else {
+        summary.cancelled = true;
+        return Ok(summary);
+    };
+    let stderr = stderr_task
+        .await
+        .unwrap_or_else(|_| Ok(Vec::new()))
+        .map_err(AppError::BackendExit)?;
+    let reported = summary.succeeded + summary.failed + summary.skipped;
+    if !status.success() && reported == 0 {
+        return Err(AppError::BackendExit(format!(
+            "Session setup error: {}",
+            String::from_utf8_lossy(&stderr).trim()
+        )));
+    }
+    Ok(summary)
+}
+
+/// Sets up the session again for each bot whose answer failed with an expired
+/// session, then asks it once more. If the setup or the retry fails, the
+/// original failure is kept.
+pub async fn retry_expired(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+    results: &mut [ChatBotResponse],
+) {
+    let Some(app) = ctx.app else {
+        return;
+    };
+    let state = app.state::<AppState>();
+    for result in results.iter_mut().filter(|r| session_expired(r)) {
+        let summary = run(
+            app,
+            &state.backend_config,
+            &state.settings.get(),
+            &state.children,
+            &state.sessions,
+            Uuid::new_v4().to_string(),
+            std::slice::from_ref(&result.id),
+        )
+        .await;
+        match summary {
+            Ok(summary) if summary.succeeded > 0 => {}
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::warn!(chatbot_id = %result.id, "Session re-setup failed: {}", e);
+                continue;
+            }
+        }
+
+        match dispatch_one(ctx, request, request_id, &result.id).await {
+            Ok(mut retried) if retried.status == "success" => {
+                add_note(
+                    &mut retried,
+                    "session had expired and was set up again".to_string(),
+                );
+                *result = retried;
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!(chatbot_id = %result.id, "Retry after re-setup failed: {}", e),
+        }
+    }
+}
+
+/// What the backend puts in a bot's error when its saved login no longer works.
+const SESSION_EXPIRED_MARKER: &str = "SESSION_INVALID";
+
+fn session_expired(response: &ChatBotResponse) -> bool {
+    response.status == "error"
+        && response
+            .error
+            .as_deref()
+            .is_some_and(|e| e.contains(SESSION_EXPIRED_MARKER))
+}
+
+/// Reads progress until the script exits. `None` for the status means the
+/// run was cancelled and the child already killed.
+async fn track(
+    app: &AppHandle,
+    children: &ChildRegistry,
+    sessions: &Sessions,
+    setup_id: &str,
+    child: tokio::process::Child,
+    mut lines: mpsc::Receiver<Result<String, String>>,
+) -> Result<(SetupSummary, Option<std::process::ExitStatus>), AppError> {
+    let mut summary = SetupSummary {
+        setup_id: setup_id.to_string(),
+        ..SetupSummary::default()
+    };
+    if let Some(mut child) = children.insert(setup_id, CHILD_KEY, child) {
+        let _ = child.kill().await;
+        return Ok((summary, None));
+    }
+
+    while let Some(line) = lines.recv().await {
+        let line = match line {
+            Ok(line) => line,
+            Err(message) => {
+                children.kill(setup_id, CHILD_KEY).await;
+                return Err(AppError::BackendExit(message));
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let event = match serde_json::from_str::<SessionLine>(line) {
+            Ok(session) => {
+                match session.status.as_str() {
+                    "succeeded" => {
+                        summary.succeeded += 1;
+                        sessions.set(&session.session, SessionStatus::LoggedIn);
+                    }
+                    "failed" => {
+                        summary.failed += 1;
+                        sessions.set(&session.session, SessionStatus::Failed);
+                    }
+                    "skipped" => {
+                        summary.skipped += 1;
+                        sessions.set(&session.session, SessionStatus::LoggedIn);
+                    }
+                    _ => {}
+                }
+                SetupProgress {
+                    setup_id: setup_id.to_string(),
+                    message: session
+                        .error
+                        .unwrap_or_else(|| format!("{}: {}", session.session, session.status)),
+                    chatbot_id: Some(session.session),
+                    status: Some(session.status),
+                }
+            }
+            Err(_) => SetupProgress {
+                setup_id: setup_id.to_string(),
+                chatbot_id: None,
+                status: None,
+                message: line.to_string(),
+            },
+        };
+        let _ = app.emit("setup-progress", &event);
+    }
+
+    let Some(mut child) = children.take(setup_id, CHILD_KEY) else {
+        return Ok((summary, None));
+    };
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| AppError::BackendSpawn(format!("Failed to setup sessions: {}", e)))?;
+    Ok((summary, Some(status)))
+}
@@ -0,0 +1,39 @@
+use crate::{ChatBotConfig, ChatBotResponse};
+
+/// Best outcome first; anything not listed goes last.
+const STATUS_ORDER: &[&str] = &[
+    "success",
+    "invalid_json",
+    "schema_violation",
+    // Still waiting for a slot, so it may yet succeed.
+    "queued",
+    "cancelled",
+    "timeout",
+    "rate_limited",
+    "snoozed",
+    "circuit_open",
+    "error",
+];
+
+/// Orders `results` by `by`: "latency" (fastest first), "config" (the
+/// chatbot list's order), "status" (successes first) or "name". The sort is
+/// stable, so ties keep the order the backend returned them in.
+pub fn sort_results(results: &mut [ChatBotResponse], by: &str, chatbots: &[ChatBotConfig]) {
+    match by {
+        "latency" => results.sort_by_key(|r| r.latency_ms),
+        "config" => results.sort_by_key(|r| {
+            chatbots
+                .iter()
+                .position(|c| c.id == r.id)
+                .unwrap_or(usize::MAX)
+        }),
+        "status" => results.sort_by_key(|r| {
+            STATUS_ORDER
+                .iter()
+                .position(|s| *s == r.status)
+                .unwrap_or(STATUS_ORDER.len())
+        }),
+        "name" => results.sort_by_cached_key(|r| r.name.to_lowercase()),
+        _ => {}
+    }
+}
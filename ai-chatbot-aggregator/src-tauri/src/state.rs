@@ -0,0 +1,54 @@
+use crate::activity::Activity;
+use crate::backend::{ActiveBackend, BackendConfig};
+use crate::breaker::CircuitBreaker;
+use crate::cache::ResponseCache;
+use crate::capabilities::CapabilityCache;
+use crate::chatbots::ChatbotStore;
+use crate::children::ChildRegistry;
+use crate::conversations::ConversationStore;
+use crate::dispatch::ConcurrencyLimit;
+use crate::event_server::EventServer;
+use crate::history::History;
+use crate::logging::Logging;
+use crate::metrics::Metrics;
+use crate::models::ModelCatalog;
+use crate::post_process::PostProcessors;
+use crate::queue::PromptQueue;
+use crate::ratelimit::RateLimiter;
+use crate::response_files::ResponseFiles;
+use crate::secrets::Secrets;
+use crate::selection::SelectionStore;
+use crate::settings::SettingsStore;
+use crate::setup::Sessions;
+use crate::templates::TemplateStore;
+use std::sync::Arc;
+
+/// Everything the app keeps between commands, registered once with
+/// `manage`. Each part guards its own mutable data, so commands can share
+/// the state without a lock around the whole thing.
+pub struct AppState {
+    pub logging: Logging,
+    pub settings: SettingsStore,
+    pub backend_config: Arc<BackendConfig>,
+    pub secrets: Arc<Secrets>,
+    pub backend: ActiveBackend,
+    pub chatbots: ChatbotStore,
+    pub selection: SelectionStore,
+    pub templates: TemplateStore,
+    pub history: History,
+    pub response_files: ResponseFiles,
+    pub children: ChildRegistry,
+    pub sessions: Sessions,
+    pub limit: ConcurrencyLimit,
+    pub rate_limiter: RateLimiter,
+    pub breaker: CircuitBreaker,
+    pub conversations: ConversationStore,
+    pub cache: ResponseCache,
+    pub metrics: Metrics,
+    pub activity: Activity,
+    pub models: ModelCatalog,
+    pub capabilities: CapabilityCache,
+    pub event_server: EventServer,
+    pub queue: PromptQueue,
+    pub post_processors: PostProcessors,
+}
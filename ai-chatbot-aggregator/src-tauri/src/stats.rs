@@ -0,0 +1,67 @@
+/// Length in Unicode scalar values, the same unit as `max_response_chars`.
+pub fn char_count(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Whitespace-separated words, except that each Han, Hiragana or Katakana
+/// character counts as a word of its own: those scripts don't put spaces
+/// between words, so plain `split_whitespace` would count a paragraph of
+/// Chinese as one word. Hangul is spaced like English and needs no special case.
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .map(|token| {
+            let (ideographs, rest) = token.chars().fold((0, false), |(n, rest), c| {
+                if is_unspaced(c) {
+                    (n + 1, rest)
+                } else {
+                    (n, rest || c.is_alphanumeric())
+                }
+            });
+            // Latin runs mixed in with ideographs count once per token.
+            ideographs + usize::from(rest || ideographs == 0)
+        })
+        .sum()
+}
+
+pub fn is_unspaced(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FA1F}' // Supplementary ideographs
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(char_count(""), 0);
+        assert_eq!(char_count("héllo 👍"), 7);
+        assert_eq!(char_count("你好"), 2);
+    }
+
+    #[test]
+    fn counts_spaced_words() {
+        assert_eq!(word_count(""), 0);
+        assert_eq!(word_count("  Hello,   world!\n"), 2);
+        assert_eq!(word_count("안녕하세요 세계"), 2);
+    }
+
+    #[test]
+    fn counts_each_ideograph_or_kana_as_a_word() {
+        assert_eq!(word_count("你好世界"), 4);
+        assert_eq!(word_count("こんにちは"), 5);
+        assert_eq!(word_count("今日は、いい天気。"), 7);
+    }
+
+    #[test]
+    fn latin_mixed_into_cjk_counts_once_per_token() {
+        // 我 用 写 代 码, plus "Rust".
+        assert_eq!(word_count("我用Rust写代码"), 6);
+        assert_eq!(word_count("Rust 很快"), 3);
+    }
+}
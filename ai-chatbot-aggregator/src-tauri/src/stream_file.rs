@@ -0,0 +1,138 @@
+use crate::dispatch::{dispatch_streaming, DispatchContext};
+use crate::error::AppError;
+use crate::{ChatBotResponse, PromptRequest};
+use futures::future::{select, Either};
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Longest a streamed chunk waits in the write buffer before reaching disk.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Output {
+    file: BufWriter<File>,
+    last_flush: Instant,
+    /// What the file holds: the chunks of the current attempt.
+    streamed: String,
+    /// The first write that failed; nothing more is written after it.
+    error: Option<String>,
+}
+
+/// A file that streamed chunks are appended to as they arrive.
+struct FileSink {
+    path: String,
+    output: Mutex<Output>,
+    failed: Notify,
+}
+
+impl FileSink {
+    fn create(path: &str) -> Result<Self, AppError> {
+        let file = File::create(Path::new(path))
+            .map_err(|e| AppError::Storage(format!("Failed to create {}: {}", path, e)))?;
+        Ok(Self {
+            path: path.to_string(),
+            output: Mutex::new(Output {
+                file: BufWriter::new(file),
+                last_flush: Instant::now(),
+                streamed: String::new(),
+                error: None,
+            }),
+            failed: Notify::new(),
+        })
+    }
+
+    fn append(&self, delta: &str) {
+        let mut output = self.lock();
+        if output.error.is_some() {
+            return;
+        }
+        output.streamed.push_str(delta);
+        let mut written = output.file.write_all(delta.as_bytes());
+        if written.is_ok() && output.last_flush.elapsed() >= FLUSH_INTERVAL {
+            written = output.file.flush();
+            output.last_flush = Instant::now();
+        }
+        self.check(&mut output, written);
+    }
+
+    /// Empties the file for a retry, which streams the answer from the start.
+    fn restart(&self) {
+        let mut output = self.lock();
+        if output.error.is_some() {
+            return;
+        }
+        output.streamed.clear();
+        let emptied = truncate(&mut output.file);
+        self.check(&mut output, emptied);
+    }
+
+    /// Leaves the file holding the final answer, which normalization, the
+    /// post-processors or truncation may have changed from what streamed in.
+    /// A failed answer without text keeps whatever was streamed.
+    fn finish(&self, response: &ChatBotResponse) -> Result<(), AppError> {
+        let mut output = self.lock();
+        if let Some(e) = output.error.take() {
+            return Err(AppError::Storage(e));
+        }
+        let mut written = Ok(());
+        if !response.response.is_empty() && response.response != output.streamed {
+            written = truncate(&mut output.file)
+                .and_then(|_| output.file.write_all(response.response.as_bytes()));
+        }
+        written
+            .and_then(|_| output.file.flush())
+            .map_err(|e| AppError::Storage(format!("Failed to write {}: {}", self.path, e)))
+    }
+
+    /// Records the first failed write and stops the dispatch.
+    fn check(&self, output: &mut Output, written: std::io::Result<()>) {
+        if let Err(e) = written {
+            output.error = Some(format!("Failed to write {}: {}", self.path, e));
+            self.failed.notify_one();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Output> {
+        self.output.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn truncate(file: &mut BufWriter<File>) -> std::io::Result<()> {
+    file.flush()?;
+    file.get_mut().set_len(0)?;
+    file.rewind()
+}
+
+/// Sends `request` to `chatbot_id` alone, writing its answer to `path` as it
+/// streams in; the file is truncated first. If writing fails the bot is
+/// stopped and the error returned, leaving what was written so far.
+pub async fn stream_to_file(
+    ctx: &DispatchContext<'_>,
+    request: &PromptRequest,
+    request_id: &str,
+    chatbot_id: &str,
+    path: &str,
+) -> Result<ChatBotResponse, AppError> {
+    let sink = FileSink::create(path)?;
+    let on_delta = |delta: &str| sink.append(delta);
+    let on_retry = || sink.restart();
+    let dispatch = dispatch_streaming(ctx, request, request_id, chatbot_id, &on_delta, &on_retry);
+    let response = match select(Box::pin(dispatch), Box::pin(sink.failed.notified())).await {
+        Either::Left((response, _)) => response?,
+        Either::Right((_, dispatch)) => {
+            // Dropping the dispatch stops bots without a child; those with
+            // one are killed here.
+            ctx.children.cancel(request_id).await;
+            drop(dispatch);
+            ctx.children.finish(request_id);
+            return Err(AppError::Storage(
+                sink.lock().error.take().unwrap_or_default(),
+            ));
+        }
+    };
+    sink.finish(&response)?;
+    Ok(response)
+}
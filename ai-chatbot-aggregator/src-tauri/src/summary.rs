@@ -0,0 +1,72 @@
+use crate::dispatch::{dispatch_one, DispatchContext};
+use crate::error::AppError;
+use crate::{ChatBotResponse, PromptRequest, PromptResponse};
+use uuid::Uuid;
+
+pub const CONSENSUS_ID: &str = "consensus";
+
+/// Asks `summarizer` to merge the successful answers in `response` into one.
+/// With a single success there is nothing to merge and that answer is
+/// returned unchanged. A failed summary is an error rather than an error entry,
+/// since there is no other answer to show it next to.
+pub async fn summarize(
+    ctx: &DispatchContext<'_>,
+    response: &PromptResponse,
+    summarizer: &str,
+) -> Result<ChatBotResponse, AppError> {
+    let answers: Vec<&ChatBotResponse> = response
+        .results
+        .iter()
+        .filter(|r| r.status == "success")
+        .collect();
+
+    match answers.as_slice() {
+        [] => {
+            return Err(AppError::Validation(
+                "No successful responses to summarize".to_string(),
+            ))
+        }
+        [only] => return Ok((*only).clone()),
+        _ => {}
+    }
+
+    let request = PromptRequest {
+        prompt: summary_prompt(&response.prompt, &answers),
+        chatbots: vec![summarizer.to_string()],
+        ..Default::default()
+    };
+    let request_id = Uuid::new_v4().to_string();
+    let mut summary = dispatch_one(ctx, &request, &request_id, summarizer).await?;
+    if summary.status != "success" {
+        let message = format!(
+            "Summarizer {} failed: {}",
+            summary.name,
+            summary.error.unwrap_or_default()
+        );
+        return Err(if summary.status == "timeout" {
+            AppError::Timeout(message)
+        } else {
+            AppError::BackendExit(message)
+        });
+    }
+    summary.id = CONSENSUS_ID.to_string();
+    summary.name = "Consensus".to_string();
+    Ok(summary)
+}
+
+fn summary_prompt(prompt: &str, answers: &[&ChatBotResponse]) -> String {
+    let mut out = String::from(
+        "Several assistants answered the question below. Write a single answer that \
+         combines what they agree on, notes where they disagree, and drops anything \
+         that is clearly wrong.\n\n",
+    );
+    out.push_str(&format!("Question:\n{}\n", prompt.trim()));
+    for answer in answers {
+        out.push_str(&format!(
+            "\nAnswer from {}:\n{}\n",
+            answer.name,
+            answer.response.trim()
+        ));
+    }
+    out
+}
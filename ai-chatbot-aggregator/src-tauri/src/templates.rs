@@ -0,0 +1,243 @@
+use crate::error::AppError;
+use crate::persist::{read_json, write_json};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+/// A reusable prompt with `{name}` placeholders. `{{` and `}}` stand for
+/// literal braces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub body: String,
+}
+
+/// Saved templates, persisted as JSON in the app config dir.
+pub struct TemplateStore {
+    path: PathBuf,
+    templates: Mutex<Vec<Template>>,
+}
+
+impl TemplateStore {
+    pub fn load(path: PathBuf) -> Self {
+        let templates = read_json(&path)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable templates: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            path,
+            templates: Mutex::new(templates),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Template> {
+        self.lock().clone()
+    }
+
+    /// Adds the template, replacing any existing one with the same name.
+    pub fn save(&self, template: Template) -> Result<(), AppError> {
+        if template.name.trim().is_empty() {
+            return Err(AppError::Validation(
+                "Template name must not be empty".to_string(),
+            ));
+        }
+        // Surface malformed bodies now rather than on first use.
+        placeholders(&template.body)?;
+
+        let mut templates = self.lock();
+        let mut updated = templates.clone();
+        match updated.iter_mut().find(|t| t.name == template.name) {
+            Some(existing) => *existing = template,
+            None => updated.push(template),
+        }
+        write_json(&self.path, &updated).map_err(AppError::Storage)?;
+        *templates = updated;
+        Ok(())
+    }
+
+    /// Replaces every template at once, e.g. from a config bundle.
+    pub fn replace(&self, replacement: Vec<Template>) -> Result<(), AppError> {
+        for (index, template) in replacement.iter().enumerate() {
+            if template.name.trim().is_empty() {
+                return Err(AppError::Validation(
+                    "Template name must not be empty".to_string(),
+                ));
+            }
+            if replacement[..index].iter().any(|t| t.name == template.name) {
+                return Err(AppError::Validation(format!(
+                    "Template '{}' appears more than once",
+                    template.name
+                )));
+            }
+            placeholders(&template.body)?;
+        }
+
+        let mut templates = self.lock();
+        write_json(&self.path, &replacement).map_err(AppError::Storage)?;
+        *templates = replacement;
+        Ok(())
+    }
+
+    pub fn render(&self, name: &str, vars: &HashMap<String, String>) -> Result<String, AppError> {
+        let template = self
+            .lock()
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("Unknown template '{}'", name)))?;
+        render(&template.body, vars)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Template>> {
+        self.templates.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Substitutes every `{var}` in `body`. Any placeholder without a value is an
+/// error listing all the missing names.
+pub fn render(body: &str, vars: &HashMap<String, String>) -> Result<String, AppError> {
+    let mut out = String::with_capacity(body.len());
+    let mut missing = Vec::new();
+
+    for segment in parse(body)? {
+        match segment {
+            Segment::Text(text) => out.push_str(text),
+            Segment::Brace(c) => out.push(c),
+            Segment::Var(name) => match vars.get(name) {
+                Some(value) => out.push_str(value),
+                None if !missing.contains(&name) => missing.push(name),
+                None => {}
+            },
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(out)
+    } else {
+        Err(AppError::Validation(format!(
+            "Missing template variables: {}",
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Names of the placeholders in `body`, in order of first appearance.
+pub fn placeholders(body: &str) -> Result<Vec<&str>, AppError> {
+    let mut names = Vec::new();
+    for segment in parse(body)? {
+        if let Segment::Var(name) = segment {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Brace(char),
+    Var(&'a str),
+}
+
+fn parse(body: &str) -> Result<Vec<Segment<'_>>, AppError> {
+    let mut segments = Vec::new();
+    let mut rest = body;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        if pos > 0 {
+            segments.push(Segment::Text(&rest[..pos]));
+        }
+        let brace = rest[pos..].chars().next().expect("found a brace");
+        let after = &rest[pos + 1..];
+
+        if after.starts_with(brace) {
+            segments.push(Segment::Brace(brace));
+            rest = &after[1..];
+        } else if brace == '}' {
+            return Err(AppError::ParseFailure(
+                "Unmatched '}' in template; use '}}' for a literal brace".to_string(),
+            ));
+        } else {
+            let end = after
+                .find('}')
+                .ok_or_else(|| AppError::ParseFailure("Unclosed '{' in template".to_string()))?;
+            let name = after[..end].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(AppError::ParseFailure(format!(
+                    "Invalid template placeholder '{{{}}}'",
+                    &after[..end]
+                )));
+            }
+            segments.push(Segment::Var(name));
+            rest = &after[end + 1..];
+        }
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn parse_error(body: &str) -> String {
+        match placeholders(body) {
+            Err(AppError::ParseFailure(message)) => message,
+            other => panic!("expected a parse failure for {body:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let rendered = render(
+            "Translate { text } into {lang}: {text}",
+            &vars(&[("text", "hello"), ("lang", "French")]),
+        );
+        assert_eq!(rendered.unwrap(), "Translate hello into French: hello");
+        assert_eq!(placeholders("{a} {b} {a}").unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn missing_variables_are_all_listed_once() {
+        let rendered = render("{greeting}, {name}! {name}?", &vars(&[("greeting", "Hi")]));
+        assert!(
+            matches!(&rendered, Err(AppError::Validation(message)) if message == "Missing template variables: name"),
+            "{rendered:?}"
+        );
+        let rendered = render("{a}{b}", &HashMap::new());
+        assert!(
+            matches!(&rendered, Err(AppError::Validation(message)) if message.ends_with(": a, b")),
+            "{rendered:?}"
+        );
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let rendered = render("{{\"key\": \"{value}\"}}", &vars(&[("value", "v")]));
+        assert_eq!(rendered.unwrap(), "{\"key\": \"v\"}");
+        assert!(placeholders("{{not_a_var}}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn unbalanced_braces_are_rejected() {
+        assert_eq!(parse_error("Hello {name"), "Unclosed '{' in template");
+        assert!(parse_error("Hello name}").starts_with("Unmatched '}'"));
+        assert!(parse_error("{}").starts_with("Invalid template placeholder"));
+        assert!(parse_error("{two words}").starts_with("Invalid template placeholder"));
+    }
+}
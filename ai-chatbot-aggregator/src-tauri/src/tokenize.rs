@@ -0,0 +1,247 @@
+use crate::stats::is_unspaced;
+use serde::Serialize;
+use tiktoken_rs::CoreBPE;
+
+/// Model families with a known tokenizer shape, matched by model name prefix,
+/// for estimating counts without the real tokenizer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    /// cl100k / o200k BPE: common words are one token, digits go in threes.
+    /// Only estimated for models newer than tiktoken's list.
+    Gpt,
+    Claude,
+    Gemini,
+}
+
+impl Family {
+    fn of(model: &str) -> Option<Self> {
+        let model = model.trim().to_ascii_lowercase();
+        if ["gpt-", "o1", "o3", "o4", "chatgpt", "text-embedding"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            Some(Self::Gpt)
+        } else if model.starts_with("claude") {
+            Some(Self::Claude)
+        } else if model.starts_with("gemini") || model.starts_with("gemma") {
+            Some(Self::Gemini)
+        } else {
+            None
+        }
+    }
+
+    /// Letters one word-piece token covers on average.
+    fn chars_per_piece(self) -> usize {
+        match self {
+            Self::Gpt | Self::Gemini => 5,
+            Self::Claude => 4,
+        }
+    }
+
+    /// Digits merged into one token.
+    fn digits_per_token(self) -> usize {
+        match self {
+            Self::Gpt => 3,
+            Self::Claude | Self::Gemini => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TokenCount {
+    tokens: usize,
+    /// Set when `tokens` is an estimate, which it is for every model
+    /// tiktoken has no tokenizer for.
+    fallback: bool,
+}
+
+impl TokenCount {
+    pub fn tokens(&self) -> usize {
+        self.tokens
+    }
+
+    pub fn fallback(&self) -> bool {
+        self.fallback
+    }
+}
+
+/// Number of tokens `text` is for `model`. OpenAI's models are counted
+/// exactly with tiktoken. Other known families are estimated by splitting
+/// text the way their tokenizers pre-split it (words, digit runs,
+/// punctuation, line breaks), which is usually within 10-15% of the real
+/// count; anything else is one token per four characters.
+pub fn count(text: &str, model: &str) -> TokenCount {
+    if let Some(bpe) = bpe(model) {
+        return TokenCount {
+            tokens: bpe.encode_ordinary(text).len(),
+            fallback: false,
+        };
+    }
+    TokenCount {
+        tokens: token_ends(text, Family::of(model)).count(),
+        fallback: true,
+    }
+}
+
+/// The longest start of `text` that is at most `max_tokens` tokens for
+/// `model`, counted as [`count`] does; `None` if `text` already fits.
+pub fn truncate<'a>(text: &'a str, model: &str, max_tokens: usize) -> Option<&'a str> {
+    if let Some(bpe) = bpe(model) {
+        let tokens = bpe.encode_ordinary(text);
+        if tokens.len() <= max_tokens {
+            return None;
+        }
+        let mut cut = bpe
+            .decode_bytes(&tokens[..max_tokens])
+            .map_or(0, |kept| kept.len());
+        // A token may end inside a multi-byte character; drop the partial one.
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        return Some(&text[..cut]);
+    }
+    let mut ends = token_ends(text, Family::of(model));
+    let cut = match max_tokens {
+        0 => 0,
+        n => ends.nth(n - 1)?,
+    };
+    ends.next()?;
+    Some(&text[..cut])
+}
+
+/// The real tokenizer for `model`, if tiktoken has one.
+fn bpe(model: &str) -> Option<&'static CoreBPE> {
+    tiktoken_rs::bpe_for_model(&model.trim().to_ascii_lowercase()).ok()
+}
+
+/// Byte offset just past each token, in order. Without a family every four
+/// characters are a token.
+fn token_ends(text: &str, family: Option<Family>) -> impl Iterator<Item = usize> + '_ {
+    let mut chars = text.char_indices().peekable();
+    let end = |(i, c): (usize, char)| i + c.len_utf8();
+    std::iter::from_fn(move || loop {
+        let (i, c) = chars.next()?;
+        let Some(family) = family else {
+            let mut last = end((i, c));
+            for _ in 1..4 {
+                match chars.next() {
+                    Some(next) => last = end(next),
+                    None => break,
+                }
+            }
+            return Some(last);
+        };
+        if is_unspaced(c) {
+            return Some(end((i, c)));
+        } else if c.is_alphabetic() || c.is_numeric() {
+            // Long words and numbers split into pieces of a fixed length.
+            let alphabetic = c.is_alphabetic();
+            let (per_token, same_run): (usize, fn(&char) -> bool) = if alphabetic {
+                (family.chars_per_piece(), |c| {
+                    c.is_alphabetic() && !is_unspaced(*c)
+                })
+            } else {
+                (family.digits_per_token(), |c| c.is_numeric())
+            };
+            let mut last = end((i, c));
+            for _ in 1..per_token {
+                match chars.next_if(|(_, c)| same_run(c)) {
+                    Some(next) => last = end(next),
+                    None => break,
+                }
+            }
+            return Some(last);
+        } else if c == '\n' {
+            // A run of line breaks is one token.
+            let mut last = end((i, c));
+            while let Some(next) = chars.next_if(|(_, c)| *c == '\n') {
+                last = end(next);
+            }
+            return Some(last);
+        } else if !c.is_whitespace() {
+            // Spaces ride along with the following word; punctuation doesn't.
+            return Some(end((i, c)));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str, model: &str) -> usize {
+        count(text, model).tokens()
+    }
+
+    #[test]
+    fn recognizes_model_families() {
+        assert_eq!(Family::of("GPT-4o"), Some(Family::Gpt));
+        assert_eq!(Family::of("o3-mini"), Some(Family::Gpt));
+        assert_eq!(Family::of("claude-3-5-sonnet"), Some(Family::Claude));
+        assert_eq!(Family::of("gemma-2"), Some(Family::Gemini));
+        assert_eq!(Family::of("mistral-large"), None);
+    }
+
+    #[test]
+    fn openai_models_are_counted_exactly() {
+        for model in ["gpt-4o", " GPT-4o-mini ", "gpt-3.5-turbo", "o3-mini"] {
+            let counted = count("Hello, world!", model);
+            assert_eq!(counted.tokens(), 4, "{model}");
+            assert!(!counted.fallback(), "{model}");
+        }
+        assert_eq!(tokens("", "gpt-4o"), 0);
+        assert_eq!(tokens("internationalization", "gpt-4o"), 2);
+        assert_eq!(tokens("你好世界", "gpt-4o"), 2);
+    }
+
+    #[test]
+    fn other_models_are_estimates() {
+        for model in ["claude-3-5-sonnet", "gemini-1.5-pro", "gpt-99", "llama-3"] {
+            assert!(count("Hello, world!", model).fallback(), "{model}");
+        }
+    }
+
+    #[test]
+    fn splits_words_and_punctuation() {
+        assert_eq!(tokens("Hello, world!", "gemini-1.5"), 4);
+        assert_eq!(tokens("a\n\n\nb", "claude-3"), 3);
+    }
+
+    #[test]
+    fn long_words_and_numbers_split_by_family() {
+        assert_eq!(tokens("internationalization", "gemini-1.5"), 4);
+        assert_eq!(tokens("internationalization", "claude-3"), 5);
+        assert_eq!(token_ends("12345678", Some(Family::Gpt)).count(), 3);
+        assert_eq!(tokens("12345678", "gemini-1.5"), 8);
+    }
+
+    #[test]
+    fn each_cjk_character_is_a_token() {
+        assert_eq!(tokens("你好世界", "gemini-1.5"), 4);
+        assert_eq!(tokens("こんにちは", "claude-3"), 5);
+    }
+
+    #[test]
+    fn unknown_models_count_four_characters_per_token() {
+        assert_eq!(tokens("abcdefghij", "llama-3"), 3);
+    }
+
+    #[test]
+    fn truncate_cuts_between_tokens() {
+        assert_eq!(truncate("Hello, world!", "gpt-4o", 2), Some("Hello,"));
+        assert_eq!(truncate("Hello, world!", "gpt-4o", 0), Some(""));
+        assert_eq!(truncate("Hello, world!", "gpt-4o", 4), None);
+        assert_eq!(truncate("你好世界", "claude-3", 3), Some("你好世"));
+    }
+
+    #[test]
+    fn truncate_never_splits_a_character() {
+        let text = "🦀🦀🦀";
+        let total = tokens(text, "gpt-4o");
+        for max in 0..total {
+            let kept = truncate(text, "gpt-4o", max).expect("too long");
+            assert!(tokens(kept, "gpt-4o") <= max);
+            assert_eq!(kept.chars().count() * 4, kept.len());
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::tokenize;
+
+const MARKER: &str = "…[truncated]";
+
+/// Cuts `s` down to its first `max` characters plus a marker. Counts chars,
+/// not bytes, so a multi-byte character is never split. Returns whether
+/// anything was cut.
+pub fn truncate_response(s: &str, max: usize) -> (String, bool) {
+    match s.char_indices().nth(max) {
+        Some((end, _)) => (format!("{}{}", &s[..end], MARKER), true),
+        None => (s.to_string(), false),
+    }
+}
+
+/// Like [`truncate_response`], but keeps the first `max_tokens` tokens as
+/// counted for `model`, cutting between tokens rather than characters.
+pub fn truncate_response_tokens(s: &str, model: &str, max_tokens: usize) -> (String, bool) {
+    match tokenize::truncate(s, model, max_tokens) {
+        Some(kept) => (format!("{}{}", kept, MARKER), true),
+        None => (s.to_string(), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_left_alone() {
+        assert_eq!(truncate_response("hello", 5), ("hello".to_string(), false));
+        assert_eq!(truncate_response("", 0), (String::new(), false));
+    }
+
+    #[test]
+    fn cuts_at_the_character_limit() {
+        assert_eq!(
+            truncate_response("hello world", 5),
+            ("hello…[truncated]".to_string(), true)
+        );
+        assert_eq!(
+            truncate_response("abc", 0),
+            ("…[truncated]".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn never_splits_emoji() {
+        // Each of these is four bytes.
+        assert_eq!(
+            truncate_response("👍🎉🚀 done", 2),
+            ("👍🎉…[truncated]".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn token_limit_cuts_between_tokens() {
+        assert_eq!(
+            truncate_response_tokens("one two three four five", "gpt-4o", 3),
+            ("one two three…[truncated]".to_string(), true)
+        );
+        assert_eq!(
+            truncate_response_tokens("one two three", "gpt-4o", 3),
+            ("one two three".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn token_limit_keeps_exactly_that_many_tokens() {
+        let text = "Rust's ownership model, explained: 1234567 borrows & lifetimes 日本語.";
+        for model in ["gpt-4o", "claude", "gemini-pro", "unknown-model"] {
+            let total = tokenize::count(text, model).tokens();
+            for max in 0..total {
+                let (cut, truncated) = truncate_response_tokens(text, model, max);
+                let kept = cut.strip_suffix(MARKER).expect("marked as truncated");
+                assert!(truncated);
+                assert_eq!(
+                    tokenize::count(kept, model).tokens(),
+                    max,
+                    "{} at {}",
+                    model,
+                    max
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn counts_cjk_as_one_character_each() {
+        assert_eq!(
+            truncate_response("你好世界", 4),
+            ("你好世界".to_string(), false)
+        );
+        assert_eq!(
+            truncate_response("こんにちは世界", 5),
+            ("こんにちは…[truncated]".to_string(), true)
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use crate::backend::CommandTemplate;
+use crate::error::AppError;
+use crate::schema;
+use crate::{Attachment, ChatBotConfig, ChatBotResponse, PromptRequest};
+use serde::Serialize;
+use std::path::{Component, Path};
+
+/// Statuses a backend may report for an answer. "queued" and "rate_limited"
+/// are only ever set by the dispatcher itself.
+const BACKEND_STATUSES: &[&str] = &["success", "error", "timeout", "cancelled"];
+
+const RESPONSE_FORMATS: &[&str] = &["markdown", "plain", "json"];
+
+const MODES: &[&str] = &["all", "race"];
+
+const SORT_KEYS: &[&str] = &["latency", "config", "status", "name"];
+
+/// Largest file that may be attached to a prompt.
+const MAX_ATTACHMENT_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Rejects requests that would only waste a backend process.
+pub fn validate_request(
+    request: &PromptRequest,
+    chatbots: &[ChatBotConfig],
+    max_prompt_chars: usize,
+) -> Result<(), AppError> {
+    if request.prompt.trim().is_empty() {
+        return Err(AppError::Validation("Prompt must not be empty".to_string()));
+    }
+
+    let prompt_chars = request.prompt.chars().count();
+    if prompt_chars > max_prompt_chars {
+        return Err(AppError::Validation(format!(
+            "Prompt is {} characters long; the limit is {}",
+            prompt_chars, max_prompt_chars
+        )));
+    }
+
+    if request.chatbots.is_empty() && request.require_capabilities.is_empty() {
+        return Err(AppError::Validation(
+            "Select at least one chatbot or capability".to_string(),
+        ));
+    }
+
+    if let Some(unknown) = request
+        .chatbots
+        .iter()
+        .find(|id| !chatbots.iter().any(|c| &c.id == *id))
+    {
+        return Err(AppError::NotFound(format!("Unknown chatbot '{}'", unknown)));
+    }
+
+    if let Some(format) = &request.response_format {
+        if !RESPONSE_FORMATS.contains(&format.as_str()) {
+            return Err(AppError::Validation(format!(
+                "Unknown response format '{}'; expected markdown, plain or json",
+                format
+            )));
+        }
+    }
+
+    if let Some(schema) = &request.response_schema {
+        schema::compile(schema)
+            .map_err(|e| AppError::Validation(format!("Invalid response schema: {}", e)))?;
+    }
+
+    if let Some(mode) = &request.mode {
+        if !MODES.contains(&mode.as_str()) {
+            return Err(AppError::Validation(format!(
+                "Unknown mode '{}'; expected all or race",
+                mode
+            )));
+        }
+    }
+
+    if let Some(by) = &request.sort_by {
+        if !SORT_KEYS.contains(&by.as_str()) {
+            return Err(AppError::Validation(format!(
+                "Unknown sort order '{}'; expected latency, config, status or name",
+                by
+            )));
+        }
+    }
+
+    request.attachments.iter().try_for_each(validate_attachment)
+}
+
+/// Checks what can be checked of a chatbot config before it is saved.
+pub fn validate_chatbot(config: &ChatBotConfig) -> Result<(), AppError> {
+    match config_issues(config).into_iter().next() {
+        Some((_, message)) => Err(AppError::Validation(message)),
+        None => Ok(()),
+    }
+}
+
+/// Everything [`validate_chatbot`] rejects, by field: an empty id, a url
+/// that isn't http(s), a bad command template or header.
+fn config_issues(config: &ChatBotConfig) -> Vec<(&'static str, String)> {
+    let mut issues = Vec::new();
+    if config.id.trim().is_empty() {
+        issues.push(("id", "id must not be empty".to_string()));
+    }
+    if !is_http_url(&config.url) {
+        issues.push(("url", format!("invalid url '{}'", config.url)));
+    }
+    if let Some(Err(e)) = config
+        .command_template
+        .as_deref()
+        .map(CommandTemplate::parse)
+    {
+        issues.push(("command_template", e));
+    }
+    for (name, value) in config.headers.iter().flatten() {
+        if let Err(e) = validate_header(name, value) {
+            issues.push(("headers", e.to_string()));
+        }
+    }
+    issues
+}
+
+/// One problem with a stored chatbot config.
+#[derive(Debug, Serialize)]
+pub struct ConfigValidationIssue {
+    /// Position in the list, which tells entries with a bad id apart.
+    pub index: usize,
+    pub chatbot_id: String,
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Every problem in `configs`, each bot's in field order: what
+/// [`validate_import`] checks plus empty names.
+pub fn chatbot_issues(configs: &[ChatBotConfig]) -> Vec<ConfigValidationIssue> {
+    let mut issues = Vec::new();
+    for (index, config) in configs.iter().enumerate() {
+        let mut issue = |field: &'static str, message: String| {
+            issues.push(ConfigValidationIssue {
+                index,
+                chatbot_id: config.id.clone(),
+                field,
+                message,
+            })
+        };
+        let (id, rest): (Vec<_>, Vec<_>) = config_issues(config)
+            .into_iter()
+            .partition(|(field, _)| *field == "id");
+        for (field, message) in id {
+            issue(field, message);
+        }
+        if !config.id.trim().is_empty() && configs[..index].iter().any(|c| c.id == config.id) {
+            issue("id", "id appears more than once".to_string());
+        }
+        if config.name.trim().is_empty() {
+            issue("name", "name must not be empty".to_string());
+        }
+        for (field, message) in rest {
+            issue(field, message);
+        }
+    }
+    issues
+}
+
+/// Checks a whole list of imported bots, naming the first bad entry: ids
+/// must be unique, on top of what [`validate_chatbot`] checks.
+pub fn validate_import(configs: &[ChatBotConfig]) -> Result<(), AppError> {
+    for (index, config) in configs.iter().enumerate() {
+        let entry = |message: String| {
+            AppError::Validation(format!(
+                "Chatbot #{} ('{}'): {}",
+                index + 1,
+                config.id,
+                message
+            ))
+        };
+        validate_chatbot(config).map_err(|e| entry(e.to_string()))?;
+        if configs[..index].iter().any(|c| c.id == config.id) {
+            return Err(entry("id appears more than once".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Checks the invariants the UI relies on for an answer parsed from a backend,
+/// so a drifting backend contract surfaces as an error instead of a blank card.
+pub fn validate_response(response: &ChatBotResponse) -> Result<(), String> {
+    if response.id.trim().is_empty() {
+        return Err("Backend returned a response without a chatbot id".to_string());
+    }
+    if !BACKEND_STATUSES.contains(&response.status.as_str()) {
+        return Err(format!(
+            "Backend returned an invalid response for '{}': unknown status '{}'",
+            response.id, response.status
+        ));
+    }
+    Ok(())
+}
+
+/// Names must be non-empty and free of `=`; neither part may contain NUL,
+/// which no OS can pass to a child process.
+pub fn validate_env_var(name: &str, value: &str) -> Result<(), AppError> {
+    if name.is_empty() || name.contains('=') || name.contains('\0') {
+        return Err(AppError::Validation(format!(
+            "Invalid environment variable name '{}'",
+            name
+        )));
+    }
+    if value.contains('\0') {
+        return Err(AppError::Validation(format!(
+            "Environment variable '{}' must not contain NUL",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Proxies must be absolute http or https URLs, e.g. `http://proxy.corp:8080`.
+pub fn validate_proxy_url(url: &str) -> Result<(), AppError> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| AppError::Validation(format!("Invalid proxy URL '{}': {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(AppError::Validation(format!(
+            "Proxy URL '{}' must be http:// or https:// with a host",
+            url
+        )));
+    }
+    Ok(())
+}
+
+/// User agents and client ids are sent as header values, so they must be
+/// printable and not blank.
+pub fn validate_client_identity(what: &str, value: &str) -> Result<(), AppError> {
+    if value.trim().is_empty() || reqwest::header::HeaderValue::from_str(value).is_err() {
+        return Err(AppError::Validation(format!(
+            "Invalid {} '{}': must be printable and not blank",
+            what, value
+        )));
+    }
+    Ok(())
+}
+
+/// Header names must be valid HTTP tokens and values printable, so a bad
+/// header is refused when saved rather than failing every request. Headers
+/// the HTTP client manages itself can't be overridden.
+fn validate_header(name: &str, value: &str) -> Result<(), AppError> {
+    if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+        return Err(AppError::Validation(format!(
+            "Invalid header name '{}'",
+            name
+        )));
+    }
+    if [
+        "content-length",
+        "content-type",
+        "host",
+        "transfer-encoding",
+    ]
+    .iter()
+    .any(|managed| name.eq_ignore_ascii_case(managed))
+    {
+        return Err(AppError::Validation(format!(
+            "Header '{}' is set automatically and can't be configured",
+            name
+        )));
+    }
+    if value.trim().is_empty() || reqwest::header::HeaderValue::from_str(value).is_err() {
+        // The value may be a credential, so it stays out of the message.
+        return Err(AppError::Validation(format!(
+            "Header '{}' has an empty or invalid value",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Attachments must be absolute paths to existing regular files within the
+/// size cap; `..` components are refused outright rather than resolved.
+fn validate_attachment(attachment: &Attachment) -> Result<(), AppError> {
+    let path = Path::new(&attachment.path);
+    if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+        return Err(AppError::Validation(format!(
+            "Attachment '{}' must be an absolute path without '..'",
+            attachment.filename
+        )));
+    }
+
+    let metadata = std::fs::metadata(path).map_err(|_| {
+        AppError::Validation(format!(
+            "Attachment '{}' not found at {}",
+            attachment.filename, attachment.path
+        ))
+    })?;
+    if !metadata.is_file() {
+        return Err(AppError::Validation(format!(
+            "Attachment '{}' is not a file",
+            attachment.filename
+        )));
+    }
+    if metadata.len() > MAX_ATTACHMENT_BYTES {
+        return Err(AppError::Validation(format!(
+            "Attachment '{}' is {} bytes; the limit is {}",
+            attachment.filename,
+            metadata.len(),
+            MAX_ATTACHMENT_BYTES
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bot(id: &str) -> ChatBotConfig {
+        ChatBotConfig {
+            id: id.to_string(),
+            name: id.to_string(),
+            url: "https://example.com".to_string(),
+            is_enabled: true,
+            model: None,
+            params: None,
+            order: 0,
+            capabilities: Vec::new(),
+            command_template: None,
+            headers: None,
+            disabled_until: None,
+            prompt_prefix: None,
+            prompt_suffix: None,
+        }
+    }
+
+    fn request(prompt: &str, chatbots: &[&str]) -> PromptRequest {
+        PromptRequest {
+            prompt: prompt.to_string(),
+            chatbots: chatbots.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn check(request: &PromptRequest) -> Result<(), AppError> {
+        validate_request(request, &[bot("chatgpt"), bot("claude")], 100)
+    }
+
+    fn rejected(request: &PromptRequest) -> bool {
+        matches!(check(request), Err(AppError::Validation(_)))
+    }
+
+    fn attachment(path: &str) -> Attachment {
+        Attachment {
+            filename: "notes.txt".to_string(),
+            mime: "text/plain".to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_a_plain_request() {
+        assert!(check(&request("Hello", &["chatgpt", "claude"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_blank_prompts() {
+        assert!(rejected(&request("", &["chatgpt"])));
+        assert!(rejected(&request(" \n\t", &["chatgpt"])));
+    }
+
+    #[test]
+    fn limits_prompt_length_in_characters() {
+        assert!(check(&request(&"é".repeat(100), &["chatgpt"])).is_ok());
+        assert!(rejected(&request(&"é".repeat(101), &["chatgpt"])));
+    }
+
+    #[test]
+    fn needs_a_chatbot_or_capability() {
+        assert!(rejected(&request("Hello", &[])));
+        let by_capability = PromptRequest {
+            require_capabilities: vec!["vision".to_string()],
+            ..request("Hello", &[])
+        };
+        assert!(check(&by_capability).is_ok());
+    }
+
+    #[test]
+    fn unknown_chatbots_are_not_found() {
+        assert!(matches!(
+            check(&request("Hello", &["chatgpt", "bard"])),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        let format = PromptRequest {
+            response_format: Some("html".to_string()),
+            ..request("Hello", &["chatgpt"])
+        };
+        let mode = PromptRequest {
+            mode: Some("fastest".to_string()),
+            ..request("Hello", &["chatgpt"])
+        };
+        let sort = PromptRequest {
+            sort_by: Some("cost".to_string()),
+            ..request("Hello", &["chatgpt"])
+        };
+        let schema = PromptRequest {
+            response_schema: Some(serde_json::json!("object")),
+            ..request("Hello", &["chatgpt"])
+        };
+        assert!(rejected(&format));
+        assert!(rejected(&mode));
+        assert!(rejected(&sort));
+        assert!(rejected(&schema));
+    }
+
+    #[test]
+    fn checks_attachment_paths() {
+        let dir = std::env::temp_dir().join(format!("validation-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "notes").unwrap();
+        let with = |path: &str| PromptRequest {
+            attachments: vec![attachment(path)],
+            ..request("Hello", &["chatgpt"])
+        };
+
+        assert!(check(&with(file.to_str().unwrap())).is_ok());
+        assert!(rejected(&with("notes.txt")));
+        let parent = format!("{}/../notes.txt", dir.display());
+        assert!(rejected(&with(&parent)));
+        assert!(rejected(&with(dir.join("missing.txt").to_str().unwrap())));
+        assert!(rejected(&with(dir.to_str().unwrap())));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -188,6 +188,11 @@ export abstract class BaseChatBot {
     }
   }
 
+  // Models the user can pick for this bot; empty when the web UI doesn't say
+  async listModels(): Promise<string[]> {
+    return [];
+  }
+
   async saveSession(): Promise<void> {
     if (!this.context) {
       throw new Error('Context not available');
@@ -11,6 +11,13 @@ import { withRetry, isRetryableError } from '../utils/retry.js';
 import * as fs from 'fs';
 import * as path from 'path';
 
+/** Reported once per chatbot when it starts and when it finishes session setup. */
+export interface SetupProgress {
+  session: string;
+  status: 'started' | 'succeeded' | 'failed' | 'skipped';
+  error?: string;
+}
+
 export class AIManager {
   private browser?: Browser;
   private chatbots: Map<string, BaseChatBot>;
@@ -57,18 +64,24 @@ export class AIManager {
     }
   }
 
-  async setupSessions(): Promise<void> {
+  /**
+   * Logs in to every chatbot without a saved session. With `only`, just those
+   * chatbots are set up, replacing any session they already have.
+   */
+  async setupSessions(onProgress?: (progress: SetupProgress) => void, only?: string[]): Promise<void> {
     if (!this.browser) {
       throw new Error('Browser not initialized');
     }
 
-    logger.info('Starting session setup for all chatbots');
+    logger.info(only ? `Starting session setup for ${only.join(', ')}` : 'Starting session setup for all chatbots');
 
-    const setupPromises = Array.from(this.chatbots.entries()).map(async ([id, chatbot]) => {
+    const entries = Array.from(this.chatbots.entries()).filter(([id]) => !only || only.includes(id));
+    const setupPromises = entries.map(async ([id, chatbot]) => {
       const sessionPath = path.join(process.cwd(), CONFIG.APP.SESSIONS_DIR, `${id}-session.json`);
       
-      if (!fs.existsSync(sessionPath)) {
+      if (only || !fs.existsSync(sessionPath)) {
         logger.info(`Setting up session for ${chatbot.name}`, id);
+        onProgress?.({ session: id, status: 'started' });
         
         try {
           // Create a temporary context for login
@@ -93,12 +106,15 @@ export class AIManager {
           await context.close();
           
           logger.info(`Session saved for ${chatbot.name}`, id);
+          onProgress?.({ session: id, status: 'succeeded' });
         } catch (error) {
           logger.error(`Failed to setup session for ${chatbot.name}`, id, error as Error);
+          onProgress?.({ session: id, status: 'failed', error: (error as Error).message });
           throw error;
         }
       } else {
         logger.info(`Session already exists for ${chatbot.name}`, id);
+        onProgress?.({ session: id, status: 'skipped' });
       }
     });
 
@@ -280,6 +296,15 @@ export class AIManager {
     }));
   }
 
+  // Doesn't need initialize(): bots answer this without a browser
+  async listModels(id: string): Promise<string[]> {
+    const chatbot = this.chatbots.get(id);
+    if (!chatbot) {
+      throw new Error(`Unknown chatbot: ${id}`);
+    }
+    return chatbot.listModels();
+  }
+
   // Health check method
   async healthCheck(): Promise<boolean> {
     return this.isInitialized && !!this.browser;
@@ -1,6 +1,14 @@
 import { invoke } from '@tauri-apps/api/core';
 import { ChatBotConfig, PromptRequest, PromptResponse } from '../types';
 
+export interface SetupSummary {
+  setup_id: string;
+  succeeded: number;
+  failed: number;
+  skipped: number;
+  cancelled: boolean;
+}
+
 export class TauriService {
   static async getChatbotsList(): Promise<ChatBotConfig[]> {
     try {
@@ -20,9 +28,9 @@ export class TauriService {
     }
   }
 
-  static async setupChatbotSessions(): Promise<string> {
+  static async setupChatbotSessions(): Promise<SetupSummary> {
     try {
-      return await invoke<string>('setup_chatbot_sessions');
+      return await invoke<SetupSummary>('setup_chatbot_sessions');
     } catch (error) {
       console.error('Failed to setup chatbot sessions:', error);
       throw error;